@@ -25,6 +25,10 @@ pub const PAGE_SIZE_BITS:       usize = 0xc;
 pub const TRAMPOLINE:           usize = usize::MAX - PAGE_SIZE + 1;
 /// Trap 上下文在应用地址空间中的位置
 pub const TRAP_CONTEXT:         usize = TRAMPOLINE - PAGE_SIZE;
+/// 用户程序可以访问的虚拟地址上界（SV39 地址空间的低半部分）
+pub const USER_SPACE_END:       usize = 1 << 38;
+/// 信号处理函数返回时使用的跳板，映射在用户地址空间的最高页
+pub const SIGRETURN_TRAMPOLINE: usize = USER_SPACE_END - PAGE_SIZE;
 
 pub use crate::board::{CLOCK_FREQ, MMIO};
 
@@ -1,4 +1,5 @@
 use alloc::vec::Vec;
+use bitflags::*;
 
 pub struct FdSet {
     fd_list: [u64; 16],
@@ -69,3 +70,29 @@ impl FdSet {
         unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, size) }
     }
 }
+
+bitflags! {
+    /// `ppoll` 中关心或发生的事件
+    pub struct PollEvents: i16 {
+        const POLLIN   = 0x001;
+        const POLLPRI  = 0x002;
+        const POLLOUT  = 0x004;
+        const POLLERR  = 0x008;
+        const POLLHUP  = 0x010;
+        const POLLNVAL = 0x020;
+    }
+}
+
+/// ### `ppoll` 等待的一个文件描述符
+/// |成员变量|描述|
+/// |--|--|
+/// |`fd`|文件描述符，为负数时忽略该项|
+/// |`events`|关心的事件|
+/// |`revents`|实际发生的事件，由内核填写|
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
@@ -145,6 +145,24 @@ impl OSInode {
         let vfile = &inner.inode;
         vfile.first_cluster()
     }
+
+    /// 从文件的 `offset` 处读取数据，不改变文件偏移量
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let inner = self.inner.lock();
+        inner.inode.read_at(offset, buf)
+    }
+
+    /// ### 向文件的 `offset` 处写入数据，不改变文件偏移量，也不会扩大文件
+    /// - 返回值：实际写入的字节数
+    pub fn write_at(&self, offset: usize, data: &[u8]) -> usize {
+        let inner = self.inner.lock();
+        let file_size = inner.inode.file_size() as usize;
+        if offset >= file_size {
+            return 0;
+        }
+        let len = data.len().min(file_size - offset);
+        inner.inode.write_at(offset, &data[..len])
+    }
 }
 
 // 这里在实例化的时候进行文件系统的打开
@@ -304,6 +322,11 @@ pub fn open(work_path: &str, path: &str, flags: OpenFlags) -> Option<Arc<OSInode
     }
 }
 
+/// 将文件系统块缓存中被修改过的内容写回磁盘
+pub fn sync_all() {
+    simple_fat32::sync_all();
+}
+
 // display debug todo
 pub fn chdir(work_path: &str, path: &str) -> Option<String> {
     let current_inode = {
@@ -507,4 +530,8 @@ impl File for OSInode {
     fn get_path(&self) -> &str {
         self.path.as_str()
     }
+
+    fn as_osinode(&self) -> Option<&OSInode> {
+        Some(self)
+    }
 }
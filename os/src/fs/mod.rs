@@ -3,12 +3,22 @@ mod fdset;
 mod inode;
 mod mount;
 mod pipe;
+mod proc;
+mod signalfd;
 mod stat;
 mod stdio;
+mod tty;
 
+use crate::task::WaitQueue;
 use crate::{mm::UserBuffer, timer::Timespec};
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
+use lazy_static::*;
+
+lazy_static! {
+    /// 文件的读写就绪状态可能发生变化（管道读写、控制台输入、信号到达）时唤醒，`pselect`/`ppoll` 在其上等待
+    pub static ref POLL_QUEUE: WaitQueue = WaitQueue::new();
+}
 
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
@@ -71,6 +81,22 @@ pub trait File: Send + Sync {
     fn w_ready(&self) -> bool {
         true
     }
+    /// 是否为所有读端都已关闭的管道写端，此时写入返回 `EPIPE`
+    fn broken_pipe(&self) -> bool {
+        false
+    }
+    /// 是否为终端设备，终端相关的 ioctl 只对终端有效
+    fn is_tty(&self) -> bool {
+        false
+    }
+    /// 对 signalfd 返回其自身，用于 `signalfd4` 修改已有 signalfd 接收的信号
+    fn as_signalfd(&self) -> Option<&SignalFd> {
+        None
+    }
+    /// 对常规文件返回其自身，用于共享文件映射按页读写文件内容
+    fn as_osinode(&self) -> Option<&OSInode> {
+        None
+    }
 }
 
 // impl Debug for dyn File {
@@ -87,8 +113,11 @@ impl Debug for dyn File + Send + Sync {
 
 pub use dirent::Dirent;
 pub use fdset::*;
-pub use inode::{chdir, init, open, OSInode, OpenFlags};
+pub use inode::{chdir, init, open, sync_all, OSInode, OpenFlags};
 pub use mount::MNT_TABLE;
 pub use pipe::{make_pipe, Pipe};
+pub use proc::open_proc;
+pub use signalfd::SignalFd;
 pub use stat::*;
 pub use stdio::{Stdin, Stdout};
+pub use tty::{poll_console, Tty, CONSOLE_TTY};
@@ -1,4 +1,4 @@
-use super::File;
+use super::{File, POLL_QUEUE};
 use crate::mm::UserBuffer;
 use alloc::{
     sync::{Arc, Weak},
@@ -6,7 +6,7 @@ use alloc::{
 };
 use spin::Mutex;
 
-use crate::task::suspend_current_and_run_next;
+use crate::task::{current_add_signal, SignalFlags, WaitQueue};
 
 pub struct Pipe {
     readable: bool,
@@ -31,6 +31,18 @@ impl Pipe {
             buffer,
         }
     }
+    /// 管道中有数据写入或写端关闭时，唤醒等待读的任务
+    fn notify_readers(&self) {
+        let read_wait = self.buffer.lock().read_wait.clone();
+        read_wait.wake_all();
+        POLL_QUEUE.wake_all();
+    }
+    /// 管道中有数据被读走或读端关闭时，唤醒等待写的任务
+    fn notify_writers(&self) {
+        let write_wait = self.buffer.lock().write_wait.clone();
+        write_wait.wake_all();
+        POLL_QUEUE.wake_all();
+    }
 }
 
 /// 管道缓冲区状态
@@ -51,12 +63,18 @@ const RING_BUFFER_SIZE: usize = 4096; // 4KB
 /// |`tail`|队列尾，写|
 /// |`status`|队列状态|
 /// |`write_end`|保存了它的写端的一个弱引用计数，<br>在需要确认该管道所有的写端是否都已经被关闭时，<br>通过这个字段很容易确认这一点|
+/// |`read_end`|保存了它的读端的一个弱引用计数，用于确认管道所有的读端是否都已经被关闭|
+/// |`read_wait`|管道为空时等待读的任务|
+/// |`write_wait`|管道已满时等待写的任务|
 pub struct PipeRingBuffer {
     arr: [u8; RING_BUFFER_SIZE],
     head: usize,
     tail: usize,
     status: RingBufferStatus,
     write_end: Option<Weak<Pipe>>,
+    read_end: Option<Weak<Pipe>>,
+    read_wait: Arc<WaitQueue>,
+    write_wait: Arc<WaitQueue>,
 }
 
 impl PipeRingBuffer {
@@ -67,11 +85,17 @@ impl PipeRingBuffer {
             tail: 0,
             status: RingBufferStatus::Empty,
             write_end: None,
+            read_end: None,
+            read_wait: Arc::new(WaitQueue::new()),
+            write_wait: Arc::new(WaitQueue::new()),
         }
     }
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
         self.write_end = Some(Arc::downgrade(write_end));
     }
+    pub fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
     /// 写一个字节到管道尾
     pub fn write_byte(&mut self, byte: u8) {
         self.status = RingBufferStatus::Normal;
@@ -113,6 +137,20 @@ impl PipeRingBuffer {
     pub fn all_write_ends_closed(&self) -> bool {
         self.write_end.as_ref().unwrap().upgrade().is_none()
     }
+    /// 通过管道缓冲区读端弱指针判断管道的所有读端都被关闭
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_end.as_ref().unwrap().upgrade().is_none()
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if self.writable {
+            self.notify_readers();
+        } else {
+            self.notify_writers();
+        }
+    }
 }
 
 /// 创建一个管道并返回管道的读端和写端 (read_end, write_end)
@@ -121,6 +159,7 @@ pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
     buffer.lock().set_write_end(&write_end);
+    buffer.lock().set_read_end(&read_end);
     (read_end, write_end)
 }
 
@@ -145,8 +184,9 @@ impl File for Pipe {
                 if ring_buffer.all_write_ends_closed() {
                     return read_size;
                 }
+                let read_wait = ring_buffer.read_wait.clone();
                 drop(ring_buffer);
-                if suspend_current_and_run_next() < 0 {
+                if read_wait.wait() < 0 {
                     return read_size;
                 }
                 continue;
@@ -159,22 +199,34 @@ impl File for Pipe {
                     }
                     read_size += 1;
                 } else {
-                    return read_size;
+                    break;
                 }
             }
+            drop(ring_buffer);
+            self.notify_writers();
             return read_size;
         }
     }
+    /// 所有读端都已关闭时向当前线程发送 SIGPIPE，返回已写入的字节数
     fn write(&self, buf: UserBuffer) -> usize {
         assert_eq!(self.writable(), true);
-        let mut buf_iter = buf.into_iter();
+        let mut buf_iter = buf.into_iter().peekable();
         let mut write_size = 0usize;
+        if buf_iter.peek().is_none() {
+            return 0;
+        }
         loop {
             let mut ring_buffer = self.buffer.lock();
+            if ring_buffer.all_read_ends_closed() {
+                drop(ring_buffer);
+                current_add_signal(SignalFlags::SIGPIPE);
+                return write_size;
+            }
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                let write_wait = ring_buffer.write_wait.clone();
                 drop(ring_buffer);
-                if suspend_current_and_run_next() < 0 {
+                if write_wait.wait() < 0 {
                     return write_size;
                 }
                 continue;
@@ -185,9 +237,14 @@ impl File for Pipe {
                     ring_buffer.write_byte(unsafe { *byte_ref });
                     write_size += 1;
                 } else {
-                    return write_size;
+                    break;
                 }
             }
+            drop(ring_buffer);
+            self.notify_readers();
+            if buf_iter.peek().is_none() {
+                return write_size;
+            }
         }
     }
 
@@ -213,8 +270,9 @@ impl File for Pipe {
                 if ring_buffer.all_write_ends_closed() {
                     return buf;
                 }
+                let read_wait = ring_buffer.read_wait.clone();
                 drop(ring_buffer);
-                if suspend_current_and_run_next() < 0 {
+                if read_wait.wait() < 0 {
                     return buf;
                 }
                 continue;
@@ -222,19 +280,30 @@ impl File for Pipe {
             for _ in 0..loop_read {
                 buf.push(ring_buffer.read_byte());
             }
+            drop(ring_buffer);
+            self.notify_writers();
             return buf;
         }
     }
     fn write_kernel_space(&self, data: Vec<u8>) -> usize {
         assert_eq!(self.writable(), true);
-        let mut data_iter = data.into_iter();
+        let mut data_iter = data.into_iter().peekable();
         let mut write_size = 0usize;
+        if data_iter.peek().is_none() {
+            return 0;
+        }
         loop {
             let mut ring_buffer = self.buffer.lock();
+            if ring_buffer.all_read_ends_closed() {
+                drop(ring_buffer);
+                current_add_signal(SignalFlags::SIGPIPE);
+                return write_size;
+            }
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                let write_wait = ring_buffer.write_wait.clone();
                 drop(ring_buffer);
-                if suspend_current_and_run_next() < 0 {
+                if write_wait.wait() < 0 {
                     return write_size;
                 }
                 continue;
@@ -244,9 +313,14 @@ impl File for Pipe {
                     ring_buffer.write_byte(data_ref);
                     write_size += 1;
                 } else {
-                    return write_size;
+                    break;
                 }
             }
+            drop(ring_buffer);
+            self.notify_readers();
+            if data_iter.peek().is_none() {
+                return write_size;
+            }
         }
     }
 
@@ -260,9 +334,14 @@ impl File for Pipe {
         loop_read > 0
     }
 
+    /// 所有读端都已关闭时写入不会阻塞，视为可写
     fn w_ready(&self) -> bool {
         let ring_buffer = self.buffer.lock();
         let loop_write = ring_buffer.available_write();
-        loop_write > 0
+        loop_write > 0 || ring_buffer.all_read_ends_closed()
+    }
+
+    fn broken_pipe(&self) -> bool {
+        self.writable && self.buffer.lock().all_read_ends_closed()
     }
 }
@@ -0,0 +1,150 @@
+/// # /proc 下的内核虚拟文件
+/// `os/src/fs/proc.rs`
+///
+/// 目前只提供 `/proc/<pid>/trace`（`<pid>` 也可以写作 `self`）：
+/// - 读出 `1\n` / `0\n`，表示该进程是否开启了系统调用跟踪
+/// - 写入以 `1` / `0` 开头的内容，开启 / 关闭该进程的系统调用跟踪
+///
+/// 这些文件不落盘，在 `sys_openat` 中先于 FAT32 文件系统被拦截
+//
+use super::{File, Kstat, OpenFlags, S_IFREG};
+use crate::mm::UserBuffer;
+use crate::task::{current_task, pid2task, TaskControlBlock};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::Mutex;
+
+/// ### `/proc/<pid>/trace` 文件
+/// |成员变量|描述|
+/// |--|--|
+/// |`task`|被跟踪的进程，进程退出后文件随之失效|
+/// |`offset`|读写偏移量|
+pub struct ProcTraceFile {
+    task: Weak<TaskControlBlock>,
+    offset: Mutex<usize>,
+}
+
+impl ProcTraceFile {
+    fn content(&self) -> &'static [u8] {
+        match self.task.upgrade() {
+            Some(task) if task.inner_exclusive_access().trace => b"1\n",
+            _ => b"0\n",
+        }
+    }
+}
+
+impl File for ProcTraceFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn available(&self) -> bool {
+        self.task.strong_count() > 0
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let content = self.content();
+        let mut offset = self.offset.lock();
+        if *offset >= content.len() {
+            return 0;
+        }
+        let len = buf.write(&content[*offset..]);
+        *offset += len;
+        len
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let len = buf.len();
+        let mut data = [0u8; 1];
+        if buf.read(&mut data) == 0 {
+            return 0;
+        }
+        if let Some(task) = self.task.upgrade() {
+            match data[0] {
+                b'1' => task.inner_exclusive_access().trace = true,
+                b'0' => task.inner_exclusive_access().trace = false,
+                _ => {}
+            }
+        }
+        len
+    }
+
+    fn get_name(&self) -> &str {
+        "trace"
+    }
+
+    fn get_fstat(&self, kstat: &mut Kstat) {
+        kstat.init(0, 512, 0, S_IFREG | 0o644, 0);
+    }
+
+    fn get_offset(&self) -> usize {
+        *self.offset.lock()
+    }
+
+    fn set_offset(&self, offset: usize) {
+        *self.offset.lock() = offset;
+    }
+
+    fn set_flags(&self, _flag: OpenFlags) {}
+
+    fn set_cloexec(&self) {}
+
+    fn read_kernel_space(&self) -> Vec<u8> {
+        self.content().to_vec()
+    }
+
+    fn write_kernel_space(&self, data: Vec<u8>) -> usize {
+        if let Some(task) = self.task.upgrade() {
+            match data.first() {
+                Some(b'1') => task.inner_exclusive_access().trace = true,
+                Some(b'0') => task.inner_exclusive_access().trace = false,
+                _ => {}
+            }
+        }
+        data.len()
+    }
+
+    fn file_size(&self) -> usize {
+        self.content().len()
+    }
+}
+
+/// ### 尝试打开 /proc 下的虚拟文件
+/// - 参数
+///     - `work_path`：当前工作目录，用于拼接相对路径
+///     - `path`：用户传入的路径
+/// - 返回值
+///     - `None`：不是 /proc 虚拟文件，交给文件系统处理
+///     - `Some(None)`：是 /proc 虚拟文件，但对应进程不存在
+///     - `Some(Some(file))`：打开成功
+///
+/// 调用时不能持有当前进程的 `inner` 锁
+pub fn open_proc(work_path: &str, path: &str) -> Option<Option<Arc<dyn File + Send + Sync>>> {
+    let mut abs_path = String::new();
+    if !path.starts_with('/') {
+        abs_path.push_str(work_path);
+        abs_path.push('/');
+    }
+    abs_path.push_str(path);
+    let pathv: Vec<&str> = abs_path.split('/').filter(|s| !s.is_empty() && *s != ".").collect();
+    if pathv.len() != 3 || pathv[0] != "proc" || pathv[2] != "trace" {
+        return None;
+    }
+    let task = if pathv[1] == "self" {
+        current_task()
+    } else {
+        match pathv[1].parse::<usize>() {
+            Ok(pid) => pid2task(pid),
+            Err(_) => return None,
+        }
+    };
+    Some(task.map(|task| {
+        Arc::new(ProcTraceFile {
+            task: Arc::downgrade(&task),
+            offset: Mutex::new(0),
+        }) as Arc<dyn File + Send + Sync>
+    }))
+}
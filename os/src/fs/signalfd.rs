@@ -0,0 +1,148 @@
+/// # signalfd
+/// `os/src/fs/signalfd.rs`
+/// ```
+/// pub struct SignalFd
+/// pub struct SignalfdSiginfo
+/// ```
+///
+/// 通过读文件同步地接收信号：读取时从 **读者自己** 的待处理信号中取出 `mask` 中的信号，
+/// 每个信号对应一个 `SignalfdSiginfo`。`mask` 中的信号通常应先用 `sigprocmask` 屏蔽，以免被按处理方式处理掉
+//
+use super::{File, Kstat, OpenFlags, POLL_QUEUE};
+use crate::mm::UserBuffer;
+use crate::syscall::errno::{EAGAIN, EINTR, EINVAL};
+use crate::task::{current_task, dequeue_signal, SignalFlags};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// ### 从 signalfd 读出的信号信息（Linux `struct signalfd_siginfo`，共 128 字节）
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalfdSiginfo {
+    pub ssi_signo: u32,
+    pub ssi_errno: i32,
+    pub ssi_code: i32,
+    _pad: [u32; 29],
+}
+
+impl SignalfdSiginfo {
+    fn new(signo: usize) -> Self {
+        Self {
+            ssi_signo: signo as u32,
+            ssi_errno: 0,
+            ssi_code: 0,
+            _pad: [0; 29],
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, core::mem::size_of::<Self>()) }
+    }
+}
+
+/// ### signalfd 文件
+/// |成员变量|描述|
+/// |--|--|
+/// |`mask`|通过该文件接收的信号|
+/// |`nonblock`|没有信号时读取是否立即返回|
+pub struct SignalFd {
+    mask: Mutex<SignalFlags>,
+    nonblock: bool,
+}
+
+impl SignalFd {
+    pub fn new(mask: SignalFlags, nonblock: bool) -> Self {
+        Self {
+            mask: Mutex::new(mask - SignalFlags::unblockable()),
+            nonblock,
+        }
+    }
+
+    /// 修改通过该文件接收的信号，`SIGKILL` 与 `SIGSTOP` 不能通过 signalfd 接收
+    pub fn set_mask(&self, mask: SignalFlags) {
+        *self.mask.lock() = mask - SignalFlags::unblockable();
+    }
+
+    /// ### 读取待处理的信号，每个信号对应一个 `SignalfdSiginfo`
+    /// - 返回值：写入缓冲区的字节数；缓冲区放不下一个 `SignalfdSiginfo` 时返回 `-EINVAL`，
+    ///   非阻塞且没有信号时返回 `-EAGAIN`，等待中被信号打断时返回 `-EINTR`
+    pub fn read_signals(&self, mut buf: UserBuffer) -> isize {
+        let entry_size = core::mem::size_of::<SignalfdSiginfo>();
+        let count = buf.len() / entry_size;
+        if count == 0 {
+            return -EINVAL;
+        }
+        let mut entries = Vec::new();
+        loop {
+            let mask = *self.mask.lock();
+            while entries.len() < count {
+                match dequeue_signal(mask) {
+                    Some(signo) => entries.push(SignalfdSiginfo::new(signo)),
+                    None => break,
+                }
+            }
+            if !entries.is_empty() {
+                break;
+            }
+            if self.nonblock {
+                return -EAGAIN;
+            }
+            if POLL_QUEUE.wait() < 0 {
+                return -EINTR;
+            }
+        }
+        let bytes: Vec<u8> = entries.iter().flat_map(|entry| entry.as_bytes().iter().copied()).collect();
+        buf.write(&bytes) as isize
+    }
+}
+
+impl File for SignalFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn available(&self) -> bool {
+        true
+    }
+    /// 同 `read_signals`，出错时返回 0；`sys_read` 直接调用 `read_signals` 以返回错误码
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.read_signals(buf).max(0) as usize
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+
+    fn get_name(&self) -> &str {
+        "signalfd"
+    }
+
+    fn get_fstat(&self, kstat: &mut Kstat) {
+        kstat.init(0, 512, 0, 0o600, 0);
+    }
+
+    fn get_offset(&self) -> usize {
+        0
+    }
+
+    fn set_offset(&self, _offset: usize) {}
+
+    fn set_flags(&self, _flag: OpenFlags) {}
+
+    fn set_cloexec(&self) {}
+
+    fn file_size(&self) -> usize {
+        core::usize::MAX
+    }
+
+    fn r_ready(&self) -> bool {
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        inner.pending_signals().intersects(*self.mask.lock())
+    }
+
+    fn as_signalfd(&self) -> Option<&SignalFd> {
+        Some(self)
+    }
+}
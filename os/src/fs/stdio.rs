@@ -1,7 +1,6 @@
+use super::tty::console_read;
 use super::File;
 use crate::mm::UserBuffer;
-use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
 use alloc::vec::Vec;
 
 pub struct Stdin;
@@ -18,20 +17,13 @@ impl File for Stdin {
     fn available(&self) -> bool {
         true
     }
+    /// 等待输入时被信号打断返回 0，由 `sys_read` 根据待处理的信号返回 `-ERESTARTSYS`
     fn read(&self, mut user_buf: UserBuffer) -> usize {
         assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
-            }
-        }
-        let ch = c as u8;
+        let ch = match console_read() {
+            Some(ch) => ch,
+            None => return 0,
+        };
         unsafe {
             user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
         }
@@ -57,6 +49,10 @@ impl File for Stdin {
     fn file_size(&self) -> usize {
         core::usize::MAX
     }
+
+    fn is_tty(&self) -> bool {
+        true
+    }
 }
 
 impl File for Stdout {
@@ -84,6 +80,10 @@ impl File for Stdout {
         "Stdout"
     }
 
+    fn is_tty(&self) -> bool {
+        true
+    }
+
     fn set_cloexec(&self) {
         // 涉及刚开始的 open /dev/tty，然后 sys_fcntl:fd:2,cmd:1030,arg:Some(10)
         // 可能是 sh: ls: unknown operan 等问题的原因
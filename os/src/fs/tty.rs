@@ -0,0 +1,135 @@
+/// # 控制终端
+/// `os/src/fs/tty.rs`
+/// ```
+/// pub struct Tty
+/// pub static ref CONSOLE_TTY: Mutex<Tty>
+/// pub fn poll_console()
+/// pub fn console_read() -> Option<u8>
+/// ```
+///
+/// 控制台是系统中唯一的终端，`Stdin`/`Stdout` 都指向它。
+/// 终端最多作为一个会话的控制终端，并记录该会话的前台进程组；
+/// 初始时是 initproc 所在会话（会话号为 0）的控制终端，前台进程组为 initproc 所在的进程组
+///
+/// 控制台没有输入中断，由时钟中断与 idle 控制流调用 `poll_console` 读入输入：
+/// 中断、退出与挂起字符转换为发给前台进程组的信号，其余字符进入输入缓冲区供 `Stdin` 读取，
+/// 并唤醒在 `pselect`/`ppoll` 中等待的任务
+//
+use super::POLL_QUEUE;
+use crate::sbi::console_getchar;
+use crate::task::{signal_group, SignalFlags, WaitQueue};
+use alloc::collections::VecDeque;
+use lazy_static::*;
+use spin::Mutex;
+
+/// 中断字符（Ctrl-C），向前台进程组发送 SIGINT
+const VINTR: u8 = 0x03;
+/// 退出字符（Ctrl-\），向前台进程组发送 SIGQUIT
+const VQUIT: u8 = 0x1c;
+/// 挂起字符（Ctrl-Z），向前台进程组发送 SIGTSTP
+const VSUSP: u8 = 0x1a;
+/// 输入缓冲区最多保存的字符数，缓冲区满时丢弃新的输入
+const INPUT_MAX: usize = 4096;
+
+/// ### 终端的会话与前台进程组
+/// |成员变量|描述|
+/// |--|--|
+/// |`session`|以该终端为控制终端的会话，`None` 表示不属于任何会话|
+/// |`foreground`|前台进程组号，只在 `session` 不为空时有意义|
+/// |`input`|尚未被读取的输入|
+pub struct Tty {
+    session: Option<usize>,
+    foreground: usize,
+    input: VecDeque<u8>,
+}
+
+impl Tty {
+    pub fn new(session: Option<usize>, foreground: usize) -> Self {
+        Self {
+            session,
+            foreground,
+            input: VecDeque::new(),
+        }
+    }
+
+    /// 是否为会话 `sid` 的控制终端
+    pub fn is_controlling(&self, sid: usize) -> bool {
+        self.session == Some(sid)
+    }
+
+    /// ### 成为会话 `sid` 的控制终端，会话首进程所在的进程组 `pgid` 成为前台进程组
+    pub fn attach(&mut self, sid: usize, pgid: usize) {
+        self.session = Some(sid);
+        self.foreground = pgid;
+    }
+
+    /// 前台进程组号，不属于任何会话时返回 `None`
+    pub fn foreground(&self) -> Option<usize> {
+        self.session.map(|_| self.foreground)
+    }
+
+    /// 设置前台进程组，调用者负责检查进程组属于终端所在的会话
+    pub fn set_foreground(&mut self, pgid: usize) {
+        self.foreground = pgid;
+    }
+}
+
+lazy_static! {
+    pub static ref CONSOLE_TTY: Mutex<Tty> = Mutex::new(Tty::new(Some(0), 0));
+    /// 输入缓冲区为空时，读取控制台的任务在此等待
+    static ref INPUT_QUEUE: WaitQueue = WaitQueue::new();
+}
+
+/// ### 读入控制台上的全部输入
+/// 中断、退出与挂起字符不进入输入缓冲区，而是向前台进程组发送 SIGINT/SIGQUIT/SIGTSTP；
+/// 终端不属于任何会话时这些字符被丢弃
+pub fn poll_console() {
+    let mut tty = CONSOLE_TTY.lock();
+    let mut signals = SignalFlags::empty();
+    let mut received = false;
+    loop {
+        let c = console_getchar();
+        // 没有输入时 SBI 返回 -1，NUL 字符（Ctrl-@）是合法的输入
+        if c == usize::MAX {
+            break;
+        }
+        match c as u8 {
+            VINTR => signals |= SignalFlags::SIGINT,
+            VQUIT => signals |= SignalFlags::SIGQUIT,
+            VSUSP => signals |= SignalFlags::SIGTSTP,
+            ch => {
+                if tty.input.len() < INPUT_MAX {
+                    tty.input.push_back(ch);
+                    received = true;
+                }
+            }
+        }
+    }
+    let foreground = tty.foreground();
+    drop(tty);
+    if received {
+        INPUT_QUEUE.wake_all();
+        POLL_QUEUE.wake_all();
+    }
+    if let Some(pgid) = foreground {
+        while let Some(signo) = signals.first() {
+            let signal = SignalFlags::from_signum(signo);
+            signal_group(pgid, signal);
+            signals.remove(signal);
+        }
+    }
+}
+
+/// ### 从控制台读取一个字符，没有输入时阻塞
+/// - 返回值：等待中被信号打断时返回 `None`
+pub fn console_read() -> Option<u8> {
+    loop {
+        poll_console();
+        if let Some(ch) = CONSOLE_TTY.lock().input.pop_front() {
+            return Some(ch);
+        }
+        if INPUT_QUEUE.wait() < 0 {
+            return None;
+        }
+    }
+}
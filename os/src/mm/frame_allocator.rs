@@ -189,6 +189,13 @@ pub fn enquire_refcount(ppn: PhysPageNum) -> usize {
         .enquire_ref(ppn)
 }
 
+/// ### 物理页帧的总数与空闲数
+/// - 返回值：`(total, free)`，单位为页
+pub fn frame_stat() -> (usize, usize) {
+    let (current, recycled, end, base_num) = FRAME_ALLOCATOR.lock().usage();
+    (end - base_num, end - current + recycled)
+}
+
 pub fn frame_usage() {
     let (current, recycled, end, base_num) = FRAME_ALLOCATOR.lock().usage();
     let usage = (current - base_num - recycled) as f64 * 100.0 / (end - base_num) as f64;
@@ -5,11 +5,16 @@ use super::page_table::{PTEFlags, PageTable, PageTableEntry};
 use super::{frame_usage, heap_usage};
 use crate::config::*;
 use crate::fs::{open, OSInode, OpenFlags};
+use crate::syscall::errno::{EINVAL, ENOMEM};
 use crate::task::{AuxEntry, AT_BASE, AT_ENTRY, AT_PHDR, AT_PHENT, AT_PHNUM};
-use alloc::{sync::Arc, vec::Vec};
+use super::vma::MmapProts;
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 use lazy_static::*;
 use spin::Mutex;
 
+/// 描述用户栈是否可执行的程序段类型，没有该程序段时用户栈不可执行
+const PT_GNU_STACK: u32 = 0x6474e551;
+
 extern "C" {
     fn stext();
     fn etext();
@@ -21,6 +26,7 @@ extern "C" {
     fn ebss();
     fn ekernel();
     fn strampoline();
+    fn ssigreturn();
 }
 
 lazy_static! {
@@ -39,7 +45,8 @@ pub fn kernel_token() -> usize {
 /// |参数|描述|
 /// |--|--|
 /// |`page_table`|挂着所有多级页表的节点所在的物理页帧|
-/// |`areas`|挂着对应逻辑段中的数据所在的物理页帧|
+/// |`areas`|挂着对应逻辑段中的数据所在的物理页帧，以起始虚拟页号为键有序保存|
+/// |`mmap_chunks`|mmap 映射的离散逻辑段，以起始虚拟页号为键有序保存|
 ///
 /// ```
 /// MemorySet::new_bare() -> Self
@@ -50,9 +57,9 @@ pub struct MemorySet {
     /// 挂着所有多级页表的节点所在的物理页帧
     page_table: PageTable,
     /// 挂着对应逻辑段中的数据所在的物理页帧
-    areas: Vec<MapArea>,
+    areas: BTreeMap<VirtPageNum, MapArea>,
     heap_chunk: ChunkArea,
-    mmap_chunks: Vec<ChunkArea>,
+    mmap_chunks: BTreeMap<VirtPageNum, ChunkArea>,
 
     pub heap_start: usize,
     pub heap_pt: usize,
@@ -64,14 +71,14 @@ impl MemorySet {
     pub fn new_bare() -> Self {
         Self {
             page_table: PageTable::new(),
-            areas: Vec::new(),
+            areas: BTreeMap::new(),
             heap_chunk: ChunkArea::new(
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
                 0.into(),
                 0.into(),
             ),
-            mmap_chunks: Vec::new(),
+            mmap_chunks: BTreeMap::new(),
             heap_start:0,
             heap_pt:0,
             stack_top:0,
@@ -90,24 +97,11 @@ impl MemorySet {
 
     /// 通过起始虚拟页号删除对应的逻辑段（包括连续逻辑段和离散逻辑段）
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
-        if let Some((idx, area)) = self
-            .areas
-            .iter_mut()
-            .enumerate()
-            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
-        {
+        if let Some(mut area) = self.areas.remove(&start_vpn) {
             area.unmap(&mut self.page_table);
-            self.areas.remove(idx);
         }
-
-        if let Some((idx, chunk)) = self
-            .mmap_chunks
-            .iter_mut()
-            .enumerate()
-            .find(|(_, chunk)| chunk.start_va.floor() == start_vpn)
-        {
+        if let Some(mut chunk) = self.mmap_chunks.remove(&start_vpn) {
             chunk.unmap(&mut self.page_table);
-            self.mmap_chunks.remove(idx);
         }
     }
 
@@ -123,13 +117,13 @@ impl MemorySet {
             // 写入初始化数据，如果数据存在
             map_area.copy_data(&mut self.page_table, data.0, data.1, data.2, data.3);
         }
-        self.areas.push(map_area); // 将生成的数据段压入 areas 使其生命周期由areas控制
+        self.areas.insert(map_area.vpn_range.get_start(), map_area); // 将生成的数据段压入 areas 使其生命周期由areas控制
     }
 
     /// ### 在当前地址空间插入一段已被分配空间的连续逻辑段
     /// 主要用于 COW 创建时子进程空间连续逻辑段的插入，其要求指定物理页号
     fn push_mapped_area(&mut self, map_area: MapArea) {
-        self.areas.push(map_area);
+        self.areas.insert(map_area.vpn_range.get_start(), map_area);
     }
 
     /// 映射跳板的虚拟页号和物理物理页号
@@ -141,6 +135,15 @@ impl MemorySet {
         );
     }
 
+    /// 映射信号返回跳板，用户态可以执行
+    fn map_sigreturn_trampoline(&mut self) {
+        self.page_table.map(
+            VirtAddr::from(SIGRETURN_TRAMPOLINE).into(),
+            PhysAddr::from(ssigreturn as usize).into(),
+            PTEFlags::R | PTEFlags::X | PTEFlags::U,
+        );
+    }
+
     fn map_trap_context(&mut self) {
         self.push(
             MapArea::new(
@@ -226,6 +229,7 @@ impl MemorySet {
         let mut memory_set = Self::new_bare();
         // 将跳板插入到应用地址空间
         memory_set.map_trampoline();
+        memory_set.map_sigreturn_trampoline();
         // 在应用地址空间中映射次高页面来存放 Trap 上下文
         // 将 TRAP_CONTEXT 段尽量放前，以节省 cow 时寻找时间
         memory_set.map_trap_context();
@@ -246,6 +250,8 @@ impl MemorySet {
         let mut max_end_vpn = VirtPageNum(0);
         // 是否为动态加载
         let mut elf_interpreter = false;
+        // 用户栈的访问权限，由 PT_GNU_STACK 决定是否可执行
+        let mut stack_perm = MapPermission::R | MapPermission::W | MapPermission::U;
         // 动态链接器加载地址
         let mut interp_entry_point = 0;
         // 遍历程序段进行加载
@@ -263,18 +269,7 @@ impl MemorySet {
                 xmas_elf::program::Type::Load => {
                     let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
                     let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                    let map_perm = MapPermission::U | MapPermission::R | MapPermission::W | MapPermission::X;
-                    // let mut map_perm = MapPermission::U;
-                    // let ph_flags = ph.flags();
-                    // if ph_flags.is_read() {
-                    //     map_perm |= MapPermission::R;
-                    // }
-                    // if ph_flags.is_write() {
-                    //     map_perm |= MapPermission::W;
-                    // }
-                    // if ph_flags.is_execute() {
-                    //     map_perm |= MapPermission::X;
-                    // }
+                    let map_perm = MapPermission::from_elf_flags(ph.flags());
                     let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
                     max_end_vpn = map_area.vpn_range.get_end();
                     memory_set.push(
@@ -287,6 +282,12 @@ impl MemorySet {
                         )),
                     );
                 }
+                xmas_elf::program::Type::OsSpecific(PT_GNU_STACK) => {
+                    if ph.flags().is_execute() {
+                        stack_perm |= MapPermission::X;
+                    }
+                }
+                // PT_GNU_RELRO 由动态链接器在重定位完成后通过 mprotect 设为只读
                 _ => continue,
             }
         }
@@ -314,7 +315,7 @@ impl MemorySet {
                 if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
                     let start_va: VirtAddr = (ph.virtual_addr() as usize + base_address).into();
                     let end_va: VirtAddr = (ph.virtual_addr() as usize + ph.mem_size() as usize + base_address).into();
-                    let map_perm = MapPermission::U | MapPermission::R | MapPermission::W | MapPermission::X;
+                    let map_perm = MapPermission::from_elf_flags(ph.flags());
                     let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
                     memory_set.push(
                         map_area,
@@ -341,7 +342,7 @@ impl MemorySet {
                 user_stack_bottom.into(),
                 user_stack_top.into(),
                 MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
+                stack_perm,
             ),
             None,
         );
@@ -376,83 +377,106 @@ impl MemorySet {
         // Including:   Trampoline
         //              Trap_Context
         new_memory_set.map_trampoline(); // use 2 pages (page_table create ptes)
-        for area in user_space.areas.iter() {
+        new_memory_set.map_sigreturn_trampoline();
+        if let Some(area) = user_space.areas.get(&VirtAddr::from(TRAP_CONTEXT).floor()) {
             // use 1 page
-            let start_vpn = area.vpn_range.get_start();
-            if start_vpn == VirtAddr::from(TRAP_CONTEXT).floor() {
-                let new_area = MapArea::from_another(area);
-                new_memory_set.push(new_area, None);
-                for vpn in area.vpn_range {
-                    let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                    let dst_ppn = new_memory_set.translate(vpn).unwrap().ppn();
-                    // println!{"[COW TRAP_CONTEXT] mapping {:?} --- {:?}, src: {:?}", vpn, dst_ppn, src_ppn};
-                    dst_ppn.get_bytes_array().copy_from_slice(src_ppn.get_bytes_array());
-                }
+            let new_area = MapArea::from_another(area);
+            new_memory_set.push(new_area, None);
+            for vpn in area.vpn_range {
+                let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                let dst_ppn = new_memory_set.translate(vpn).unwrap().ppn();
+                // println!{"[COW TRAP_CONTEXT] mapping {:?} --- {:?}, src: {:?}", vpn, dst_ppn, src_ppn};
+                dst_ppn.get_bytes_array().copy_from_slice(src_ppn.get_bytes_array());
             }
-            break;
         }
         // This part is for copy on write
         let parent_areas = &user_space.areas;
         let parent_page_table = &mut user_space.page_table;
-        for area in parent_areas.iter() {
+        for area in parent_areas.values() {
             let start_vpn = area.vpn_range.get_start();
-            if start_vpn != VirtAddr::from(TRAP_CONTEXT).floor() {
+            // 其余不带 U 标志的逻辑段是同一地址空间中其他线程的 Trap 上下文，子进程不需要
+            if start_vpn != VirtAddr::from(TRAP_CONTEXT).floor() && area.map_perm.contains(MapPermission::U) {
                 let mut new_area = MapArea::from_another(area);
                 // map the former physical address
                 for vpn in area.vpn_range {
                     // change the map permission of both pagetable
                     // get the former flags and ppn
-                    let pte = parent_page_table.translate(vpn).unwrap();
+                    let pte = parent_page_table.translate_present(vpn).unwrap();
                     let pte_flags = pte.flags() & !PTEFlags::W;
+                    // 不可写的页面无需写时复制，父子进程直接共享
+                    let cow = pte.writable() || pte.is_cow();
                     let src_ppn = pte.ppn();
                     frame_add_ref(src_ppn);
                     // change the flags of the src_pte
                     parent_page_table.set_flags(vpn, pte_flags);
-                    parent_page_table.set_cow(vpn);
+                    if cow {
+                        parent_page_table.set_cow(vpn);
+                    }
                     // map the cow page table to src_ppn
-                    new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-                    new_memory_set.page_table.set_cow(vpn);
+                    new_memory_set.page_table.map_like(vpn, src_ppn, pte_flags);
+                    if cow {
+                        new_memory_set.page_table.set_cow(vpn);
+                    }
                     new_area.data_frames.push(FrameTracker::from_ppn(src_ppn));
                 }
                 new_memory_set.push_mapped_area(new_area);
             }
         }
-        for chunk in user_space.mmap_chunks.iter() {
+        for chunk in user_space.mmap_chunks.values() {
             let mut new_chunk = ChunkArea::from_another(chunk);
             for _vpn in chunk.vpn_table.iter() {
                 let vpn = (*_vpn).clone();
                 // change the map permission of both pagetable
                 // get the former flags and ppn
-                let pte = parent_page_table.translate(vpn).unwrap();
+                let pte = parent_page_table.translate_present(vpn).unwrap();
                 let pte_flags = pte.flags() & !PTEFlags::W;
+                // 不可写的页面无需写时复制，父子进程直接共享
+                let cow = pte.writable() || pte.is_cow();
                 let src_ppn = pte.ppn();
                 frame_add_ref(src_ppn);
+                // 共享映射的页面父子进程直接共享，保留原有的权限
+                if chunk.shared {
+                    new_memory_set.page_table.map_like(vpn, src_ppn, pte.flags());
+                    new_chunk.vpn_table.push(vpn);
+                    new_chunk.data_frames.push(FrameTracker::from_ppn(src_ppn));
+                    continue;
+                }
                 // change the flags of the src_pte
                 parent_page_table.set_flags(vpn, pte_flags);
-                parent_page_table.set_cow(vpn);
+                if cow {
+                    parent_page_table.set_cow(vpn);
+                }
                 // map the cow page table to src_ppn
-                new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-                new_memory_set.page_table.set_cow(vpn);
+                new_memory_set.page_table.map_like(vpn, src_ppn, pte_flags);
+                if cow {
+                    new_memory_set.page_table.set_cow(vpn);
+                }
                 new_chunk.vpn_table.push(vpn);
                 new_chunk.data_frames.push(FrameTracker::from_ppn(src_ppn));
             }
-            new_memory_set.mmap_chunks.push(new_chunk);
+            new_memory_set.mmap_chunks.insert(new_chunk.start_va.floor(), new_chunk);
         }
         new_memory_set.heap_chunk = ChunkArea::from_another(&user_space.heap_chunk);
         for _vpn in user_space.heap_chunk.vpn_table.iter() {
             let vpn = (*_vpn).clone();
             // change the map permission of both pagetable
             // get the former flags and ppn
-            let pte = parent_page_table.translate(vpn).unwrap();
+            let pte = parent_page_table.translate_present(vpn).unwrap();
             let pte_flags = pte.flags() & !PTEFlags::W;
+            // 不可写的页面无需写时复制，父子进程直接共享
+            let cow = pte.writable() || pte.is_cow();
             let src_ppn = pte.ppn();
             frame_add_ref(src_ppn);
             // change the flags of the src_pte
             parent_page_table.set_flags(vpn, pte_flags);
-            parent_page_table.set_cow(vpn);
+            if cow {
+                parent_page_table.set_cow(vpn);
+            }
             // map the cow page table to src_ppn
-            new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-            new_memory_set.page_table.set_cow(vpn);
+            new_memory_set.page_table.map_like(vpn, src_ppn, pte_flags);
+            if cow {
+                new_memory_set.page_table.set_cow(vpn);
+            }
             new_memory_set.heap_chunk.vpn_table.push(vpn);
             new_memory_set.heap_chunk.data_frames.push(FrameTracker::from_ppn(src_ppn));
         }
@@ -465,6 +489,10 @@ impl MemorySet {
 
     #[no_mangle]
     pub fn cow_alloc(&mut self, vpn: VirtPageNum, former_ppn: PhysPageNum) -> isize {
+        // mprotect 去掉写权限后 COW 页面仍保留标记，此时的写入是非法访问
+        if !self.permission(vpn).map_or(false, |perm| perm.is_write()) {
+            return -1;
+        }
         if enquire_refcount(former_ppn) == 1 {
             self.page_table.reset_cow(vpn);
             // change the flags of the src_pte
@@ -475,21 +503,13 @@ impl MemorySet {
         let frame = frame_alloc().unwrap();
         let ppn = frame.ppn;
         self.remap_cow(vpn, ppn, former_ppn);
-        for area in self.areas.iter_mut() {
-            let head_vpn = area.vpn_range.get_start();
-            let tail_vpn = area.vpn_range.get_end();
-            if vpn <= tail_vpn && vpn >= head_vpn {
-                area.data_frames.push(frame);
-                return 0;
-            }
+        if let Some((_, area)) = self.areas.range_mut(..=vpn).next_back().filter(|(_, area)| area.contains(vpn)) {
+            area.data_frames.push(frame);
+            return 0;
         }
-        for chunk in self.mmap_chunks.iter_mut() {
-            let head_vpn = VirtPageNum::from(chunk.start_va);
-            let tail_vpn = VirtPageNum::from(chunk.end_va);
-            if vpn <= tail_vpn && vpn >= head_vpn {
-                chunk.data_frames.push(frame);
-                return 0;
-            }
+        if let Some((_, chunk)) = self.mmap_chunks.range_mut(..=vpn).next_back().filter(|(_, chunk)| chunk.contains(vpn)) {
+            chunk.data_frames.push(frame);
+            return 0;
         }
         let head_vpn = VirtPageNum::from(self.heap_chunk.start_va);
         let tail_vpn = VirtPageNum::from(self.heap_chunk.end_va);
@@ -504,18 +524,40 @@ impl MemorySet {
         self.page_table.remap_cow(vpn, ppn, former_ppn);
     }
 
-    /// 为mmap缺页分配空页表
-    pub fn lazy_mmap(&mut self, stval: VirtAddr) -> isize {
-        for mmap_chunk in self.mmap_chunks.iter_mut() {
-            if stval >= mmap_chunk.start_va && stval < mmap_chunk.end_va {
-                mmap_chunk.push_vpn(stval.floor(), &mut self.page_table);
-                return 0;
+    /// 共享文件映射缺页时，映射从共享页面缓存中取得的物理页帧
+    pub fn map_shared_page(&mut self, vpn: VirtPageNum, frame: FrameTracker) -> isize {
+        if self.page_table.translate_present(vpn).is_some() {
+            return -1;
+        }
+        match self.mmap_chunks.range_mut(..=vpn).next_back() {
+            Some((_, chunk)) if chunk.contains(vpn) => {
+                chunk.push_frame(vpn, frame, &mut self.page_table);
+                0
             }
+            _ => -1,
+        }
+    }
+
+    /// 为mmap缺页分配空页表，`is_load` 为 `false` 表示写入；逻辑段的权限不允许该访问时返回 -1
+    pub fn lazy_mmap(&mut self, stval: VirtAddr, is_load: bool) -> isize {
+        let vpn = stval.floor();
+        // 被 mprotect 设为不可访问的页面仍映射着物理页帧，不能再分配
+        if self.page_table.translate_present(vpn).is_some() || !self.fault_allowed(vpn, is_load) {
+            return -1;
+        }
+        match self.mmap_chunks.range_mut(..=vpn).next_back() {
+            Some((_, mmap_chunk)) if stval >= mmap_chunk.start_va && stval < mmap_chunk.end_va => {
+                mmap_chunk.push_vpn(vpn, &mut self.page_table);
+                0
+            }
+            _ => -1,
         }
-        -1
     }
 
     pub fn lazy_alloc_heap(&mut self, vpn: VirtPageNum) -> isize {
+        if self.page_table.translate_present(vpn).is_some() {
+            return -1;
+        }
         self.heap_chunk.push_vpn(vpn, &mut self.page_table);
         0
     }
@@ -535,6 +577,16 @@ impl MemorySet {
         self.page_table.translate(vpn)
     }
 
+    /// ### 取出页面的写入标记
+    /// - 返回值：页面已映射且被写过时返回其物理页号，并清除页表项的 D 标志位，以便记录之后的写入
+    pub fn take_dirty(&mut self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        let ppn = self.page_table.take_dirty(vpn)?;
+        unsafe {
+            core::arch::asm!("sfence.vma");
+        }
+        Some(ppn)
+    }
+
     // WARNING: This function causes inconsistency between pte flags and
     //          map_area flags.
     // return -1 if not found, 0 if found
@@ -542,15 +594,6 @@ impl MemorySet {
     //     self.page_table.set_pte_flags(vpn, flags)
     // }
 
-    /// ### 回收应用地址空间
-    /// 将地址空间中的逻辑段列表 areas 清空（即执行 Vec 向量清空），
-    /// 这将导致应用地址空间被回收（即进程的数据和代码对应的物理页帧都被回收），
-    /// 但用来存放页表的那些物理页帧此时还不会被回收（会由父进程最后回收子进程剩余的占用资源）
-    pub fn recycle_data_pages(&mut self) {
-        //*self = Self::new_bare();
-        self.areas.clear();
-    }
-
     /// ### 在地址空间中插入一个空的离散逻辑段
     /// - 已确定：
     ///     - 起止虚拟地址
@@ -559,46 +602,206 @@ impl MemorySet {
     /// - 留空：
     ///     - vpn_table
     ///     - data_frames
-    pub fn insert_mmap_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, permission: MapPermission) {
-        let new_chunk_area = ChunkArea::new(MapType::Framed, permission, start_va, end_va);
-        self.mmap_chunks.push(new_chunk_area);
+    pub fn insert_mmap_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, permission: MapPermission, shared: bool) {
+        let mut new_chunk_area = ChunkArea::new(MapType::Framed, permission, start_va, end_va);
+        new_chunk_area.shared = shared;
+        self.mmap_chunks.insert(start_va.floor(), new_chunk_area);
+    }
+
+    /// 虚拟页范围 `[start_vpn, end_vpn)` 是否与已有的逻辑段、mmap 映射或堆区重叠
+    pub fn is_free(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let area_overlaps = self
+            .areas
+            .range(..end_vpn)
+            .next_back()
+            .map_or(false, |(_, area)| area.vpn_range.get_end() > start_vpn);
+        let chunk_overlaps = self
+            .mmap_chunks
+            .range(..end_vpn)
+            .next_back()
+            .map_or(false, |(_, chunk)| chunk.end_va.ceil() > start_vpn);
+        let heap_overlaps = self.heap_chunk.start_va.floor() < end_vpn && self.heap_chunk.end_va.ceil() > start_vpn;
+        !(area_overlaps || chunk_overlaps || heap_overlaps)
+    }
+
+    /// ### 为新的 mmap 映射寻找 `pages` 个连续的空闲虚拟页
+    /// - `hint` 不为 0 且其后的区间空闲时直接使用 `hint`
+    /// - 否则在 `[MMAP_BASE, SIGRETURN_TRAMPOLINE)` 中按地址从低到高首次适应
+    /// - 返回值：没有足够大的空闲区间时返回 `None`
+    pub fn find_free_range(&self, hint: VirtPageNum, pages: usize) -> Option<VirtPageNum> {
+        let limit = VirtAddr::from(SIGRETURN_TRAMPOLINE).floor().0;
+        if hint.0 != 0 && hint.0 + pages <= limit && self.is_free(hint, VirtPageNum(hint.0 + pages)) {
+            return Some(hint);
+        }
+        let mut used: Vec<(VirtPageNum, VirtPageNum)> = self
+            .areas
+            .values()
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            .chain(self.mmap_chunks.values().map(|chunk| (chunk.start_va.floor(), chunk.end_va.ceil())))
+            .chain(core::iter::once((self.heap_chunk.start_va.floor(), self.heap_chunk.end_va.ceil())))
+            .collect();
+        used.sort_by_key(|&(start, _)| start);
+        let mut cursor = VirtAddr::from(MMAP_BASE).floor();
+        for (start, end) in used {
+            if start.0 >= cursor.0 + pages {
+                break;
+            }
+            cursor = cursor.max(end);
+        }
+        if cursor.0 + pages <= limit {
+            Some(cursor)
+        } else {
+            None
+        }
+    }
+
+    /// ### 缺页访问是否为页面所在逻辑段的权限所允许
+    /// `PROT_NONE` 的页面不允许任何访问，写入（`is_load` 为 `false`）还需要写权限；不属于任何用户逻辑段时返回 `false`
+    pub fn fault_allowed(&self, vpn: VirtPageNum, is_load: bool) -> bool {
+        match self.permission(vpn) {
+            Some(perm) => {
+                perm.intersects(MapPermission::R | MapPermission::W | MapPermission::X) && (is_load || perm.is_write())
+            }
+            None => false,
+        }
     }
 
-    pub fn check_va_range(&self, start_va: VirtAddr, len: usize) -> bool {
-        let end_va = VirtAddr::from(start_va.0 + len);
-        for area in self.areas.iter() {
-            if area.start_va <= start_va && end_va <= area.end_va {
-                return true;
+    /// 虚拟页面所在用户逻辑段的访问权限，不属于任何用户逻辑段时返回 `None`；
+    /// `PROT_NONE` 的逻辑段返回不含 R/W/X 的权限，其中的页面不可访问
+    pub fn permission(&self, vpn: VirtPageNum) -> Option<MapPermission> {
+        if let Some((_, area)) = self.areas.range(..=vpn).next_back() {
+            if area.map_perm.is_user() && area.contains(vpn) {
+                return Some(area.map_perm);
             }
         }
-        for chunk in self.mmap_chunks.iter() {
-            if start_va <= chunk.start_va && end_va <= chunk.start_va {
-                return true;
+        if let Some((_, chunk)) = self.mmap_chunks.range(..=vpn).next_back() {
+            if chunk.contains(vpn) {
+                return Some(chunk.map_perm);
             }
         }
-        if self.heap_chunk.start_va <= start_va && end_va <= self.heap_chunk.end_va {
-            return true;
+        if self.heap_chunk.contains(vpn) {
+            return Some(self.heap_chunk.map_perm);
         }
-        return false;
+        None
     }
 
-    // 检查mmap是否有重叠区域
-    pub fn reduce_chunk_range(&mut self, addr: usize, new_len: usize) {
-        // 检查是否与堆空间重合
-        if self.heap_chunk.start_va.0 == addr {
-            self.heap_chunk.set_mmap_range(VirtAddr::from(self.heap_chunk.start_va.0 + new_len), self.heap_chunk.end_va);
-            self.heap_start = addr+ new_len;
+    /// ### 修改 `[start_vpn, end_vpn)` 范围内用户页面的访问权限
+    /// - 涉及的逻辑段在范围边界处被拆分，范围内已映射页面的页表项随之更新
+    /// - COW 页面保持只读与 COW 标记，写入时仍先复制；与其他地址空间共享的只读页面获得写权限时同样改为 COW，
+    ///   `MAP_SHARED` 映射的页面除外
+    /// - 新权限不含 R/W/X（`PROT_NONE`）时清除页表项的 V 标志位，物理页帧仍保留在页表项与逻辑段中，
+    ///   恢复权限时重新设置 V 标志位；R/W/X 全为 0 而 V 为 1 的页表项会被 MMU 当作指向下一级页表的页表项
+    /// - 堆区作为一个整体管理，只能整体修改
+    /// - 返回值：范围内存在未映射的页面时返回 `-ENOMEM`，只修改堆区的一部分时返回 `-EINVAL`
+    pub fn mprotect(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum, perm: MapPermission) -> isize {
+        if VPNRange::new(start_vpn, end_vpn).into_iter().any(|vpn| self.permission(vpn).is_none()) {
+            return -ENOMEM;
         }
-        for chunk in self.mmap_chunks.iter_mut() {
-            // 实际上不止这一种情况，todo
-            if chunk.start_va.0 == addr || addr + new_len == chunk.end_va.0 {
-                chunk.set_mmap_range(chunk.start_va.into(), (chunk.end_va.0 - new_len).into());
+        let heap_start = self.heap_chunk.start_va.floor();
+        let heap_end = self.heap_chunk.end_va.ceil();
+        if start_vpn < heap_end && end_vpn > heap_start {
+            if start_vpn > heap_start || end_vpn < heap_end {
+                return -EINVAL;
+            }
+            self.heap_chunk.map_perm = perm;
+        }
+
+        self.split_range(start_vpn, end_vpn);
+        for area in self.areas.range_mut(start_vpn..end_vpn).map(|(_, area)| area).filter(|area| area.map_perm.is_user()) {
+            area.map_perm = perm;
+        }
+        for (_, chunk) in self.mmap_chunks.range_mut(start_vpn..end_vpn) {
+            chunk.map_perm = perm;
+        }
+
+        let rwx = PTEFlags::R | PTEFlags::W | PTEFlags::X;
+        let new_rwx = PTEFlags::from_bits(perm.bits).unwrap() & rwx;
+        for vpn in VPNRange::new(start_vpn, end_vpn) {
+            let pte = match self.page_table.translate_present(vpn) {
+                Some(pte) => pte,
+                None => continue,
+            };
+            let mut flags = (pte.flags() - rwx - PTEFlags::V) | new_rwx;
+            if !new_rwx.is_empty() {
+                flags |= PTEFlags::V;
+            }
+            let shared = match self.mmap_chunks.range(..=vpn).next_back() {
+                Some((_, chunk)) => chunk.shared && chunk.contains(vpn),
+                None => false,
+            };
+            if perm.is_write() && !shared && (pte.is_cow() || enquire_refcount(pte.ppn()) > 1) {
+                flags.remove(PTEFlags::W);
+                self.page_table.set_cow(vpn);
+            }
+            self.page_table.set_flags(vpn, flags);
+        }
+        unsafe {
+            core::arch::asm!("sfence.vma");
+        }
+        0
+    }
+
+    /// ### 解除 `[start_vpn, end_vpn)` 范围内的用户映射
+    /// - 部分位于范围内的逻辑段在范围边界处被拆分，范围内的部分连同其物理页帧一起被回收
+    /// - 范围覆盖堆区开头时，堆区起点后移到范围之后
+    /// - 范围内没有映射的部分被忽略
+    pub fn unmap_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        self.split_range(start_vpn, end_vpn);
+
+        let removed: Vec<VirtPageNum> = self
+            .areas
+            .range(start_vpn..end_vpn)
+            .filter(|(_, area)| area.map_perm.is_user())
+            .map(|(&vpn, _)| vpn)
+            .collect();
+        for vpn in removed {
+            let mut area = self.areas.remove(&vpn).unwrap();
+            area.unmap(&mut self.page_table);
+        }
+
+        let removed: Vec<VirtPageNum> = self.mmap_chunks.range(start_vpn..end_vpn).map(|(&vpn, _)| vpn).collect();
+        for vpn in removed {
+            let mut chunk = self.mmap_chunks.remove(&vpn).unwrap();
+            chunk.unmap(&mut self.page_table);
+        }
+
+        let heap_start = self.heap_chunk.start_va.floor();
+        let heap_end = self.heap_chunk.end_va.ceil();
+        if start_vpn <= heap_start && end_vpn > heap_start {
+            let at = end_vpn.min(heap_end);
+            let upper = self.heap_chunk.split_off(at, &self.page_table);
+            let mut lower = core::mem::replace(&mut self.heap_chunk, upper);
+            lower.unmap(&mut self.page_table);
+            self.heap_start = VirtAddr::from(at).0;
+            self.heap_pt = self.heap_pt.max(self.heap_start);
+        }
+        unsafe {
+            core::arch::asm!("sfence.vma");
+        }
+    }
+
+    /// ### 在 `start_vpn` 与 `end_vpn` 处拆分用户逻辑段与离散逻辑段
+    /// 拆分后每个逻辑段要么完全位于范围之内，要么完全位于范围之外
+    fn split_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        for at in [start_vpn, end_vpn] {
+            if let Some((_, area)) = self.areas.range_mut(..at).next_back() {
+                if area.map_perm.is_user() && at < area.vpn_range.get_end() {
+                    let upper = area.split_off(at, &self.page_table);
+                    self.areas.insert(at, upper);
+                }
+            }
+            if let Some((_, chunk)) = self.mmap_chunks.range_mut(..at).next_back() {
+                if at < chunk.end_va.ceil() {
+                    let upper = chunk.split_off(at, &self.page_table);
+                    self.mmap_chunks.insert(at, upper);
+                }
             }
         }
     }
 }
 
 /// ### 离散逻辑段
+/// `shared` 表示 `MAP_SHARED` 映射，其页面在 fork 时直接共享而不是写时复制
 pub struct ChunkArea {
     vpn_table: Vec<VirtPageNum>,
     data_frames: Vec<FrameTracker>,
@@ -606,6 +809,7 @@ pub struct ChunkArea {
     map_perm: MapPermission,
     start_va: VirtAddr,
     end_va: VirtAddr,
+    shared: bool,
 }
 
 impl ChunkArea {
@@ -617,18 +821,30 @@ impl ChunkArea {
             map_perm,
             start_va: start,
             end_va: end,
+            shared: false,
         }
     }
 
-    pub fn set_mmap_range(&mut self, start: VirtAddr, end: VirtAddr) {
-        self.start_va = start;
-        self.end_va = end;
-        for (idx, vpn) in self.vpn_table.clone().iter_mut().enumerate() {
-            if VirtAddr::from(*vpn) >= self.end_va {
-                self.vpn_table.remove(idx);
-                // todo:删除 data_frame 中超范围的物理页帧
+    /// 虚拟页面是否位于该离散逻辑段的地址范围内
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        vpn >= self.start_va.floor() && vpn < self.end_va.ceil()
+    }
+
+    /// ### 在虚拟页 `at` 处将离散逻辑段一分为二
+    /// 当前逻辑段保留 `at` 之前的部分，返回 `at` 及之后的部分，已分配的页面按其映射的物理页帧归入对应的一半
+    pub fn split_off(&mut self, at: VirtPageNum, page_table: &PageTable) -> Self {
+        let mut upper = Self::new(self.map_type, self.map_perm, at.into(), self.end_va);
+        upper.shared = self.shared;
+        self.end_va = at.into();
+        let (lower_vpns, upper_vpns) = self.vpn_table.iter().partition(|&&vpn| vpn < at);
+        self.vpn_table = lower_vpns;
+        upper.vpn_table = upper_vpns;
+        for &vpn in upper.vpn_table.iter() {
+            if let Some(frame) = take_frame(&mut self.data_frames, vpn, page_table) {
+                upper.data_frames.push(frame);
             }
         }
+        upper
     }
 
     pub fn push_vpn(&mut self, vpn: VirtPageNum, page_table: &mut PageTable) {
@@ -644,9 +860,18 @@ impl ChunkArea {
             map_perm: another.map_perm,
             start_va: another.start_va,
             end_va: another.end_va,
+            shared: another.shared,
         }
     }
 
+    /// 将已有的物理页帧映射到虚拟页 `vpn`，用于共享文件映射
+    pub fn push_frame(&mut self, vpn: VirtPageNum, frame: FrameTracker, page_table: &mut PageTable) {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags);
+        self.vpn_table.push(vpn);
+        self.data_frames.push(frame);
+    }
+
     // Alloc and map one page
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         let ppn: PhysPageNum;
@@ -699,6 +924,34 @@ bitflags! {
 }
 
 impl MapPermission {
+    /// 由 ELF 程序段的 `p_flags` 得到用户态的访问权限
+    pub fn from_elf_flags(flags: xmas_elf::program::Flags) -> Self {
+        let mut map_perm = MapPermission::U;
+        if flags.is_read() {
+            map_perm |= MapPermission::R;
+        }
+        if flags.is_write() {
+            map_perm |= MapPermission::W;
+        }
+        if flags.is_execute() {
+            map_perm |= MapPermission::X;
+        }
+        map_perm
+    }
+    /// 由 mmap/mprotect 的 `prot` 得到用户态的访问权限，RISC-V 不支持只写页面，可写的页面同时可读
+    pub fn from_mmap_prot(prot: MmapProts) -> Self {
+        let mut map_perm = MapPermission::U;
+        if prot.contains(MmapProts::PROT_READ) {
+            map_perm |= MapPermission::R;
+        }
+        if prot.contains(MmapProts::PROT_WRITE) {
+            map_perm |= MapPermission::R | MapPermission::W;
+        }
+        if prot.contains(MmapProts::PROT_EXEC) {
+            map_perm |= MapPermission::X;
+        }
+        map_perm
+    }
     pub fn is_read(self) -> bool {
         self.bits & 1 << 1 == 1 << 1
     }
@@ -773,6 +1026,26 @@ impl MapArea {
         }
     }
 
+    /// 虚拟页面是否位于该逻辑段内
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        vpn >= self.vpn_range.get_start() && vpn < self.vpn_range.get_end()
+    }
+
+    /// ### 在虚拟页 `at` 处将逻辑段一分为二
+    /// 当前逻辑段保留 `at` 之前的部分，返回 `at` 及之后的部分，物理页帧按映射关系归入对应的一半
+    pub fn split_off(&mut self, at: VirtPageNum, page_table: &PageTable) -> Self {
+        let end_vpn = self.vpn_range.get_end();
+        let mut upper = Self::new(at.into(), self.end_va, self.map_type, self.map_perm);
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), at);
+        self.end_va = at.into();
+        for vpn in VPNRange::new(at, end_vpn) {
+            if let Some(frame) = take_frame(&mut self.data_frames, vpn, page_table) {
+                upper.data_frames.push(frame);
+            }
+        }
+        upper
+    }
+
     /// 在多级页表中根据vpn分配空间
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         let ppn: PhysPageNum;
@@ -850,6 +1123,13 @@ impl MapArea {
     }
 }
 
+/// 从物理页帧列表中取出虚拟页 `vpn` 当前映射到的物理页帧，用于拆分逻辑段
+fn take_frame(frames: &mut Vec<FrameTracker>, vpn: VirtPageNum, page_table: &PageTable) -> Option<FrameTracker> {
+    let pte = page_table.translate_present(vpn)?;
+    let idx = frames.iter().position(|frame| frame.ppn == pte.ppn())?;
+    Some(frames.remove(idx))
+}
+
 lazy_static! {
     pub static ref BUSYBOX: Vec<u8> = {
         if let Some(app_inode) = open("/", "busybox", OpenFlags::O_RDONLY) {
@@ -911,7 +1191,7 @@ impl MemorySet {
     pub fn debug_show_layout(&self) {
         println!("-----------------------MM Layout-----------------------");
         println! {"PID:{}",crate::task::current_task().unwrap().pid.0};
-        for area in &self.areas {
+        for area in self.areas.values() {
             print!(
                 "MapArea  : 0x{:010x}--0x{:010x} len:0x{:08x} ",
                 area.start_va.0,
@@ -939,7 +1219,7 @@ impl MemorySet {
                 println!("-");
             };
         }
-        for mmap_chunk in &self.mmap_chunks {
+        for mmap_chunk in self.mmap_chunks.values() {
             print!(
                 "ChunkArea: 0x{:010x}--0x{:010x} len:0x{:08x} ",
                 mmap_chunk.start_va.0,
@@ -14,10 +14,10 @@ mod page_table;     // 页表
 mod vma;            // 虚拟内存地址映射空间
 
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker,frame_usage};
+pub use frame_allocator::{frame_alloc, frame_dealloc, frame_stat, FrameTracker,frame_usage};
 pub use memory_set::{kernel_token, MapPermission, MemorySet, KERNEL_SPACE};
 pub use page_table::{
-    translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
+    copy_from_user, copy_str_from_user, copy_to_user, translated_byte_buffer, translated_refmut, user_buffer, PageTable,
     PageTableEntry, UserBuffer, UserBufferIterator,
 };
 pub use vma::*;
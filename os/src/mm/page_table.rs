@@ -7,10 +7,16 @@
 /// pub struct PageTable
 ///
 /// pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]>
+///
+/// pub fn user_buffer(token: usize, ptr: *const u8, len: usize, write: bool) -> Result<UserBuffer, Errno>
+/// pub fn copy_from_user<T: Copy>(token: usize, src: *const T) -> Result<T, Errno>
+/// pub fn copy_to_user<T>(token: usize, dst: *mut T, value: &T) -> Result<(), Errno>
+/// pub fn copy_str_from_user(token: usize, ptr: *const u8) -> Result<String, Errno>
 /// ```
 //
 
-use crate::config::PAGE_SIZE;
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use crate::syscall::errno::{Errno, EFAULT};
 use crate::task::current_task;
 
 use super::{frame_alloc, FrameTracker};
@@ -119,6 +125,16 @@ impl PageTableEntry {
     pub fn is_cow(&self) -> bool {
         self.bits & (1 << 9) != 0
     }
+
+    /// 页表项是否映射了物理页帧，包括被 mprotect 设为不可访问而清除了 V 标志位的页面
+    pub fn is_present(&self) -> bool {
+        self.bits != 0
+    }
+
+    /// 验证页面是否被写过（D标志位是否为1）
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
 }
 
 // SV39 多级页表
@@ -200,6 +216,20 @@ impl PageTable {
         result
     }
 
+    /// 根据vpn查找第三级页表中的页表项，不要求该页表项有效，用于处理不可访问（V 为 0）但仍映射着物理页帧的页面
+    fn find_leaf(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for idx in &idxs[..2] {
+            let pte = &ppn.get_pte_array()[*idx];
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        Some(&mut ppn.get_pte_array()[idxs[2]])
+    }
+
     /// ### 建立一个虚拟页号到物理页号的映射
     /// 根据VPN找到第三级页表中的对应项，将 `PPN` 和 `flags` 写入到页表项
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
@@ -212,17 +242,30 @@ impl PageTable {
     /// ### 删除一个虚拟页号到物理页号的映射
     /// 只需根据虚拟页号找到页表项，然后修改或者直接清空其内容即可
     pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte(vpn).unwrap();
-        assert!(pte.is_valid(), "{:?} is invalid before unmapping", vpn);
+        let pte = self.find_leaf(vpn).unwrap();
+        assert!(pte.is_present(), "{:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
 
+    /// ### 按给定的标志位原样建立映射，用于 fork 复制页表项
+    /// 与 `map` 不同，不会设置 V 标志位，不可访问的页面在新页表中同样不可访问
+    pub fn map_like(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(!pte.is_present(), "{:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags);
+    }
+
     /// ### 根据 vpn 查找页表项
     /// 调用 `find_pte` 来实现，如果能够找到页表项，那么它会将页表项拷贝一份并返回，否则就返回一个 `None`
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).map(|pte| *pte)
     }
 
+    /// ### 根据 vpn 查找映射着物理页帧的页表项，包括不可访问（V 为 0）的页面
+    pub fn translate_present(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.find_leaf(vpn).map(|pte| *pte).filter(|pte| pte.is_present())
+    }
+
     /// 在当前多级页表中将虚拟地址转换为物理地址
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
         self.find_pte(va.clone().floor()).map(|pte| {
@@ -271,6 +314,17 @@ impl PageTable {
         self.find_pte_create(vpn).unwrap().set_flags(flags);
     }
 
+    /// ### 清除页表项的 D 标志位
+    /// - 返回值：页面已映射且被写过时返回其物理页号，否则返回 `None`
+    pub fn take_dirty(&mut self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        let pte = self.find_leaf(vpn)?;
+        if !pte.is_present() || !pte.dirty() {
+            return None;
+        }
+        pte.set_flags(pte.flags() - PTEFlags::D);
+        Some(pte.ppn())
+    }
+
     pub fn remap_cow(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, former_ppn: PhysPageNum) {
         let pte = self.find_pte_create(vpn).unwrap();
         *pte = PageTableEntry::new(ppn, pte.flags() | PTEFlags::W);
@@ -316,32 +370,6 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
     v
 }
 
-/// ### 从内核地址空间之外的某个应用的用户态地址空间中拿到一个字符串
-/// 针对应用的字符串中字符的用户态虚拟地址，查页表，找到对应的内核虚拟地址，逐字节地构造字符串，直到发现一个 \0 为止
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-    loop {
-        let ch: u8 = *(page_table.translate_va(VirtAddr::from(va)).unwrap().get_mut());
-        if ch == 0 {
-            break;
-        } else {
-            string.push(ch as char);
-            va += 1;
-        }
-    }
-    string
-}
-
-/// 根据 多级页表token (satp) 和 虚拟地址 获取大小为 T 的空间的不可变切片
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
-    let offset = ptr as usize % PAGE_SIZE;
-    assert!(PAGE_SIZE - offset >= size_of::<T>(), "cross-page access");
-    let page_table = PageTable::from_token(token);
-    page_table.translate_va(VirtAddr::from(ptr as usize)).unwrap().get_ref()
-}
-
 /// 根据 多级页表token (satp) 和 虚拟地址 获取大小为 T 的空间的切片
 pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
     let offset = ptr as usize % PAGE_SIZE;
@@ -352,6 +380,116 @@ pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
     page_table.translate_va(VirtAddr::from(va)).unwrap().get_mut()
 }
 
+/// ### 检查用户虚拟页面并返回其物理页号
+/// 页面尚未分配（堆、mmap 的懒分配）或需要写入 COW 页面时，通过当前任务的 `check_lazy` 补齐后再次检查
+/// - `token` 必须是当前任务的地址空间
+/// - `write` 表示内核是否要写入该页面
+/// - 返回值：页面不属于用户、权限不足或无法补齐时返回 `EFAULT`
+fn translate_user_page(token: usize, va: VirtAddr, write: bool) -> Result<PhysPageNum, Errno> {
+    let page_table = PageTable::from_token(token);
+    let vpn = va.floor();
+    let accessible = |pte: &PageTableEntry| {
+        let flags = pte.flags();
+        flags.contains(PTEFlags::U) && flags.contains(PTEFlags::R) && (!write || flags.contains(PTEFlags::W))
+    };
+    if let Some(pte) = page_table.translate(vpn) {
+        if accessible(&pte) {
+            return Ok(pte.ppn());
+        }
+        // 已经映射但不可访问的页面，只有写 COW 页面这一种情况可以补救
+        if !(write && pte.is_cow() && pte.flags().contains(PTEFlags::U)) {
+            return Err(EFAULT);
+        }
+    }
+    // 对文件映射总是先读入文件内容，以免内核部分写入时丢失原有数据
+    if current_task().unwrap().check_lazy(va, true) != 0 {
+        return Err(EFAULT);
+    }
+    match page_table.translate(vpn) {
+        Some(pte) if accessible(&pte) => Ok(pte.ppn()),
+        _ => Err(EFAULT),
+    }
+}
+
+/// ### 检查用户地址范围 `[start, start + len)` 是否位于用户地址空间之内
+fn check_user_range(start: usize, len: usize) -> Result<(), Errno> {
+    match start.checked_add(len) {
+        Some(end) if start != 0 && end <= USER_SPACE_END => Ok(()),
+        _ => Err(EFAULT),
+    }
+}
+
+/// ### 获取一段经过检查的用户缓冲区
+/// 与 `translated_byte_buffer` 相同，但会检查地址范围与页面权限，并按需补齐懒分配页面和 COW 页面
+/// |参数|描述|
+/// |--|--|
+/// |`token`|当前任务地址空间的 token|
+/// |`ptr`|应用地址空间中的一段缓冲区的起始地址|
+/// |`len`|应用地址空间中的一段缓冲区的长度|
+/// |`write`|内核是否要写入该缓冲区|
+pub fn user_buffer(token: usize, ptr: *const u8, len: usize, write: bool) -> Result<UserBuffer, Errno> {
+    if len == 0 {
+        return Ok(UserBuffer::empty());
+    }
+    check_user_range(ptr as usize, len)?;
+    let mut start = ptr as usize;
+    let end = start + len;
+    let mut v = Vec::new();
+    while start < end {
+        let start_va = VirtAddr::from(start);
+        let mut vpn = start_va.floor();
+        let ppn = translate_user_page(token, start_va, write)?;
+        vpn.step();
+        let mut end_va: VirtAddr = vpn.into();
+        end_va = end_va.min(VirtAddr::from(end));
+        if end_va.page_offset() == 0 {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+        } else {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+        }
+        start = end_va.into();
+    }
+    Ok(UserBuffer::new(v))
+}
+
+/// ### 从用户地址空间读取一个 `T` 类型的值
+/// 允许跨页，地址不合法时返回 `EFAULT`
+pub fn copy_from_user<T: Copy>(token: usize, src: *const T) -> Result<T, Errno> {
+    let buffer = user_buffer(token, src as *const u8, size_of::<T>(), false)?;
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let bytes = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+    buffer.read(bytes);
+    Ok(unsafe { value.assume_init() })
+}
+
+/// ### 向用户地址空间写入一个 `T` 类型的值
+/// 允许跨页，地址不合法时返回 `EFAULT`
+pub fn copy_to_user<T>(token: usize, dst: *mut T, value: &T) -> Result<(), Errno> {
+    let mut buffer = user_buffer(token, dst as *const u8, size_of::<T>(), true)?;
+    let bytes = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    buffer.write(bytes);
+    Ok(())
+}
+
+/// ### 从用户地址空间读取一个以 \0 结尾的字符串
+/// 逐页检查，直到遇到 \0 为止，地址不合法时返回 `EFAULT`
+pub fn copy_str_from_user(token: usize, ptr: *const u8) -> Result<String, Errno> {
+    let mut string = String::new();
+    let mut va = ptr as usize;
+    loop {
+        check_user_range(va, 1)?;
+        let ppn = translate_user_page(token, VirtAddr::from(va), false)?;
+        let page = ppn.get_bytes_array();
+        for &ch in &page[VirtAddr::from(va).page_offset()..] {
+            if ch == 0 {
+                return Ok(string);
+            }
+            string.push(ch as char);
+        }
+        va = (va & !(PAGE_SIZE - 1)) + PAGE_SIZE;
+    }
+}
+
 /// ### 应用地址空间中的一段缓冲区（即内存）的抽象
 /// - `buffers`：位于应用地址空间中，内核无法直接通过用户地址空间的虚拟地址来访问，因此需要进行封装
 #[derive(Debug)]
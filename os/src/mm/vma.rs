@@ -1,9 +1,14 @@
 use super::{UserBuffer, translated_byte_buffer};
-use super::address::VirtAddr;
+use super::address::{VirtAddr, VirtPageNum};
+use super::frame_allocator::{enquire_refcount, frame_add_ref, frame_alloc, FrameTracker};
+use super::MemorySet;
 use crate::config::PAGE_SIZE;
-use crate::fs::File;
+use crate::fs::{File, OSInode};
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
+use lazy_static::*;
+use spin::Mutex;
 // use core::fmt::{self, Debug, Formatter};
 
 bitflags! {
@@ -42,93 +47,172 @@ bitflags! {
 
 }
 
+lazy_static! {
+    /// 共享文件映射的页面，以（文件首簇号，文件页号）为键；
+    /// 以 `MAP_SHARED` 映射同一文件同一页的所有地址空间共享这里的物理页帧
+    static ref SHARED_PAGES: Mutex<BTreeMap<(u32, usize), FrameTracker>> = Mutex::new(BTreeMap::new());
+}
+
+/// ### 获取文件第 `index` 页对应的共享物理页帧
+/// - 没有地址空间映射该页时从文件中重新读入，以免用到 `write` 修改前的旧内容
+/// - 空文件没有首簇号，无法区分，每次都分配一个新的空白页面
+/// - 返回值：增加了一次引用的物理页帧，由调用者映射并持有
+pub fn shared_page(file: &OSInode, index: usize) -> FrameTracker {
+    let cluster = file.get_head_cluster();
+    if cluster == 0 {
+        return frame_alloc().expect("out of memory");
+    }
+    let mut shared_pages = SHARED_PAGES.lock();
+    let key = (cluster, index);
+    if let Some(frame) = shared_pages.get(&key) {
+        if enquire_refcount(frame.ppn) == 1 {
+            shared_pages.remove(&key);
+        }
+    }
+    let frame = shared_pages.entry(key).or_insert_with(|| {
+        let frame = frame_alloc().expect("out of memory");
+        file.read_at(index * PAGE_SIZE, frame.ppn.get_bytes_array());
+        frame
+    });
+    frame_add_ref(frame.ppn);
+    FrameTracker::from_ppn(frame.ppn)
+}
+
+/// 释放已经没有地址空间映射的共享页面
+pub fn release_shared_pages() {
+    let mut shared_pages = SHARED_PAGES.lock();
+    let unused: Vec<(u32, usize)> = shared_pages
+        .iter()
+        .filter(|(_, frame)| enquire_refcount(frame.ppn) == 1)
+        .map(|(&key, _)| key)
+        .collect();
+    for key in unused {
+        shared_pages.remove(&key);
+    }
+}
+
 /// ### mmap 块管理器
-/// - `mmap_start` : 地址空间中mmap区块起始虚地址
-/// - `mmap_top` : 地址空间中mmap区块当结束虚地址
-/// - `mmap_set` : mmap块 向量
-#[derive(Clone,Debug)]
+/// - `mmap_set` : mmap块，以起始虚地址为键有序保存，块之间互不重叠
+///
+/// mmap 块的位置由 `MemorySet::find_free_range` 在整个地址空间中分配
+#[derive(Clone)]
 pub struct MmapArea {
-    pub mmap_start: VirtAddr,
-    pub mmap_top: VirtAddr,
-    pub mmap_set: Vec<MmapSpace>,
+    pub mmap_set: BTreeMap<usize, MmapSpace>,
 }
 
 impl MmapArea {
-    pub fn new( mmap_start: VirtAddr, mmap_top: VirtAddr ) -> Self {
+    pub fn new() -> Self {
         Self {
-            mmap_start,
-            mmap_top,
-            mmap_set: Vec::new(),
+            mmap_set: BTreeMap::new(),
         }
     }
 
-    pub fn get_mmap_top(&mut self) -> VirtAddr { self.mmap_top }
-
-    pub fn lazy_map_page(&mut self, va: VirtAddr, fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>, token: usize) {
-        for mmap_space in self.mmap_set.iter_mut() {
-            if va.0 >= mmap_space.oaddr.0 && va.0 < mmap_space.oaddr.0 + mmap_space.length {
+    /// 返回值：该页是否从文件中读入了内容（用于区分 major/minor 缺页）
+    pub fn lazy_map_page(&mut self, va: VirtAddr, fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>, token: usize) -> bool {
+        match self.mmap_set.range_mut(..=va.0).next_back() {
+            Some((_, mmap_space)) if va.0 < mmap_space.oaddr.0 + mmap_space.length => {
                 mmap_space.lazy_map_page(va, fd_table, token);
-                return 
+                mmap_space.fd >= 0
             }
+            _ => false,
         }
     }
 
+    /// ### 虚拟地址位于共享文件映射中时，返回映射的文件与该页在文件中的偏移
+    pub fn shared_file_page(&self, va: VirtAddr) -> Option<(Arc<dyn File + Send + Sync>, usize)> {
+        let page_start = VirtAddr::from(va.floor());
+        match self.mmap_set.range(..=va.0).next_back() {
+            Some((_, space)) if va.0 < space.oaddr.0 + space.length => {
+                let file = space.file.clone()?;
+                Some((file, space.offset + page_start.0 - space.oaddr.0))
+            }
+            _ => None,
+        }
+    }
+
+    /// ### 将 `[start, end)` 范围内共享文件映射中被写过的页面写回文件
+    /// - 只写回页表项 D 标志位为 1 的页面，写回后清除该标志位；不可写的映射不会被写回，
+    ///   以免用缓存中的旧页面覆盖其他进程通过 `write` 写入的内容
+    /// - 写回不会改变文件大小，超出文件末尾的部分被丢弃
+    /// - 除 `msync`/`munmap` 外，地址空间被最后一个使用它的任务（多线程进程中最后退出的线程）放弃时也会写回全部范围
+    pub fn sync_shared(&self, start: usize, end: usize, memory_set: &mut MemorySet) {
+        let spaces = self.mmap_set.range(..end).rev().take_while(|(_, space)| space.oaddr.0 + space.length > start);
+        for (_, space) in spaces {
+            if space.prot & MmapProts::PROT_WRITE.bits() == 0 {
+                continue;
+            }
+            let inode = match space.file.as_ref().and_then(|file| file.as_osinode()) {
+                Some(inode) => inode,
+                None => continue,
+            };
+            let space_end = space.oaddr.0 + space.length;
+            let start_vpn = VirtAddr::from(start.max(space.oaddr.0)).floor();
+            let end_vpn = VirtAddr::from(end.min(space_end)).ceil();
+            for vpn in (start_vpn.0..end_vpn.0).map(VirtPageNum) {
+                let ppn = match memory_set.take_dirty(vpn) {
+                    Some(ppn) => ppn,
+                    None => continue,
+                };
+                let page_start = VirtAddr::from(vpn).0;
+                let len = PAGE_SIZE.min(space_end - page_start);
+                inode.write_at(space.offset + page_start - space.oaddr.0, &ppn.get_bytes_array()[..len]);
+            }
+        }
+    }
+
+    /// `file` 仅在共享文件映射时给出，用于缺页时取得共享页面以及写回
     pub fn push(&mut self, start: usize, len: usize, prot: usize, flags: usize,
-                fd: isize, offset: usize, _fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>, _token: usize) -> usize {
+                fd: isize, offset: usize, file: Option<Arc<dyn File + Send + Sync>>) -> usize {
         
         let start_addr = start.into();
 
-        let mmap_space = MmapSpace::new(start_addr, len, prot, flags, 0, fd, offset);
-        // mmap_space.map_file(start_addr, PAGE_SIZE, offset, _fd_table, _token);
+        let mut mmap_space = MmapSpace::new(start_addr, len, prot, flags, 0, fd, offset);
+        mmap_space.file = file;
 
-        self.mmap_set.push(mmap_space);
-
-        // update mmap_top
-        if self.mmap_top == start_addr{
-            self.mmap_top = (start_addr.0 + len).into();
-        }
+        self.mmap_set.insert(start, mmap_space);
 
         start_addr.0
     }
 
-    pub fn remove(&mut self, start: usize, len: usize) -> isize {
-        let pair = self.mmap_set.iter().enumerate()
-            .find(|(_, p)| { p.oaddr.0 == start });
-        if let Some((idx, _)) = pair {
-            if self.mmap_top == VirtAddr::from(start + len) {
-                self.mmap_top = VirtAddr::from(start);
+    /// ### 删除 `[start, start + len)` 范围内的 mmap 块记录
+    /// 完全位于范围内的块被删除，部分位于范围内的块被截短或一分为二，文件偏移随之调整
+    pub fn remove(&mut self, start: usize, len: usize) {
+        let end = start + len;
+        // 块之间互不重叠，结束地址随起始地址递增，从后向前找到所有与范围重叠的块
+        let overlapped: Vec<usize> = self
+            .mmap_set
+            .range(..end)
+            .rev()
+            .take_while(|(_, space)| space.oaddr.0 + space.length > start)
+            .map(|(&addr, _)| addr)
+            .collect();
+        for addr in overlapped {
+            let space = self.mmap_set.remove(&addr).unwrap();
+            let space_end = space.oaddr.0 + space.length;
+            if space.oaddr.0 < start {
+                let mut head = space.clone();
+                head.length = start - space.oaddr.0;
+                self.mmap_set.insert(head.oaddr.0, head);
+            }
+            if space_end > end {
+                let mut tail = space.clone();
+                tail.oaddr = VirtAddr::from(end);
+                tail.length = space_end - end;
+                tail.offset = space.offset + (end - space.oaddr.0);
+                self.mmap_set.insert(end, tail);
             }
-            self.mmap_set.remove(idx);
-            0
-        } else {
-            panic!{"No matched Mmap Space!"}
         }
     }
 
     #[allow(unused)]
     pub fn debug_show(&self) {
         println!("------------------MmapArea Layout-------------------");
-        println!("MmapArea: mmap_start: 0x{:x}  mmap_top: 0x{:x}", self.mmap_start.0, self.mmap_top.0);
-        for mmapspace in &self.mmap_set {
+        for mmapspace in self.mmap_set.values() {
             mmapspace.debug_show();
         }
         println!("----------------------------------------------------");
     }
 
-    pub fn reduce_mmap_range(&mut self, addr:usize, len:usize) {
-        for space in self.mmap_set.iter_mut() {
-            // 实际上不止这一种情况，todo
-            if addr == space.oaddr.0{
-                space.oaddr = VirtAddr::from(addr + len);
-                space.length = space.length - len;
-                if self.mmap_top.0 == space.oaddr.0 + space.length + len {
-                    self.mmap_top.0 -= len;
-                }
-                return;
-            }
-        }
-    }
-
 }
 
 /// ### mmap 块
@@ -143,13 +227,14 @@ impl MmapArea {
 /// |`flags`|映射方式|
 /// |`fd`|文件描述符|
 /// |`offset`|映射文件偏移地址|
+/// |`file`|共享文件映射所映射的文件，其他映射为 `None`|
 /// 
 /// - 成员函数
 ///     ```
 ///     pub fn new()
 ///     pub fn lazy_map_page()
 ///     ```
-#[derive(Clone, Copy,Debug)]
+#[derive(Clone)]
 pub struct MmapSpace {
     // pub addr: VirtAddr,
     pub oaddr: VirtAddr,
@@ -159,6 +244,7 @@ pub struct MmapSpace {
     pub flags: usize,
     pub fd: isize,
     pub offset: usize,
+    pub file: Option<Arc<dyn File + Send + Sync>>,
 }
 
 impl MmapSpace{
@@ -171,7 +257,7 @@ impl MmapSpace{
         fd: isize,
         offset: usize,
     ) -> Self {
-        Self {oaddr, length, prot, flags, valid, fd, offset}
+        Self {oaddr, length, prot, flags, valid, fd, offset, file: None}
     }
 
     pub fn new_len(&mut self, len:usize) {
@@ -1,3 +1,123 @@
-pub const ENOENT: isize = 2;
-pub const EFAULT: isize = 14;
-pub const EMFILE: isize = 24;
\ No newline at end of file
+#![allow(unused)]
+/// # 错误码
+/// `os/src/syscall/errno.rs`
+///
+/// 与 Linux `asm-generic/errno-base.h`、`asm-generic/errno.h` 保持一致，
+/// 系统调用出错时返回对应错误码的相反数
+//
+
+pub const EPERM:        isize = 1;  // 操作不允许
+pub const ENOENT:       isize = 2;  // 文件或目录不存在
+pub const ESRCH:        isize = 3;  // 进程不存在
+pub const EINTR:        isize = 4;  // 系统调用被信号中断
+pub const EIO:          isize = 5;  // I/O 错误
+pub const ENXIO:        isize = 6;  // 设备或地址不存在
+pub const E2BIG:        isize = 7;  // 参数列表过长
+pub const ENOEXEC:      isize = 8;  // 可执行文件格式错误
+pub const EBADF:        isize = 9;  // 文件描述符错误
+pub const ECHILD:       isize = 10; // 没有子进程
+pub const EAGAIN:       isize = 11; // 资源暂时不可用，请重试
+pub const ENOMEM:       isize = 12; // 内存不足
+pub const EACCES:       isize = 13; // 权限不足
+pub const EFAULT:       isize = 14; // 地址错误
+pub const ENOTBLK:      isize = 15; // 需要块设备
+pub const EBUSY:        isize = 16; // 设备或资源忙
+pub const EEXIST:       isize = 17; // 文件已存在
+pub const EXDEV:        isize = 18; // 跨设备链接
+pub const ENODEV:       isize = 19; // 设备不存在
+pub const ENOTDIR:      isize = 20; // 不是目录
+pub const EISDIR:       isize = 21; // 是目录
+pub const EINVAL:       isize = 22; // 参数不合法
+pub const ENFILE:       isize = 23; // 系统打开文件过多
+pub const EMFILE:       isize = 24; // 进程打开文件过多
+pub const ENOTTY:       isize = 25; // 不是终端设备
+pub const ETXTBSY:      isize = 26; // 文本文件忙
+pub const EFBIG:        isize = 27; // 文件过大
+pub const ENOSPC:       isize = 28; // 设备空间不足
+pub const ESPIPE:       isize = 29; // 非法 seek
+pub const EROFS:        isize = 30; // 只读文件系统
+pub const EMLINK:       isize = 31; // 链接过多
+pub const EPIPE:        isize = 32; // 管道破裂
+pub const EDOM:         isize = 33; // 数学参数超出定义域
+pub const ERANGE:       isize = 34; // 结果超出范围
+pub const EDEADLK:      isize = 35; // 会发生死锁
+pub const ENAMETOOLONG: isize = 36; // 文件名过长
+pub const ENOLCK:       isize = 37; // 没有可用的记录锁
+pub const ENOSYS:       isize = 38; // 系统调用未实现
+pub const ENOTEMPTY:    isize = 39; // 目录非空
+pub const ELOOP:        isize = 40; // 符号链接层数过多
+pub const EOVERFLOW:    isize = 75; // 数值溢出
+pub const ENOTSOCK:     isize = 88; // 不是套接字
+pub const EOPNOTSUPP:   isize = 95; // 操作不支持
+pub const ETIMEDOUT:    isize = 110;// 操作超时
+
+/// 内核内部使用：系统调用被信号打断，由 `trap_handler` 根据信号的 `SA_RESTART` 决定重新执行还是返回 `EINTR`，不会返回给用户
+pub const ERESTARTSYS:  isize = 512;
+
+/// 错误码类型，取值为上面的正数常量，返回给用户时取相反数
+pub type Errno = isize;
+
+/// ### 解包 `Result<T, Errno>`
+/// 出错时直接从所在的系统调用返回对应的负错误码
+macro_rules! try_errno {
+    ($e: expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(errno) => return -errno,
+        }
+    };
+}
+pub(crate) use try_errno;
+
+/// ### 错误码名称
+/// 供系统调用跟踪打印使用，未收录的错误码返回 `"E?"`
+pub fn errno_name(errno: Errno) -> &'static str {
+    match errno {
+        EPERM => "EPERM",
+        ENOENT => "ENOENT",
+        ESRCH => "ESRCH",
+        EINTR => "EINTR",
+        EIO => "EIO",
+        ENXIO => "ENXIO",
+        E2BIG => "E2BIG",
+        ENOEXEC => "ENOEXEC",
+        EBADF => "EBADF",
+        ECHILD => "ECHILD",
+        EAGAIN => "EAGAIN",
+        ENOMEM => "ENOMEM",
+        EACCES => "EACCES",
+        EFAULT => "EFAULT",
+        ENOTBLK => "ENOTBLK",
+        EBUSY => "EBUSY",
+        EEXIST => "EEXIST",
+        EXDEV => "EXDEV",
+        ENODEV => "ENODEV",
+        ENOTDIR => "ENOTDIR",
+        EISDIR => "EISDIR",
+        EINVAL => "EINVAL",
+        ENFILE => "ENFILE",
+        EMFILE => "EMFILE",
+        ENOTTY => "ENOTTY",
+        ETXTBSY => "ETXTBSY",
+        EFBIG => "EFBIG",
+        ENOSPC => "ENOSPC",
+        ESPIPE => "ESPIPE",
+        EROFS => "EROFS",
+        EMLINK => "EMLINK",
+        EPIPE => "EPIPE",
+        EDOM => "EDOM",
+        ERANGE => "ERANGE",
+        EDEADLK => "EDEADLK",
+        ENAMETOOLONG => "ENAMETOOLONG",
+        ENOLCK => "ENOLCK",
+        ENOSYS => "ENOSYS",
+        ENOTEMPTY => "ENOTEMPTY",
+        ELOOP => "ELOOP",
+        EOVERFLOW => "EOVERFLOW",
+        ENOTSOCK => "ENOTSOCK",
+        EOPNOTSUPP => "EOPNOTSUPP",
+        ETIMEDOUT => "ETIMEDOUT",
+        ERESTARTSYS => "ERESTARTSYS",
+        _ => "E?",
+    }
+}
@@ -1,8 +1,11 @@
 use super::errno::*;
-use crate::fs::{chdir, make_pipe, open, Dirent, FdSet, File, Kstat, OpenFlags, Statfs, Stdin, MNT_TABLE};
-use crate::mm::{translated_byte_buffer, translated_ref, translated_refmut, translated_str, UserBuffer, VirtAddr};
-use crate::task::{current_task, current_user_token, suspend_current_and_run_next, FD_LIMIT, RLIMIT_NOFILE};
-use crate::timer::{get_timeval, TimeVal, Timespec};
+use crate::fs::{
+    chdir, make_pipe, open, open_proc, sync_all, Dirent, FdSet, File, Kstat, OpenFlags, PollEvents, PollFd, SignalFd, Statfs, Stdin, CONSOLE_TTY,
+    MNT_TABLE, POLL_QUEUE,
+};
+use crate::mm::{copy_from_user, copy_str_from_user, copy_to_user, user_buffer, UserBuffer};
+use crate::task::{current_task, current_user_token, pgid2tasks, signal_pending, SignalFlags, FD_LIMIT, RLIMIT_NOFILE};
+use crate::timer::{get_time_ms, Timespec};
 use alloc::{sync::Arc, vec::Vec};
 use core::mem::size_of;
 
@@ -24,33 +27,35 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
     // if len != 8192 {
-    //     println!("buffer content:{:?}", UserBuffer::new(translated_byte_buffer(token, buf, len)));
+    //     println!("buffer content:{:?}", user_buffer(token, buf, len, false));
     // }
 
     // 文件描述符不合法
-    if fd >= inner.fd_table.len() {
-        warn!("[WARNING] sys_write: fd >= inner.fd_table.len, return -1");
-        return -1;
+    if fd >= fd_table.len() {
+        warn!("[WARNING] sys_write: fd >= fd_table.len, return -EBADF");
+        return -EBADF;
     }
-    if inner.memory_set.check_va_range(VirtAddr::from(buf as usize), len) == false {
-        return -EFAULT;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
         // 文件不可写
         if !file.writable() {
-            warn!("[WARNING] sys_write: file can't write, return -1");
-            return -1;
+            warn!("[WARNING] sys_write: file can't write, return -EBADF");
+            return -EBADF;
         }
         let file = file.clone();
+        drop(fd_table);
         drop(inner);
         drop(task); // 需要及时释放减少引用数
-        let write_size = file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize;
+        let write_size = file.write(try_errno!(user_buffer(token, buf, len, false))) as isize;
+        if write_size == 0 && len > 0 && file.broken_pipe() {
+            return -EPIPE;
+        }
         // debug!("[DEBUG] sys_write: return write_size: {}",write_size);
         write_size
     } else {
-        // warn!("[WARNING] sys_write: fd {} is none, return -1", fd);
-        -1
+        // warn!("[WARNING] sys_write: fd {} is none, return -EBADF", fd);
+        -EBADF
     }
 }
 
@@ -67,25 +72,27 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
     // 文件描述符不合法
-    if fd >= inner.fd_table.len() {
-        warn!("[WARNING] sys_read: fd >= inner.fd_table.len, return -1");
-        return -1;
+    if fd >= fd_table.len() {
+        warn!("[WARNING] sys_read: fd >= fd_table.len, return -EBADF");
+        return -EBADF;
     }
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
         // 文件不可读
         if !file.readable() {
-            warn!("[WARNING] sys_read: file can't read, return -1");
-            return -1;
+            warn!("[WARNING] sys_read: file can't read, return -EBADF");
+            return -EBADF;
         }
         let file = file.clone();
 
+        drop(fd_table);
         drop(inner); // 释放以避免死锁
         drop(task); // 需要及时释放减少引用数
 
         // 对 /dev/zero 的处理，暂时先加在这里
         if file.get_name() == "zero" {
-            let mut userbuffer = UserBuffer::new(translated_byte_buffer(token, buf, len));
+            let mut userbuffer = try_errno!(user_buffer(token, buf, len, true));
             let zero: Vec<u8> = (0..userbuffer.buffers.len()).map(|_| 0).collect();
             userbuffer.write(zero.as_slice());
             return userbuffer.buffers.len() as isize;
@@ -96,25 +103,54 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
             warn!("[WARNING] sys_read: file_size is zero!");
         }
         let len = file_size.min(len);
-        let readsize = file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize;
+        // signalfd 需要返回 -EAGAIN 等错误码，不能用读出 0 字节表示
+        if let Some(signalfd) = file.as_signalfd() {
+            return signalfd.read_signals(try_errno!(user_buffer(token, buf, len, true)));
+        }
+        let readsize = file.read(try_errno!(user_buffer(token, buf, len, true))) as isize;
+        // 终端在等待输入时被信号打断，没有读到字符不代表文件结束
+        if readsize == 0 && len > 0 && file.is_tty() && signal_pending() {
+            return -ERESTARTSYS;
+        }
         // println!("[DEBUG] sys_read: return readsize: {}",readsize);
         readsize
     } else {
-        warn!("[WARNING] sys_read: fd {} is none, return -1", fd);
-        -1
+        warn!("[WARNING] sys_read: fd {} is none, return -EBADF", fd);
+        -EBADF
     }
 }
 
 pub fn sys_openat(dirfd: isize, path: *const u8, flags: u32, mode: u32) -> isize {
     let task = current_task().unwrap();
     let token = current_user_token();
-    let mut inner = task.inner_exclusive_access();
-
-    let path = translated_str(token, path);
+    let path = try_errno!(copy_str_from_user(token, path));
 
     // todo
     _ = mode;
-    let oflags = OpenFlags::from_bits(flags).expect("[DEBUG] sys_openat: unsupported open flag!");
+    let oflags = match OpenFlags::from_bits(flags) {
+        Some(oflags) => oflags,
+        None => return -EINVAL,
+    };
+    // /proc 下的虚拟文件，打开时需要访问其他进程，不能持有当前进程的锁
+    if dirfd == AT_FDCWD || path.starts_with('/') {
+        let work_path = task.inner_exclusive_access().current_path.clone();
+        if let Some(proc_file) = open_proc(work_path.as_str(), path.as_str()) {
+            let proc_file = match proc_file {
+                Some(proc_file) => proc_file,
+                None => return -ENOENT,
+            };
+            let inner = task.inner_exclusive_access();
+            let mut fd_table = inner.fd_table.lock();
+            let fd = fd_table.alloc_fd();
+            if fd == FD_LIMIT {
+                return -EMFILE;
+            }
+            fd_table[fd] = Some(proc_file);
+            return fd as isize;
+        }
+    }
+    let inner = task.inner_exclusive_access();
+    let mut fd_table = inner.fd_table.lock();
     // info!(
     //     "[DEBUG] enter sys_openat: dirfd:{}, path:{}, flags:{:?}, mode:{:o}",
     //     dirfd, path, oflags, mode
@@ -122,40 +158,40 @@ pub fn sys_openat(dirfd: isize, path: *const u8, flags: u32, mode: u32) -> isize
     if dirfd == AT_FDCWD {
         // 如果是当前工作目录
         if let Some(inode) = open(inner.get_work_path(), path.as_str(), oflags) {
-            let fd = inner.alloc_fd();
+            let fd = fd_table.alloc_fd();
             if fd == FD_LIMIT {
                 return -EMFILE;
             }
-            inner.fd_table[fd] = Some(inode);
+            fd_table[fd] = Some(inode);
             // info!("[DEBUG] sys_openat return new fd:{}", fd);
             fd as isize
         } else {
-            // println!("[WARNING] sys_openat: can't open file:{}, return -1", path);
-            -1
+            // println!("[WARNING] sys_openat: can't open file:{}, return -ENOENT", path);
+            -ENOENT
         }
     } else {
         let dirfd = dirfd as usize;
         // dirfd 不合法
-        if dirfd >= inner.fd_table.len() && dirfd > FD_LIMIT {
-            return -1;
+        if dirfd >= fd_table.len() {
+            return -EBADF;
         }
-        if let Some(file) = &inner.fd_table[dirfd] {
+        if let Some(file) = &fd_table[dirfd] {
             if let Some(tar_f) = open(file.get_name(), path.as_str(), oflags) {
-                let fd = inner.alloc_fd();
+                let fd = fd_table.alloc_fd();
                 if fd == FD_LIMIT {
                     return -EMFILE;
                 }
-                inner.fd_table[fd] = Some(tar_f);
+                fd_table[fd] = Some(tar_f);
                 // info!("[DEBUG] sys_openat return new fd:{}", fd);
                 fd as isize
             } else {
-                warn!("[WARNING] sys_openat: can't open file:{}, return -1", path);
-                -1
+                warn!("[WARNING] sys_openat: can't open file:{}, return -ENOENT", path);
+                -ENOENT
             }
         } else {
             // dirfd 对应条目为 None
-            warn!("[WARNING] sys_read: fd {} is none, return -1", dirfd);
-            -1
+            warn!("[WARNING] sys_openat: fd {} is none, return -EBADF", dirfd);
+            -EBADF
         }
     }
 }
@@ -164,19 +200,20 @@ pub fn sys_openat(dirfd: isize, path: *const u8, flags: u32, mode: u32) -> isize
 /// - `fd`：文件描述符
 /// - 返回值
 ///     - 成功关闭：0
-///     - 失败：-1
+///     - 失败：-EBADF
 pub fn sys_close(fd: usize) -> isize {
     // println!("[DEBUG] enter sys_close: fd:{}",fd);
     let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let inner = task.inner_exclusive_access();
+    let mut fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() {
+        return -EBADF;
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+    if fd_table[fd].is_none() {
+        return -EBADF;
     }
     // 把 fd 对应的值取走，变为 None
-    inner.fd_table[fd].take();
+    fd_table[fd].take();
     // info!("[DEBUG] sys_close return 0");
     0
 }
@@ -189,54 +226,110 @@ pub fn sys_close(fd: usize) -> isize {
 pub fn sys_pipe(pipe: *mut u32, flag: usize) -> isize {
     let task = current_task().unwrap();
     let token = current_user_token();
-    let mut inner = task.inner_exclusive_access();
+    let inner = task.inner_exclusive_access();
+    let mut fd_table = inner.fd_table.lock();
 
     // todo
     _ = flag;
 
     let (pipe_read, pipe_write) = make_pipe();
-    let read_fd = inner.alloc_fd();
+    let read_fd = fd_table.alloc_fd();
     if read_fd == FD_LIMIT {
         return -EMFILE;
     }
 
-    inner.fd_table[read_fd] = Some(pipe_read);
-    let write_fd = inner.alloc_fd();
-    inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd as u32;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd as u32;
+    fd_table[read_fd] = Some(pipe_read);
+    let write_fd = fd_table.alloc_fd();
+    if write_fd == FD_LIMIT {
+        fd_table[read_fd].take();
+        return -EMFILE;
+    }
+    fd_table[write_fd] = Some(pipe_write);
+    drop(fd_table);
+    drop(inner);
+    if let Err(errno) = copy_to_user(token, pipe as *mut [u32; 2], &[read_fd as u32, write_fd as u32]) {
+        let inner = task.inner_exclusive_access();
+        let mut fd_table = inner.fd_table.lock();
+        fd_table[read_fd].take();
+        fd_table[write_fd].take();
+        return -errno;
+    }
     0
 }
 
+const SFD_NONBLOCK: usize = 0o4000;
+const SFD_CLOEXEC: usize = 0o2000000;
+
+/// ### 创建或修改 signalfd
+/// - 参数：
+///     - `fd`：为 -1 时创建新的 signalfd，否则修改已有的 signalfd 接收的信号
+///     - `mask`：通过 signalfd 接收的信号
+///     - `sizemask`：必须为 8
+///     - `flags`：`SFD_NONBLOCK`、`SFD_CLOEXEC`
+/// - 返回值：
+///     - 成功返回 signalfd 的文件描述符
+///     - `fd` 不是文件描述符返回 -EBADF，不是 signalfd 或参数非法返回 -EINVAL，文件描述符用尽返回 -EMFILE
+/// - syscall ID：74
+pub fn sys_signalfd4(fd: isize, mask: *const u64, sizemask: usize, flags: usize) -> isize {
+    if sizemask != size_of::<u64>() || flags & !(SFD_NONBLOCK | SFD_CLOEXEC) != 0 {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let mask = SignalFlags::from_bits_truncate(try_errno!(copy_from_user(token, mask)));
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let mut fd_table = inner.fd_table.lock();
+    if fd == -1 {
+        let new_fd = fd_table.alloc_fd();
+        if new_fd == FD_LIMIT {
+            return -EMFILE;
+        }
+        fd_table[new_fd] = Some(Arc::new(SignalFd::new(mask, flags & SFD_NONBLOCK != 0)));
+        return new_fd as isize;
+    }
+    let file = match fd_table.get(fd as usize) {
+        Some(Some(file)) => file,
+        _ => return -EBADF,
+    };
+    match file.as_signalfd() {
+        Some(signalfd) => {
+            signalfd.set_mask(mask);
+            fd
+        }
+        None => -EINVAL,
+    }
+}
+
 /// ### 将进程中一个已经打开的文件描述符复制一份并分配到一个新的文件描述符中。
 /// - 参数：fd 表示进程中一个已经打开的文件的文件描述符。
 /// - 返回值：
 ///     - 能够访问已打开文件的新文件描述符。
-///     - 如果出现了错误则返回 -1，可能的错误原因是：传入的 fd 并不对应一个合法的已打开文件。
+///     - 如果传入的 fd 并不对应一个合法的已打开文件，返回 -EBADF。
 /// - syscall ID：23
 
 pub fn sys_dup(fd: usize) -> isize {
     let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let inner = task.inner_exclusive_access();
+    let mut fd_table = inner.fd_table.lock();
 
     // 做资源检查，目前只检查 RLIMIT_NOFILE 这一种
     let rlim_max = inner.resource[RLIMIT_NOFILE].rlim_max;
-    if inner.fd_table.len() - 1 == rlim_max - 1 {
+    if fd_table.len() - 1 == rlim_max - 1 {
         return -EMFILE;
     }
 
     // 检查传入 fd 的合法性
-    if fd >= inner.fd_table.len() {
-        return -1;
+    if fd >= fd_table.len() {
+        return -EBADF;
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+    if fd_table[fd].is_none() {
+        return -EBADF;
     }
-    let new_fd = inner.alloc_fd();
-    if new_fd > FD_LIMIT {
-        return -1;
+    let new_fd = fd_table.alloc_fd();
+    if new_fd >= FD_LIMIT {
+        return -EMFILE;
     }
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
+    fd_table[new_fd] = Some(Arc::clone(fd_table[fd].as_ref().unwrap()));
     new_fd as isize
 }
 
@@ -246,41 +339,42 @@ pub fn sys_dup(fd: usize) -> isize {
 ///     - new_fd 表示进程中一个指定的文件描述符中。
 /// - 返回值：
 ///     - 能够访问已打开文件的新文件描述符。
-///     - 如果出现了错误则返回 -1，可能的错误原因是：
-///         - 传入的 old_fd 为空。
-///         - 传入的 old_fd 不存在
-///         - 传入的 new_fd 超出描述符数量限制 (典型值：128)
+///     - 如果出现了错误则返回错误码，可能的错误原因是：
+///         - 传入的 old_fd 为空或不存在：-EBADF
+///         - 传入的 new_fd 超出描述符数量限制 (典型值：128)：-EBADF
 /// - syscall ID：24
 pub fn sys_dup3(old_fd: usize, new_fd: usize) -> isize {
     let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let inner = task.inner_exclusive_access();
+    let mut fd_table = inner.fd_table.lock();
 
-    if old_fd >= inner.fd_table.len() || new_fd > FD_LIMIT {
-        return -1;
+    if old_fd >= fd_table.len() || new_fd > FD_LIMIT {
+        return -EBADF;
     }
-    if inner.fd_table[old_fd].is_none() {
-        return -1;
+    if fd_table[old_fd].is_none() {
+        return -EBADF;
     }
-    if new_fd >= inner.fd_table.len() {
-        for _ in inner.fd_table.len()..(new_fd + 1) {
-            inner.fd_table.push(None);
+    if new_fd >= fd_table.len() {
+        for _ in fd_table.len()..(new_fd + 1) {
+            fd_table.push(None);
         }
     }
 
     //let mut act_fd = new_fd;
-    //if inner.fd_table[new_fd].is_some() {
-    //    act_fd = inner.alloc_fd();
+    //if fd_table[new_fd].is_some() {
+    //    act_fd = fd_table.alloc_fd();
     //}
-    //let new_fd = inner.alloc_fd();
-    inner.fd_table[new_fd] = Some(inner.fd_table[old_fd].as_ref().unwrap().clone());
+    //let new_fd = fd_table.alloc_fd();
+    fd_table[new_fd] = Some(fd_table[old_fd].as_ref().unwrap().clone());
     new_fd as isize
 }
 
 pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
+    let path = try_errno!(copy_str_from_user(token, path));
     let inner = task.inner_exclusive_access();
-    let path = translated_str(token, path);
+    let fd_table = inner.fd_table.lock();
 
     // todo
     _ = mode;
@@ -289,22 +383,22 @@ pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> isize {
         if let Some(_) = open(inner.get_work_path(), path.as_str(), OpenFlags::O_DIRECTROY | OpenFlags::O_CREATE) {
             0
         } else {
-            -1
+            -ENOENT
         }
     } else {
         let dirfd = dirfd as usize;
-        if dirfd >= inner.fd_table.len() && dirfd > FD_LIMIT {
-            return -1;
+        if dirfd >= fd_table.len() {
+            return -EBADF;
         }
-        if let Some(file) = &inner.fd_table[dirfd] {
+        if let Some(file) = &fd_table[dirfd] {
             if let Some(_) = open(file.get_name(), path.as_str(), OpenFlags::O_DIRECTROY | OpenFlags::O_CREATE) {
                 0
             } else {
-                -1
+                -ENOENT
             }
         } else {
             // dirfd 对应条目为 None
-            -1
+            -EBADF
         }
     }
 }
@@ -313,25 +407,28 @@ pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> isize {
 pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    let cwd = task.inner_exclusive_access().current_path.clone();
 
     if buf as usize == 0 {
-        unimplemented!();
+        // 由 libc 负责在 buf 为 NULL 时分配缓冲区，内核只处理用户给定的缓冲区
+        -EFAULT
     } else {
-        let buf_vec = translated_byte_buffer(token, buf, len);
-        let mut userbuf = UserBuffer::new(buf_vec);
-        let cwd = inner.current_path.as_bytes();
+        if cwd.len() + 1 > len {
+            return -ERANGE;
+        }
+        let mut userbuf = try_errno!(user_buffer(token, buf, len, true));
+        let cwd = cwd.as_bytes();
         userbuf.write(cwd);
         userbuf.write_at(cwd.len(), &[0]); // 添加字符串末尾的\0
-        return buf as isize;
+        buf as isize
     }
 }
 
 pub fn sys_mount(special: *const u8, dir: *const u8, fstype: *const u8, flags: usize, data: *const u8) -> isize {
     let token = current_user_token();
-    let special = translated_str(token, special);
-    let dir = translated_str(token, dir);
-    let fstype = translated_str(token, fstype);
+    let special = try_errno!(copy_str_from_user(token, special));
+    let dir = try_errno!(copy_str_from_user(token, dir));
+    let fstype = try_errno!(copy_str_from_user(token, fstype));
 
     _ = data;
 
@@ -340,36 +437,50 @@ pub fn sys_mount(special: *const u8, dir: *const u8, fstype: *const u8, flags: u
 
 pub fn sys_umount(p_special: *const u8, flags: usize) -> isize {
     let token = current_user_token();
-    let special = translated_str(token, p_special);
+    let special = try_errno!(copy_str_from_user(token, p_special));
     MNT_TABLE.lock().umount(special, flags as u32)
 }
 
 pub fn sys_unlinkat(fd: isize, path: *const u8, flags: u32) -> isize {
     let task = current_task().unwrap();
     let token = current_user_token();
+    let path = try_errno!(copy_str_from_user(token, path));
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
     // todo
     _ = flags;
 
-    let path = translated_str(token, path);
     // println!("[DEBUG] enter sys_unlinkat: fd:{}, path:{}, flags:{}",fd,path,flags);
     if fd == AT_FDCWD {
         if let Some(file) = open(inner.get_work_path(), path.as_str(), OpenFlags::O_RDWR) {
             file.delete();
             0
         } else {
-            -1
+            -ENOENT
         }
     } else {
-        unimplemented!();
+        let fd = fd as usize;
+        if fd >= fd_table.len() {
+            return -EBADF;
+        }
+        if let Some(dir) = &fd_table[fd] {
+            if let Some(file) = open(dir.get_name(), path.as_str(), OpenFlags::O_RDWR) {
+                file.delete();
+                0
+            } else {
+                -ENOENT
+            }
+        } else {
+            -EBADF
+        }
     }
 }
 
 pub fn sys_chdir(path: *const u8) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
+    let path = try_errno!(copy_str_from_user(token, path));
     let mut inner = task.inner_exclusive_access();
-    let path = translated_str(token, path);
 
     // println!("[DEBUG] enter sys_chdir: path:{}",path);
 
@@ -377,7 +488,7 @@ pub fn sys_chdir(path: *const u8) -> isize {
         inner.current_path = new_cwd;
         0
     } else {
-        -1
+        -ENOENT
     }
 }
 
@@ -385,34 +496,51 @@ pub fn sys_fstat(fd: isize, buf: *mut u8) -> isize {
     // info!("[DEBUG] enter sys_fstat: fd:{}, buf:0x{:x}", fd, buf as usize);
     let token = current_user_token();
     let task = current_task().unwrap();
-    let buf_vec = translated_byte_buffer(token, buf, size_of::<Kstat>());
+    let mut userbuf = try_errno!(user_buffer(token, buf, size_of::<Kstat>(), true));
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
 
-    let mut userbuf = UserBuffer::new(buf_vec);
     let mut kstat = Kstat::new();
 
     let dirfd = fd as usize;
-    if dirfd >= inner.fd_table.len() && dirfd > FD_LIMIT {
-        return -1;
+    if dirfd >= fd_table.len() {
+        return -EBADF;
     }
-    if let Some(file) = &inner.fd_table[dirfd] {
+    if let Some(file) = &fd_table[dirfd] {
         file.get_fstat(&mut kstat);
         // println!("kstat:{:?}",kstat);
         userbuf.write(kstat.as_bytes());
         0
     } else {
-        -1
+        -EBADF
+    }
+}
+
+/// ### 将文件的修改写回磁盘
+/// 块缓存不区分文件，写回全部被修改过的块
+/// - 返回值：成功返回 0，`fd` 不是打开的文件描述符返回 -EBADF
+/// - syscall ID：82
+pub fn sys_fsync(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() || fd_table[fd].is_none() {
+        return -EBADF;
     }
+    drop(fd_table);
+    drop(inner);
+    sync_all();
+    0
 }
 
 pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> isize {
     // println!("[DEBUG] enter sys_getdents64: fd:{}, buf:{}, len:{}", fd, buf as usize, len);
     let token = current_user_token();
     let task = current_task().unwrap();
+    let mut userbuf = try_errno!(user_buffer(token, buf, len, true));
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
     let work_path = inner.current_path.clone();
-    let buf_vec = translated_byte_buffer(token, buf, len);
-    let mut userbuf = UserBuffer::new(buf_vec);
     let mut dirent = Dirent::new();
     let dent_len = size_of::<Dirent>();
     let mut total_len: usize = 0;
@@ -432,10 +560,13 @@ pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> isize {
             }
             return total_len as isize;
         } else {
-            return -1;
+            return -ENOENT;
         }
     } else {
-        if let Some(file) = &inner.fd_table[fd as usize] {
+        if fd < 0 || fd as usize >= fd_table.len() {
+            return -EBADF;
+        }
+        if let Some(file) = &fd_table[fd as usize] {
             loop {
                 if total_len + dent_len > len {
                     break;
@@ -449,7 +580,7 @@ pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> isize {
             }
             return total_len as isize;
         } else {
-            return -1;
+            return -EBADF;
         }
     }
 }
@@ -468,13 +599,17 @@ pub fn sys_lseek(fd: usize, off_t: usize, whence: usize) -> isize {
 
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
     // 文件描述符不合法
-    if fd >= inner.fd_table.len() {
-        return -1;
+    if fd >= fd_table.len() {
+        return -EBADF;
     }
 
-    if let Some(file) = &inner.fd_table[fd] {
-        let flag = SeekFlags::from_bits(whence).unwrap();
+    if let Some(file) = &fd_table[fd] {
+        let flag = match SeekFlags::from_bits(whence) {
+            Some(flag) => flag,
+            None => return -EINVAL,
+        };
         match flag {
             SeekFlags::SEEK_SET => {
                 file.set_offset(off_t);
@@ -491,39 +626,89 @@ pub fn sys_lseek(fd: usize, off_t: usize, whence: usize) -> isize {
                 (end + off_t) as isize
             }
             // flag wrong
-            _ => panic!("sys_lseek: unsupported whence!"),
+            _ => -EINVAL,
         }
     } else {
         // file not exists
-        -3
+        -EBADF
     }
 }
 
 // 暂时放在这里
 const TCGETS: usize = 0x5401;
 const TCSETS: usize = 0x5402;
+const TIOCSCTTY: usize = 0x540e;
 const TIOCGPGRP: usize = 0x540f;
 const TIOCSPGRP: usize = 0x5410;
 const TIOCGWINSZ: usize = 0x5413;
 const RTC_RD_TIME: usize = 0xffffffff80247009; // 这个值还需考量
 
+/// ### 设备控制
+/// 终端的进程组相关请求：
+/// - `TIOCGPGRP`：获取前台进程组号，终端不是当前进程的控制终端时返回 -ENOTTY
+/// - `TIOCSPGRP`：设置前台进程组，进程组必须属于当前进程的会话，否则返回 -EPERM；进程组不存在返回 -ESRCH
+/// - `TIOCSCTTY`：会话首进程将终端设为会话的控制终端，终端已属于其他会话时需要 `argp` 为 1 才能夺取
+/// - syscall ID：29
 pub fn sys_ioctl(fd: usize, request: usize, argp: *mut u8) -> isize {
     // println!("enter sys_ioctl: fd:{}, request:0x{:x}, argp:{}", fd, request, argp as usize);
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
     // 文件描述符不合法
-    if fd >= inner.fd_table.len() {
-        return -1;
+    if fd >= fd_table.len() || fd_table[fd].is_none() {
+        return -EBADF;
     }
+    let file = fd_table[fd].clone().unwrap();
+    drop(fd_table);
+    let (pgid, sid) = (inner.pgid, inner.sid);
+    drop(inner);
     match request {
+        TIOCSCTTY | TIOCGPGRP | TIOCSPGRP if !file.is_tty() => return -ENOTTY,
         TCGETS => {}
         TCSETS => {}
-        TIOCGPGRP => *translated_refmut(token, argp) = 0 as u8,
-        TIOCSPGRP => {}
-        TIOCGWINSZ => *translated_refmut(token, argp) = 0 as u8,
+        TIOCSCTTY => {
+            // 只有会话首进程可以获取控制终端
+            if sid != task.tgid {
+                return -EPERM;
+            }
+            let mut tty = CONSOLE_TTY.lock();
+            if !tty.is_controlling(sid) {
+                if tty.foreground().is_some() && argp as usize != 1 {
+                    return -EPERM;
+                }
+                tty.attach(sid, pgid);
+            }
+        }
+        TIOCGPGRP => {
+            let tty = CONSOLE_TTY.lock();
+            if !tty.is_controlling(sid) {
+                return -ENOTTY;
+            }
+            let pgrp = tty.foreground().unwrap() as i32;
+            drop(tty);
+            try_errno!(copy_to_user(token, argp as *mut i32, &pgrp));
+        }
+        TIOCSPGRP => {
+            let pgrp = try_errno!(copy_from_user(token, argp as *const i32));
+            if pgrp < 0 {
+                return -EINVAL;
+            }
+            if !CONSOLE_TTY.lock().is_controlling(sid) {
+                return -ENOTTY;
+            }
+            let members = pgid2tasks(pgrp as usize);
+            if members.is_empty() {
+                return -ESRCH;
+            }
+            if members.iter().any(|member| member.inner_exclusive_access().sid != sid) {
+                return -EPERM;
+            }
+            CONSOLE_TTY.lock().set_foreground(pgrp as usize);
+        }
+        TIOCGWINSZ => try_errno!(copy_to_user(token, argp, &0u8)),
         RTC_RD_TIME => {}
-        _ => panic!("sys_ioctl: unsupported request!"),
+        _ => return -ENOTTY,
     }
     0
 }
@@ -540,51 +725,52 @@ pub fn sys_writev(fd: usize, iovp: *const usize, iovcnt: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
     // 文件描述符不合法
-    if fd >= inner.fd_table.len() {
-        return -1;
+    if fd >= fd_table.len() {
+        return -EBADF;
     }
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
         // 文件不可写
         if !file.writable() {
-            return -1;
+            return -EBADF;
         }
-        let iovp_buf = translated_byte_buffer(token, iovp as *const u8, iovcnt * size_of::<Iovec>())
-            .pop()
-            .unwrap();
         let file = file.clone();
-        let mut addr = iovp_buf.as_ptr() as *const _ as usize;
         let mut total_write_len = 0;
+        drop(fd_table);
         drop(inner);
-        for _ in 0..iovcnt {
-            let iovp = unsafe { &*(addr as *const Iovec) };
-            total_write_len += file.write(UserBuffer::new(translated_byte_buffer(
-                token,
-                iovp.iov_base as *const u8,
-                iovp.iov_len,
-            )));
-            addr += size_of::<Iovec>();
+        for i in 0..iovcnt {
+            let iov: Iovec = try_errno!(copy_from_user(token, unsafe { (iovp as *const Iovec).add(i) }));
+            let write_len = file.write(try_errno!(user_buffer(token, iov.iov_base as *const u8, iov.iov_len, false)));
+            if write_len < iov.iov_len && file.broken_pipe() {
+                if total_write_len + write_len == 0 {
+                    return -EPIPE;
+                }
+                total_write_len += write_len;
+                break;
+            }
+            total_write_len += write_len;
         }
         total_write_len as isize
     } else {
-        -1
+        -EBADF
     }
 }
 
 pub fn sys_newfstatat(dirfd: isize, pathname: *const u8, satabuf: *const usize, _flags: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    let path = translated_str(token, pathname);
+    let path = try_errno!(copy_str_from_user(token, pathname));
 
     // println!(
     //     "[DEBUG] enter sys_newfstatat: dirfd:{}, pathname:{}, satabuf:0x{:x}, flags:0x{:x}",
     //     dirfd, path, satabuf as usize, _flags
     // );
 
-    let buf_vec = translated_byte_buffer(token, satabuf as *const u8, size_of::<Kstat>());
-    let mut userbuf = UserBuffer::new(buf_vec);
+    let mut userbuf = try_errno!(user_buffer(token, satabuf as *const u8, size_of::<Kstat>(), true));
     let mut kstat = Kstat::new();
+    let inner = task.inner_exclusive_access();
+    let fd_table = inner.fd_table.lock();
 
     if dirfd == AT_FDCWD {
         if let Some(inode) = open(inner.get_work_path(), path.as_str(), OpenFlags::O_RDONLY) {
@@ -597,19 +783,19 @@ pub fn sys_newfstatat(dirfd: isize, pathname: *const u8, satabuf: *const usize,
         }
     } else {
         let dirfd = dirfd as usize;
-        if dirfd >= inner.fd_table.len() && dirfd > FD_LIMIT {
-            return -1;
+        if dirfd >= fd_table.len() {
+            return -EBADF;
         }
-        if let Some(file) = &inner.fd_table[dirfd] {
+        if let Some(file) = &fd_table[dirfd] {
             if let Some(inode) = open(file.get_name(), path.as_str(), OpenFlags::O_RDONLY) {
                 inode.get_fstat(&mut kstat);
                 userbuf.write(kstat.as_bytes());
                 0
             } else {
-                -1
+                -ENOENT
             }
         } else {
-            -ENOENT
+            -EBADF
         }
     }
 }
@@ -621,37 +807,38 @@ pub fn sys_utimensat(dirfd: isize, pathname: *const u8, time: *const usize, flag
     // );
     let token = current_user_token();
     let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
 
     _ = flags;
 
     if dirfd == AT_FDCWD {
         if pathname as usize == 0 {
-            unimplemented!();
+            -EFAULT
         } else {
-            let pathname = translated_str(token, pathname);
+            let pathname = try_errno!(copy_str_from_user(token, pathname));
+            let inner = task.inner_exclusive_access();
             if let Some(_file) = open(inner.get_work_path(), pathname.as_str(), OpenFlags::O_RDWR) {
-                unimplemented!(); // 记得重新制作文件镜像
+                // todo: 按路径修改时间戳需要重新制作文件镜像，暂不支持
+                -EOPNOTSUPP
             } else {
                 -ENOENT
             }
         }
     } else {
         if pathname as usize == 0 {
-            if dirfd >= inner.fd_table.len() as isize || dirfd < 0 {
-                return 0;
+            let timespec: Timespec = try_errno!(copy_from_user(token, time as *const Timespec));
+            let inner = task.inner_exclusive_access();
+            let fd_table = inner.fd_table.lock();
+            if dirfd >= fd_table.len() as isize || dirfd < 0 {
+                return -EBADF;
             }
-            if let Some(file) = &inner.fd_table[dirfd as usize] {
-                let timespec_buf = translated_byte_buffer(token, time as *const u8, size_of::<Kstat>()).pop().unwrap();
-                let addr = timespec_buf.as_ptr() as *const _ as usize;
-                let timespec = unsafe { &*(addr as *const Timespec) };
-                file.set_time(timespec);
+            if let Some(file) = &fd_table[dirfd as usize] {
+                file.set_time(&timespec);
                 0
             } else {
-                -1
+                -EBADF
             }
         } else {
-            unimplemented!();
+            -EOPNOTSUPP
         }
     }
 }
@@ -660,33 +847,33 @@ pub fn sys_readv(fd: usize, iovp: *const usize, iovcnt: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() {
+        return -EBADF;
     }
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
         if !file.readable() {
-            return -1;
+            return -EBADF;
         }
-        let iovp_buf = translated_byte_buffer(token, iovp as *const u8, iovcnt * size_of::<Iovec>())
-            .pop()
-            .unwrap();
         let file = file.clone();
         let file_size = file.file_size();
         if file_size == 0 {
             warn!("[WARNING] sys_readv: file_size is zero!");
         }
-        let mut addr = iovp_buf.as_ptr() as *const _ as usize;
         let mut total_read_len = 0;
+        drop(fd_table);
         drop(inner);
-        for _ in 0..iovcnt {
-            let iovp = unsafe { &*(addr as *const Iovec) };
-            let len = file_size.min(iovp.iov_len);
-            total_read_len += file.read(UserBuffer::new(translated_byte_buffer(token, iovp.iov_base as *const u8, len)));
-            addr += size_of::<Iovec>();
+        for i in 0..iovcnt {
+            let iov: Iovec = try_errno!(copy_from_user(token, unsafe { (iovp as *const Iovec).add(i) }));
+            let len = file_size.min(iov.iov_len);
+            total_read_len += file.read(try_errno!(user_buffer(token, iov.iov_base as *const u8, len, true)));
+        }
+        if total_read_len == 0 && file.is_tty() && signal_pending() {
+            return -ERESTARTSYS;
         }
         total_read_len as isize
     } else {
-        -1
+        -EBADF
     }
 }
 
@@ -717,35 +904,42 @@ bitflags! {
 pub fn sys_fcntl(fd: isize, cmd: usize, arg: Option<usize>) -> isize {
     // println!("[DEBUG] enter sys_fcntl: fd:{}, cmd:{}, arg:{:?}", fd, cmd, arg);
     let task = current_task().unwrap();
-    let cmd = FcntlFlags::from_bits(cmd).unwrap();
+    let cmd = match FcntlFlags::from_bits(cmd) {
+        Some(cmd) => cmd,
+        None => return -EINVAL,
+    };
+    {
+        let inner = task.inner_exclusive_access();
+        let fd_table = inner.fd_table.lock();
+        if fd < 0 || fd as usize >= fd_table.len() || fd_table[fd as usize].is_none() {
+            return -EBADF;
+        }
+    }
     match cmd {
         FcntlFlags::F_SETFL => {
             let inner = task.inner_exclusive_access();
-            if let Some(file) = &inner.fd_table[fd as usize] {
-                file.set_flags(OpenFlags::from_bits(arg.unwrap() as u32).unwrap());
-            } else {
-                panic!("sys_fcntl: fd is not an open file descriptor");
+            let fd_table = inner.fd_table.lock();
+            if let Some(file) = &fd_table[fd as usize] {
+                file.set_flags(OpenFlags::from_bits_truncate(arg.unwrap() as u32));
             }
         }
         // Currently, only one such flag is defined: FD_CLOEXEC (value: 1)
         FcntlFlags::F_GETFD => {
             // Return (as the function result) the file descriptor flags; arg is ignored.
             let inner = task.inner_exclusive_access();
-            if let Some(file) = &inner.fd_table[fd as usize] {
+            let fd_table = inner.fd_table.lock();
+            if let Some(file) = &fd_table[fd as usize] {
                 return file.available() as isize;
-            } else {
-                panic!("sys_fcntl: fd is not an open file descriptor");
             }
         }
         FcntlFlags::F_SETFD => {
             // Set the file descriptor flags to the value specified by arg.
             let inner = task.inner_exclusive_access();
-            if let Some(file) = &inner.fd_table[fd as usize] {
+            let fd_table = inner.fd_table.lock();
+            if let Some(file) = &fd_table[fd as usize] {
                 if arg.unwrap() != 0 {
                     file.set_cloexec();
                 }
-            } else {
-                panic!("sys_fcntl: fd is not an open file descriptor");
             }
         }
         FcntlFlags::F_GETFL => {
@@ -754,14 +948,21 @@ pub fn sys_fcntl(fd: isize, cmd: usize, arg: Option<usize>) -> isize {
             return 04000;
         }
         FcntlFlags::F_DUPFD_CLOEXEC => {
-            let mut inner = task.inner_exclusive_access();
+            let inner = task.inner_exclusive_access();
+            let mut fd_table = inner.fd_table.lock();
             let start_num = arg.unwrap();
             let mut new_fd = 0;
             _ = new_fd;
-            let mut tmp_fd = Vec::new();
+            let mut tmp_fd: Vec<usize> = Vec::new();
             loop {
-                new_fd = inner.alloc_fd();
-                inner.fd_table[new_fd] = Some(Arc::new(Stdin));
+                new_fd = fd_table.alloc_fd();
+                if new_fd == FD_LIMIT {
+                    for i in tmp_fd {
+                        fd_table[i].take();
+                    }
+                    return -EMFILE;
+                }
+                fd_table[new_fd] = Some(Arc::new(Stdin));
                 if new_fd >= start_num {
                     break;
                 } else {
@@ -769,17 +970,13 @@ pub fn sys_fcntl(fd: isize, cmd: usize, arg: Option<usize>) -> isize {
                 }
             }
             for i in tmp_fd {
-                inner.fd_table[i].take();
+                fd_table[i].take();
             }
-            inner.fd_table[new_fd] = Some(Arc::clone(
-                inner.fd_table[fd as usize]
-                    .as_ref()
-                    .expect("sys_fcntl: fd is not an open file descriptor"),
-            ));
-            inner.fd_table[new_fd].as_ref().unwrap().set_cloexec();
+            fd_table[new_fd] = Some(Arc::clone(fd_table[fd as usize].as_ref().unwrap()));
+            fd_table[new_fd].as_ref().unwrap().set_cloexec();
             return new_fd as isize;
         }
-        _ => panic!("sys_ioctl: unsupported request!"),
+        _ => return -EINVAL,
     }
     0
 }
@@ -789,7 +986,7 @@ pub fn sys_statfs(path: *const u8, buf: *const u8) -> isize {
 
     _ = path;
 
-    let mut userbuf = UserBuffer::new(translated_byte_buffer(token, buf, size_of::<Statfs>()));
+    let mut userbuf = try_errno!(user_buffer(token, buf, size_of::<Statfs>(), true));
     userbuf.write(Statfs::new().as_bytes());
     0
 }
@@ -798,16 +995,22 @@ pub fn sys_pread64(fd: usize, buf: *const u8, count: usize, offset: usize) -> is
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
-    if let Some(file) = &inner.fd_table[fd] {
+    let fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() {
+        return -EBADF;
+    }
+    if let Some(file) = &fd_table[fd] {
         let file = file.clone();
+        drop(fd_table);
         drop(inner);
         let old_offset = file.get_offset();
         file.set_offset(offset);
-        let readsize = file.read(UserBuffer::new(translated_byte_buffer(token, buf, count))) as isize;
+        let userbuf = try_errno!(user_buffer(token, buf, count, true));
+        let readsize = file.read(userbuf) as isize;
         file.set_offset(old_offset);
         readsize
     } else {
-        -1
+        -EBADF
     }
 }
 
@@ -818,14 +1021,21 @@ pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: usize, _count: usize) -
     // );
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
-    let fd_table = inner.fd_table.clone();
+    let fd_table = inner.fd_table.lock().clone();
     drop(inner);
     let mut total_write_size = 0usize;
     if offset as usize != 0 {
-        unimplemented!();
+        // todo: 暂不支持指定偏移量
+        -EINVAL
     } else {
-        let in_file = fd_table[in_fd].as_ref().unwrap();
-        let out_file = fd_table[out_fd].as_ref().unwrap();
+        let in_file = match fd_table.get(in_fd) {
+            Some(Some(file)) if file.readable() => file,
+            _ => return -EBADF,
+        };
+        let out_file = match fd_table.get(out_fd) {
+            Some(Some(file)) if file.writable() => file,
+            _ => return -EBADF,
+        };
         let mut data_buffer;
         loop {
             data_buffer = in_file.read_kernel_space();
@@ -846,9 +1056,9 @@ pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: usize, _count: usize) -
 pub fn sys_renameat2(old_dirfd: isize, old_path: *const u8, new_dirfd: isize, new_path: *const u8, _flags: u32) -> isize {
     let task = current_task().unwrap();
     let token = current_user_token();
+    let old_path = try_errno!(copy_str_from_user(token, old_path));
+    let new_path = try_errno!(copy_str_from_user(token, new_path));
     let inner = task.inner_exclusive_access();
-    let old_path = translated_str(token, old_path);
-    let new_path = translated_str(token, new_path);
 
     // println!(
     //     "[DEBUG] enter sys_renameat2: old_dirfd:{}, old_path:{}, new_dirfd:{}, new_path:{}, flags:0x{:x}",
@@ -870,16 +1080,16 @@ pub fn sys_renameat2(old_dirfd: isize, old_path: *const u8, new_dirfd: isize, ne
                     old_file.delete();
                     0
                 } else {
-                    panic!("can't find new file");
+                    -ENOENT
                 }
             } else {
-                unimplemented!();
+                -EOPNOTSUPP
             }
         } else {
-            panic!("can't find old file");
+            -ENOENT
         }
     } else {
-        unimplemented!();
+        -EOPNOTSUPP
     }
 }
 
@@ -890,17 +1100,17 @@ pub fn sys_umask() -> isize {
 pub fn sys_readlinkat(dirfd: isize, pathname: *const u8, buf: *const u8, bufsiz: usize) -> isize {
     if dirfd == AT_FDCWD {
         let token = current_user_token();
-        let path = translated_str(token, pathname);
+        let path = try_errno!(copy_str_from_user(token, pathname));
         if path.as_str() != "/proc/self/exe" {
-            panic!("sys_readlinkat: pathname not support");
+            return -EINVAL;
         }
-        let mut userbuf = UserBuffer::new(translated_byte_buffer(token, buf, bufsiz));
+        let mut userbuf = try_errno!(user_buffer(token, buf, bufsiz, true));
         let procinfo = "/lmbench_all\0";
         userbuf.write(procinfo.as_bytes());
         let len = procinfo.len() - 1;
         return len as isize;
     } else {
-        panic!("sys_readlinkat: fd not support");
+        -EOPNOTSUPP
     }
 }
 
@@ -910,21 +1120,20 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
     let mut w_ready_count = 0;
     let mut e_ready_count = 0;
 
-    let mut timer_interval = TimeVal::new();
-    unsafe {
-        let sec = translated_ref(token, timeout);
-        let usec = translated_ref(token, timeout.add(1));
-        timer_interval.sec = *sec;
-        timer_interval.usec = *usec;
-    }
-    let timer = timer_interval + get_timeval();
+    // timeout 为 NULL 时一直等待
+    let expire_ms = if timeout as usize == 0 {
+        None
+    } else {
+        let timeout: Timespec = try_errno!(copy_from_user(token, timeout as *const Timespec));
+        Some(get_time_ms().saturating_add(timeout.to_ms_ceil()))
+    };
 
     let mut rfd_set = FdSet::new();
     let mut wfd_set = FdSet::new();
 
     let mut ubuf_rfds = {
         if readfds as usize != 0 {
-            UserBuffer::new(translated_byte_buffer(token, readfds, size_of::<FdSet>()))
+            try_errno!(user_buffer(token, readfds, size_of::<FdSet>(), true))
         } else {
             UserBuffer::empty()
         }
@@ -933,7 +1142,7 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
 
     let mut ubuf_wfds = {
         if writefds as usize != 0 {
-            UserBuffer::new(translated_byte_buffer(token, writefds, size_of::<FdSet>()))
+            try_errno!(user_buffer(token, writefds, size_of::<FdSet>(), true))
         } else {
             UserBuffer::empty()
         }
@@ -942,7 +1151,7 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
 
     let mut ubuf_efds = {
         if exceptfds as usize != 0 {
-            UserBuffer::new(translated_byte_buffer(token, exceptfds, size_of::<FdSet>()))
+            try_errno!(user_buffer(token, exceptfds, size_of::<FdSet>(), true))
         } else {
             UserBuffer::empty()
         }
@@ -962,12 +1171,12 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
         /* handle read fd set */
         let task = current_task().unwrap();
         let inner = task.inner_exclusive_access();
-        let fd_table = &inner.fd_table;
+        let fd_table = inner.fd_table.lock();
         if readfds as usize != 0 && !r_all_ready {
             if rfd_vec.len() == 0 {
                 rfd_vec = rfd_set.get_fd_vec();
                 if rfd_vec[rfd_vec.len() - 1] >= nfds {
-                    return -1; // invalid fd
+                    return -EBADF; // invalid fd
                 }
             }
 
@@ -976,8 +1185,8 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
                 if fd == 1024 {
                     continue;
                 }
-                if fd > fd_table.len() || fd_table[fd].is_none() {
-                    return -1; // invalid fd
+                if fd >= fd_table.len() || fd_table[fd].is_none() {
+                    return -EBADF; // invalid fd
                 }
                 let fdescript = fd_table[fd].as_ref().unwrap();
                 if fdescript.r_ready() {
@@ -1001,7 +1210,7 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
             if wfd_vec.len() == 0 {
                 wfd_vec = wfd_set.get_fd_vec();
                 if wfd_vec[wfd_vec.len() - 1] >= nfds {
-                    return -1; // invalid fd
+                    return -EBADF; // invalid fd
                 }
             }
 
@@ -1010,8 +1219,8 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
                 if fd == 1024 {
                     continue;
                 }
-                if fd > fd_table.len() || fd_table[fd].is_none() {
-                    return -1; // invalid fd
+                if fd >= fd_table.len() || fd_table[fd].is_none() {
+                    return -EBADF; // invalid fd
                 }
                 let fdescript = fd_table[fd].as_ref().unwrap();
                 if fdescript.w_ready() {
@@ -1044,13 +1253,19 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
         if r_has_nready || w_has_nready {
             r_has_nready = false;
             w_has_nready = false;
-            let time_remain = get_timeval() - timer;
-            if time_remain.is_zero() {
-                // not reach timer (now < timer)
+            let time_up = matches!(expire_ms, Some(expire_ms) if get_time_ms() >= expire_ms);
+            if !time_up {
+                // 管道、控制台等文件状态变化时会唤醒 POLL_QUEUE，此外只需在超时时醒来
                 drop(fd_table);
                 drop(inner);
                 drop(task);
-                suspend_current_and_run_next();
+                let interrupted = match expire_ms {
+                    Some(expire_ms) => POLL_QUEUE.wait_timeout(expire_ms),
+                    None => POLL_QUEUE.wait(),
+                };
+                if interrupted < 0 {
+                    return -EINTR;
+                }
             } else {
                 ubuf_rfds.write(rfd_set.as_bytes());
                 ubuf_wfds.write(wfd_set.as_bytes());
@@ -1063,3 +1278,97 @@ pub fn sys_pselect(nfds: usize, readfds: *mut u8, writefds: *mut u8, exceptfds:
     // println!("pselect return: r_ready_count:{}, w_ready_count:{}, e_ready_count:{}",r_ready_count,w_ready_count,e_ready_count);
     r_ready_count + w_ready_count + e_ready_count
 }
+
+/// ### 等待一组文件描述符中的任意一个就绪
+/// - 参数：
+///     - `fds`：用户空间 `struct pollfd` 数组，`fd` 为负数的项被忽略
+///     - `nfds`：数组的项数
+///     - `timeout`：最长等待时间，为 NULL 时一直等待
+///     - `sigmask`：非 NULL 时在等待期间替换当前任务的信号屏蔽字，返回用户态时恢复
+///     - `sigsetsize`：信号集的大小，必须为 8
+/// - 返回值：
+///     - 成功返回 `revents` 不为 0 的项数，超时返回 0；不存在的文件描述符得到 `POLLNVAL`
+///     - 等待期间被信号打断返回 -EINTR
+///     - `nfds` 超过文件描述符上限、时间或信号集大小非法返回 -EINVAL，地址非法返回 -EFAULT
+/// - syscall ID：73
+pub fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout: *const Timespec, sigmask: *const u64, sigsetsize: usize) -> isize {
+    if nfds > FD_LIMIT {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let expire_ms = if timeout.is_null() {
+        None
+    } else {
+        let timeout = try_errno!(copy_from_user(token, timeout));
+        if !timeout.is_valid() {
+            return -EINVAL;
+        }
+        Some(get_time_ms().saturating_add(timeout.to_ms_ceil()))
+    };
+    let mut pollfds = Vec::with_capacity(nfds);
+    for i in 0..nfds {
+        let pollfd = (fds as usize + i * size_of::<PollFd>()) as *const PollFd;
+        pollfds.push(try_errno!(copy_from_user(token, pollfd)));
+    }
+    if !sigmask.is_null() {
+        if sigsetsize != size_of::<u64>() {
+            return -EINVAL;
+        }
+        let mask = SignalFlags::from_bits_truncate(try_errno!(copy_from_user(token, sigmask)));
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        inner.saved_sigmask = Some(inner.sigmask);
+        inner.sigmask = mask - SignalFlags::unblockable();
+    }
+
+    loop {
+        let mut ready_count = 0;
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        let fd_table = inner.fd_table.lock();
+        for pollfd in pollfds.iter_mut() {
+            pollfd.revents = 0;
+            if pollfd.fd < 0 {
+                continue;
+            }
+            let fd = pollfd.fd as usize;
+            let revents = if fd >= fd_table.len() || fd_table[fd].is_none() {
+                PollEvents::POLLNVAL
+            } else {
+                let file = fd_table[fd].as_ref().unwrap();
+                let events = PollEvents::from_bits_truncate(pollfd.events);
+                let mut revents = PollEvents::empty();
+                if events.contains(PollEvents::POLLIN) && file.r_ready() {
+                    revents |= PollEvents::POLLIN;
+                }
+                if events.contains(PollEvents::POLLOUT) && file.w_ready() {
+                    revents |= PollEvents::POLLOUT;
+                }
+                revents
+            };
+            pollfd.revents = revents.bits();
+            if !revents.is_empty() {
+                ready_count += 1;
+            }
+        }
+        drop(fd_table);
+        drop(inner);
+        drop(task);
+
+        let time_up = matches!(expire_ms, Some(expire_ms) if get_time_ms() >= expire_ms);
+        if ready_count > 0 || time_up {
+            for (i, pollfd) in pollfds.iter().enumerate() {
+                try_errno!(copy_to_user(token, (fds as usize + i * size_of::<PollFd>()) as *mut PollFd, pollfd));
+            }
+            return ready_count;
+        }
+        // 与 sys_pselect 相同，文件状态变化时会唤醒 POLL_QUEUE
+        let interrupted = match expire_ms {
+            Some(expire_ms) => POLL_QUEUE.wait_timeout(expire_ms),
+            None => POLL_QUEUE.wait(),
+        };
+        if interrupted < 0 {
+            return -EINTR;
+        }
+    }
+}
@@ -1,5 +1,9 @@
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use lazy_static::*;
+use spin::Mutex;
+use crate::fs::PollFd;
+use crate::task::{ITimerSpec, ITimerVal, SigAction, SigEvent, SigInfo, SignalStack, SysInfo};
+use crate::timer::Timespec;
 
 const SYSCALL_GETCWD:   usize = 17;
 const SYSCALL_DUP:      usize = 23;
@@ -26,6 +30,7 @@ const SYSCALL_PREAD64:  usize = 67;
 const SYSCALL_SENDFILE: usize = 71;
 const SYSCALL_PSELECT6: usize = 72;
 const SYSCALL_PPOLL:    usize = 73;
+const SYSCALL_SIGNALFD4:usize = 74;
 const SYSCALL_READLINKAT:usize = 78;
 const SYSCALL_NEWFSTATAT:  usize = 79;
 const SYSCALL_FSTAT:    usize = 80;
@@ -36,19 +41,31 @@ const SYSCALL_EXIT_GROUP:     usize = 94;
 const SYSCALL_SET_TID_ADDRESS:usize = 96;
 const SYSCALL_FUTEX:    usize = 98;
 const SYSCALL_NANOSLEEP:usize = 101;
+const SYSCALL_GETITIMER:usize = 102;
 const SYSCALL_SETITIMER:usize = 103;
+const SYSCALL_TIMER_CREATE:usize = 107;
+const SYSCALL_TIMER_GETTIME:usize = 108;
+const SYSCALL_TIMER_GETOVERRUN:usize = 109;
+const SYSCALL_TIMER_SETTIME:usize = 110;
+const SYSCALL_TIMER_DELETE:usize = 111;
 const SYSCALL_CLOCK_GETTIME:usize = 113;
+const SYSCALL_CLOCK_NANOSLEEP:usize = 115;
 const SYSCALL_SYSLOG:   usize = 116;
 const SYSCALL_YIELD:    usize = 124;
 const SYSCALL_KILL:     usize = 129;
+const SYSCALL_TKILL:     usize = 130;
 const SYSCALL_TGKILL:    usize = 131;
 const SYSCALL_RT_SIGACTION: usize = 134;
 const SYSCALL_RT_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGALTSTACK: usize = 132;
+const SYSCALL_RT_SIGSUSPEND: usize = 133;
 const SYSCALL_RT_SIGTIMEDWAIT: usize = 137;
-const SYSCALL_RT_SIGRETURN: usize = 139;
+pub const SYSCALL_RT_SIGRETURN: usize = 139;
 const SYSCALL_TIMES:    usize = 153;
 const SYSCALL_SETPGID:  usize = 154;
 const SYSCALL_GETPGID:  usize = 155;
+const SYSCALL_GETSID:   usize = 156;
+const SYSCALL_SETSID:   usize = 157;
 const SYSCALL_UNAME:    usize = 160;
 const SYSCALL_GETRUSAGE:usize = 165;
 const SYSCALL_UMASK:    usize = 166;
@@ -77,7 +94,7 @@ const SYSCALL_MMAP:     usize = 222;
 const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_MSYNC:    usize = 227;
 const SYSCALL_MADVISE:  usize = 233;
-const SYSCALL_WAITPID:  usize = 260;
+const SYSCALL_WAIT4:    usize = 260;
 const SYSCALL_PRLIMIT64:usize = 261;
 const SYSCALL_RENAMEAT2: usize = 276;
 
@@ -85,12 +102,15 @@ mod fs;
 mod process;
 mod sigset;
 mod socket;
-mod errno;
+mod trace;
+pub mod errno;
 
+use errno::*;
 use fs::*;
 use process::*;
 use sigset::*;
 use socket::*;
+pub use trace::{trace_enter, trace_exit};
 
 
 /// 系统调用分发函数
@@ -120,30 +140,42 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         SYSCALL_PREAD64=>   sys_pread64(args[0], args[1] as *const u8, args[2],args[3]),
         SYSCALL_SENDFILE=>  sys_sendfile(args[0], args[1], args[2],args[3]),
         SYSCALL_PSELECT6=>  sys_pselect(args[0] as usize, args[1] as *mut u8, args[2] as *mut u8, args[3] as *mut u8, args[4] as *mut usize),
-        SYSCALL_PPOLL  =>   sys_ppoll(),
+        SYSCALL_PPOLL  =>   sys_ppoll(args[0] as *mut PollFd, args[1], args[2] as *const Timespec, args[3] as *const u64, args[4]),
+        SYSCALL_SIGNALFD4=> sys_signalfd4(args[0] as isize, args[1] as *const u64, args[2], args[3]),
         SYSCALL_READLINKAT =>sys_readlinkat(args[0] as isize,args[1] as *const u8 ,args[2] as *const u8,args[3]),
         SYSCALL_NEWFSTATAT=>sys_newfstatat(args[0] as isize, args[1] as *const u8,args[2] as *const usize,args[3]),
         SYSCALL_FSTAT=>     sys_fstat(args[0] as isize, args[1] as *mut u8),
-        SYSCALL_FSYNC=>     0,
+        SYSCALL_FSYNC=>     sys_fsync(args[0]),
         SYSCALL_UTIMENSAT=> sys_utimensat(args[0] as isize, args[1] as *const u8,args[2] as *const usize,args[3]),
         SYSCALL_EXIT =>     sys_exit(args[0] as i32),
         SYSCALL_EXIT_GROUP=>sys_exit_group(args[0] as i32),
-        SYSCALL_SET_TID_ADDRESS=>sys_set_tid_address(args[0] as *mut usize),
-        SYSCALL_FUTEX =>    sys_futex(),
-        SYSCALL_NANOSLEEP=> sys_nanosleep(args[0] as *const u8),
-        SYSCALL_SETITIMER=> 0,
+        SYSCALL_SET_TID_ADDRESS=>sys_set_tid_address(args[0] as *mut u32),
+        SYSCALL_FUTEX =>    sys_futex(args[0] as *const u32, args[1], args[2] as u32, args[3] as *const Timespec, args[4] as *const u32, args[5] as u32),
+        SYSCALL_NANOSLEEP=> sys_nanosleep(args[0] as *const Timespec, args[1] as *mut Timespec),
+        SYSCALL_GETITIMER=> sys_getitimer(args[0], args[1] as *mut ITimerVal),
+        SYSCALL_SETITIMER=> sys_setitimer(args[0], args[1] as *const ITimerVal, args[2] as *mut ITimerVal),
+        SYSCALL_TIMER_CREATE=> sys_timer_create(args[0], args[1] as *const SigEvent, args[2] as *mut i32),
+        SYSCALL_TIMER_GETTIME=> sys_timer_gettime(args[0], args[1] as *mut ITimerSpec),
+        SYSCALL_TIMER_GETOVERRUN=> sys_timer_getoverrun(args[0]),
+        SYSCALL_TIMER_SETTIME=> sys_timer_settime(args[0], args[1], args[2] as *const ITimerSpec, args[3] as *mut ITimerSpec),
+        SYSCALL_TIMER_DELETE=> sys_timer_delete(args[0]),
         SYSCALL_CLOCK_GETTIME=> sys_clock_gettime(args[0],args[1] as *mut u64),
-        SYSCALL_SYSLOG =>   0,
+        SYSCALL_CLOCK_NANOSLEEP=> sys_clock_nanosleep(args[0], args[1], args[2] as *const Timespec, args[3] as *mut Timespec),
         SYSCALL_YIELD =>    sys_yield(),
-        SYSCALL_KILL =>     sys_kill(args[0], args[1] as u32),
-        SYSCALL_TGKILL=>    0,
-        SYSCALL_RT_SIGACTION => sys_rt_sigaction(),
-        SYSCALL_RT_SIGPROCMASK=>sys_rt_sigprocmask(args[0] as i32,args[1] as *const usize,args[2] as *const usize,args[3]),
-        SYSCALL_RT_SIGTIMEDWAIT=>sys_rt_sigtimedwait(),
-        SYSCALL_RT_SIGRETURN => sys_rt_sigreturn(args[0] as *mut usize),
+        SYSCALL_KILL =>     sys_kill(args[0] as isize, args[1]),
+        SYSCALL_TKILL =>    sys_tkill(args[0] as isize, args[1]),
+        SYSCALL_TGKILL=>    sys_tgkill(args[0] as isize, args[1] as isize, args[2]),
+        SYSCALL_RT_SIGACTION => sys_rt_sigaction(args[0], args[1] as *const SigAction, args[2] as *mut SigAction, args[3]),
+        SYSCALL_RT_SIGPROCMASK=>sys_rt_sigprocmask(args[0] as i32,args[1] as *const u64,args[2] as *mut u64,args[3]),
+        SYSCALL_SIGALTSTACK=>sys_sigaltstack(args[0] as *const SignalStack, args[1] as *mut SignalStack),
+        SYSCALL_RT_SIGSUSPEND=>sys_rt_sigsuspend(args[0] as *const u64, args[1]),
+        SYSCALL_RT_SIGTIMEDWAIT=>sys_rt_sigtimedwait(args[0] as *const u64, args[1] as *mut SigInfo, args[2] as *const Timespec, args[3]),
+        SYSCALL_RT_SIGRETURN => sys_rt_sigreturn(),
         SYSCALL_TIMES =>    sys_times(args[0] as *const u8),
-        SYSCALL_SETPGID=>   sys_setpgid(),
-        SYSCALL_GETPGID =>  sys_getpgid(),
+        SYSCALL_SETPGID=>   sys_setpgid(args[0], args[1] as isize),
+        SYSCALL_GETPGID =>  sys_getpgid(args[0]),
+        SYSCALL_GETSID =>   sys_getsid(args[0]),
+        SYSCALL_SETSID =>   sys_setsid(),
         SYSCALL_UNAME =>    sys_uname(args[0] as *const u8),
         SYSCALL_GETRUSAGE=> sys_getrusage(args[0] as isize, args[1] as *mut u8),
         SYSCALL_UMASK =>    sys_umask(),
@@ -156,7 +188,7 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         SYSCALL_FORK =>     sys_fork(args[0], args[1], args[2], args[3], args[4]),
         SYSCALL_EXEC =>     sys_exec(args[0] as *const u8, args[1] as *const usize,args[2] as *const usize),
         SYSCALL_GETTID =>   sys_gettid(),
-        SYSCALL_SYSINFO=>   sys_sysinfo(),
+        SYSCALL_SYSINFO=>   sys_sysinfo(args[0] as *mut SysInfo),
         SYSCALL_SOCKET =>   sys_socket(),
         SYSCALL_BIND   =>   sys_bind(),
         SYSCALL_LISTEN =>   sys_listen(),
@@ -169,17 +201,36 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         SYSCALL_BRK =>      sys_brk(args[0]),
         SYSCALL_MMAP=>      sys_mmap(args[0], args[1], args[2], args[3], args[4] as isize, args[5]),
         SYSCALL_MUNMAP =>   sys_munmap(args[0], args[1]),
-        SYSCALL_MPROTECT=>  0,
-        SYSCALL_MSYNC=>     0,
+        SYSCALL_MPROTECT=>  sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_MSYNC=>     sys_msync(args[0], args[1], args[2]),
         SYSCALL_MADVISE=>   sys_madvise(args[0] as *const u8, args[1], args[2]),
-        SYSCALL_WAITPID =>  sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_WAIT4 =>  sys_wait4(args[0] as isize, args[1] as *mut i32, args[2], args[3] as *mut u8),
         SYSCALL_PRLIMIT64=> sys_prlimit64(args[0], args[1], args[2] as *const u8, args[3] as *const u8),
         SYSCALL_RENAMEAT2=> sys_renameat2(args[0] as isize, args[1] as *const u8,args[2] as isize, args[3] as *const u8, args[4] as u32
         ),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        _ => sys_unsupported(syscall_id),
     }
 }
 
+lazy_static! {
+    /// 已经报告过的未实现系统调用号，保证每个系统调用只打印一次
+    static ref UNSUPPORTED_SYSCALL: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+}
+
+/// ### 未实现的系统调用
+/// - 首次遇到某个系统调用号时打印其名称与编号，之后静默处理
+/// - 返回 `-ENOSYS`，由用户程序（如 musl）自行回退
+fn sys_unsupported(syscall_id: usize) -> isize {
+    if UNSUPPORTED_SYSCALL.lock().insert(syscall_id) {
+        warn!(
+            "[kernel] Unsupported syscall: {} (id = {})",
+            SYSCALL_NAME.get(&syscall_id).unwrap_or(&"unknown"),
+            syscall_id
+        );
+    }
+    -ENOSYS
+}
+
 lazy_static! {
     pub static ref SYSCALL_NAME: BTreeMap<usize,&'static str> = {
         let mut tmp = BTreeMap::new();
@@ -208,6 +259,7 @@ lazy_static! {
         tmp.insert(SYSCALL_SENDFILE, "sendfile");
         tmp.insert(SYSCALL_PSELECT6, "pselect6");
         tmp.insert(SYSCALL_PPOLL, "ppoll");
+        tmp.insert(SYSCALL_SIGNALFD4, "signalfd4");
         tmp.insert(SYSCALL_READLINKAT, "readlinkat");
         tmp.insert(SYSCALL_NEWFSTATAT, "newfstatat");
         tmp.insert(SYSCALL_FSTAT, "fstat");
@@ -218,19 +270,31 @@ lazy_static! {
         tmp.insert(SYSCALL_SET_TID_ADDRESS, "set_tid_address");
         tmp.insert(SYSCALL_FUTEX, "futex");
         tmp.insert(SYSCALL_NANOSLEEP, "nanosleep");
+        tmp.insert(SYSCALL_GETITIMER, "getitimer");
         tmp.insert(SYSCALL_SETITIMER, "setitimer");
+        tmp.insert(SYSCALL_TIMER_CREATE, "timer_create");
+        tmp.insert(SYSCALL_TIMER_GETTIME, "timer_gettime");
+        tmp.insert(SYSCALL_TIMER_GETOVERRUN, "timer_getoverrun");
+        tmp.insert(SYSCALL_TIMER_SETTIME, "timer_settime");
+        tmp.insert(SYSCALL_TIMER_DELETE, "timer_delete");
         tmp.insert(SYSCALL_CLOCK_GETTIME, "clock_gettime");
+        tmp.insert(SYSCALL_CLOCK_NANOSLEEP, "clock_nanosleep");
         tmp.insert(SYSCALL_SYSLOG, "syslog");
         tmp.insert(SYSCALL_YIELD, "yield");
         tmp.insert(SYSCALL_KILL, "kill");
+        tmp.insert(SYSCALL_TKILL, "tkill");
         tmp.insert(SYSCALL_TGKILL, "tgkill");
         tmp.insert(SYSCALL_RT_SIGACTION, "rt_sigaction");
         tmp.insert(SYSCALL_RT_SIGPROCMASK, "rt_sigprocmask");
+        tmp.insert(SYSCALL_SIGALTSTACK, "sigaltstack");
+        tmp.insert(SYSCALL_RT_SIGSUSPEND, "rt_sigsuspend");
         tmp.insert(SYSCALL_RT_SIGTIMEDWAIT, "rt_sigtimedwait");
         tmp.insert(SYSCALL_RT_SIGRETURN, "rt_sigreturn");
         tmp.insert(SYSCALL_TIMES, "times");
         tmp.insert(SYSCALL_SETPGID, "setpgid");
         tmp.insert(SYSCALL_GETPGID, "getpgid");
+        tmp.insert(SYSCALL_GETSID, "getsid");
+        tmp.insert(SYSCALL_SETSID, "setsid");
         tmp.insert(SYSCALL_UNAME, "uname");
         tmp.insert(SYSCALL_GETRUSAGE, "getrusage");
         tmp.insert(SYSCALL_UMASK, "umask");
@@ -259,7 +323,7 @@ lazy_static! {
         tmp.insert(SYSCALL_MPROTECT, "mprotect");
         tmp.insert(SYSCALL_MSYNC, "msync");
         tmp.insert(SYSCALL_MADVISE, "madvise");
-        tmp.insert(SYSCALL_WAITPID, "waitpid");
+        tmp.insert(SYSCALL_WAIT4, "wait4");
         tmp.insert(SYSCALL_PRLIMIT64, "prlimit64");
         tmp.insert(SYSCALL_RENAMEAT2, "renameat2");
         tmp
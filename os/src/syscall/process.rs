@@ -1,17 +1,19 @@
-use crate::config::CLOCK_FREQ;
+use super::errno::*;
+use crate::config::{CLOCK_FREQ, PAGE_SIZE, USER_SPACE_END};
 use crate::fs::{open, OpenFlags};
-use crate::mm::{translated_byte_buffer, translated_ref, translated_refmut, translated_str, UserBuffer, MmapProts, MmapFlags};
+use crate::mm::{copy_from_user, copy_str_from_user, copy_to_user, frame_stat, user_buffer, MmapFlags, MmapProts};
 use crate::task::{
-    add_task, current_task, current_user_token, exit_current_and_run_next, pid2task, suspend_current_and_run_next, RLimit, RUsage,
-    SignalFlags,
+    add_task, all_processes, arm_real_timer, current_task, current_user_token, exit_current_and_run_next, exit_group_and_run_next, pgid2tasks, pid2task, send_signal, send_signal_to_thread, signal_group,
+    suspend_current_and_run_next, tgid2tasks, CpuTimer, ITimerSpec, ITimerVal, RLimit, RUsage, RealTimer, NSIG, RealTimerSlot, SigEvent, SignalFlags, SysInfo,
+    TaskControlBlock, WaitQueue, INITPROC, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL, POSIX_TIMER_MAX, RESOURCE_KIND_NUMBER, SIGEV_NONE, SIGEV_SIGNAL,
+    SIGEV_THREAD_ID,
 };
-use crate::timer::{get_time, get_time_ms, get_timeval, tms, TimeVal, NSEC_PER_SEC};
+use crate::timer::{get_time, get_time_ms, get_time_us, get_timeval, tms, TimeVal, Timespec, MSEC_PER_SEC, NSEC_PER_SEC, TICKS_PER_SEC, USEC_PER_SEC};
 use alloc::{
     string::{String, ToString},
     sync::Arc,
     vec::Vec,
 };
-use core::mem::size_of;
 pub use crate::task::{CloneFlags, Utsname, UTSNAME};
 // use simple_fat32::{CACHEGET_NUM,CACHEHIT_NUM};
 
@@ -21,8 +23,9 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("Unreachable in sys_exit!");
 }
 
+/// 结束当前线程组的全部线程然后运行下一程序
 pub fn sys_exit_group(exit_code: i32) -> ! {
-    exit_current_and_run_next(exit_code);
+    exit_group_and_run_next(exit_code);
     panic!("Unreachable in sys_exit_group!");
 }
 /// ### 应用主动交出 CPU 所有权进入 Ready 状态并切换到其他应用
@@ -33,24 +36,277 @@ pub fn sys_yield() -> isize {
     0
 }
 
-/// ### sleep 给定时长（TimeVal格式）
-/// - 返回值：总是返回 0。
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+const CLOCK_BOOTTIME: usize = 7;
+const TIMER_ABSTIME: usize = 1;
+
+/// wait4 的 options
+const WNOHANG: usize = 1;
+const WUNTRACED: usize = 2;
+const WCONTINUED: usize = 8;
+/// 以下三个选项只影响线程（clone 出的非 SIGCHLD 子任务），线程不在子进程向量中，因此忽略
+const __WNOTHREAD: usize = 0x2000_0000;
+const __WALL: usize = 0x4000_0000;
+const __WCLONE: usize = 0x8000_0000;
+
+/// ### 阻塞当前任务直到 `expire_ms`（CPU 上电时间，毫秒）
+/// - 返回值：到期返回 0；被信号打断返回 -EINTR，`rem` 非空时写回剩余时间
+fn sleep_until(expire_ms: usize, rem: *mut Timespec) -> isize {
+    // 只有定时器（或信号）会唤醒该队列上的任务
+    let queue = WaitQueue::new();
+    while get_time_ms() < expire_ms {
+        if queue.wait_timeout(expire_ms) < 0 {
+            if !rem.is_null() {
+                let remain = Timespec::from_ms(expire_ms.saturating_sub(get_time_ms()));
+                try_errno!(copy_to_user(current_user_token(), rem, &remain));
+            }
+            return -EINTR;
+        }
+    }
+    0
+}
+
+/// ### sleep 给定时长
+/// - 参数：
+///     - `req`：睡眠时长
+///     - `rem`：非空时，被信号打断后写回剩余时长
+/// - 返回值：成功返回 0；时长非法返回 -EINVAL；被信号打断返回 -EINTR
 /// - syscall ID：101
-pub fn sys_nanosleep(buf: *const u8) -> isize {
-    let tic = get_time_ms();
+pub fn sys_nanosleep(req: *const Timespec, rem: *mut Timespec) -> isize {
+    let req = try_errno!(copy_from_user(current_user_token(), req));
+    if !req.is_valid() {
+        return -EINVAL;
+    }
+    sleep_until(get_time_ms().saturating_add(req.to_ms_ceil()), rem)
+}
+
+/// ### 按指定时钟 sleep
+/// - 参数：
+///     - `clock_id`：支持 `CLOCK_REALTIME`、`CLOCK_MONOTONIC`、`CLOCK_BOOTTIME`，内核中三者都是上电时间
+///     - `flags`：带有 `TIMER_ABSTIME` 时 `req` 为绝对时刻，否则为时长
+///     - `req`：睡眠时长或唤醒时刻
+///     - `rem`：非空且不是绝对时刻时，被信号打断后写回剩余时长
+/// - 返回值：成功返回 0；时钟或时长非法返回 -EINVAL；被信号打断返回 -EINTR
+/// - syscall ID：115
+pub fn sys_clock_nanosleep(clock_id: usize, flags: usize, req: *const Timespec, rem: *mut Timespec) -> isize {
+    if !matches!(clock_id, CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME) {
+        return -EINVAL;
+    }
+    let req = try_errno!(copy_from_user(current_user_token(), req));
+    if !req.is_valid() {
+        return -EINVAL;
+    }
+    if flags & TIMER_ABSTIME != 0 {
+        sleep_until(req.to_ms_ceil(), core::ptr::null_mut())
+    } else {
+        sleep_until(get_time_ms().saturating_add(req.to_ms_ceil()), rem)
+    }
+}
+
+/// ### 获取间隔定时器的当前值
+/// - 参数：
+///     - `which`：`ITIMER_REAL`、`ITIMER_VIRTUAL` 或 `ITIMER_PROF`
+///     - `curr_value`：写入剩余时间与间隔
+/// - 返回值：成功返回 0；`which` 非法返回 -EINVAL
+/// - syscall ID：102
+pub fn sys_getitimer(which: usize, curr_value: *mut ITimerVal) -> isize {
+    if which > ITIMER_PROF {
+        return -EINVAL;
+    }
+    let curr = current_task().unwrap().inner_exclusive_access().get_itimer(which);
+    try_errno!(copy_to_user(current_user_token(), curr_value, &curr));
+    0
+}
 
+/// ### 设置间隔定时器
+/// - 参数：
+///     - `which`：`ITIMER_REAL` 按墙上时间计时，到期发送 `SIGALRM`；
+///         `ITIMER_VIRTUAL` 按用户态时间计时，到期发送 `SIGVTALRM`；
+///         `ITIMER_PROF` 按用户态与内核态时间计时，到期发送 `SIGPROF`
+///     - `new_value`：`it_value` 为 0 时停止定时器，`it_interval` 为 0 时只触发一次
+///     - `old_value`：非空时写入原来的值
+/// - 返回值：成功返回 0；参数非法返回 -EINVAL
+/// - syscall ID：103
+///
+/// riscv64 没有 `alarm` 系统调用，libc 使用 `ITIMER_REAL` 实现
+pub fn sys_setitimer(which: usize, new_value: *const ITimerVal, old_value: *mut ITimerVal) -> isize {
+    if which > ITIMER_PROF {
+        return -EINVAL;
+    }
     let token = current_user_token();
-    let len_timeval = translated_ref(token, buf as *const TimeVal);
-    let len = len_timeval.sec * 1000 + len_timeval.usec / 1000;
-    loop {
-        let toc = get_time_ms();
-        if toc - tic >= len {
-            break;
+    let new_value = try_errno!(copy_from_user(token, new_value));
+    if !new_value.it_value.is_valid() || !new_value.it_interval.is_valid() {
+        return -EINVAL;
+    }
+    let value_us = new_value.it_value.to_us();
+    let interval_us = new_value.it_interval.to_us();
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let old = inner.get_itimer(which);
+    match which {
+        ITIMER_REAL => {
+            let expire_us = if value_us == 0 { 0 } else { get_time_us() + value_us };
+            inner.itimer_real.set(expire_us, interval_us);
         }
+        ITIMER_VIRTUAL => inner.itimer_virtual = CpuTimer { value_us, interval_us },
+        _ => inner.itimer_prof = CpuTimer { value_us, interval_us },
+    }
+    drop(inner);
+    if which == ITIMER_REAL {
+        arm_real_timer(&task, RealTimerSlot::ITimerReal);
+    }
+    if !old_value.is_null() {
+        try_errno!(copy_to_user(token, old_value, &old));
     }
     0
 }
 
+/// ### 创建 POSIX 定时器
+/// - 参数：
+///     - `clock_id`：支持 `CLOCK_REALTIME`、`CLOCK_MONOTONIC`、`CLOCK_BOOTTIME`
+///     - `sevp`：到期时的通知方式，支持 `SIGEV_SIGNAL`、`SIGEV_NONE`、`SIGEV_THREAD_ID`；为空时发送 `SIGALRM`
+///     - `timer_id`：写入新定时器的 ID
+/// - 返回值：成功返回 0；参数非法返回 -EINVAL；定时器数量达到上限返回 -EAGAIN
+/// - syscall ID：107
+pub fn sys_timer_create(clock_id: usize, sevp: *const SigEvent, timer_id: *mut i32) -> isize {
+    if !matches!(clock_id, CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME) {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let (signal, target_tid) = if sevp.is_null() {
+        (SignalFlags::SIGALRM, None)
+    } else {
+        let sev = try_errno!(copy_from_user(token, sevp));
+        if sev.sigev_notify == SIGEV_NONE {
+            (SignalFlags::empty(), None)
+        } else if sev.sigev_notify == SIGEV_SIGNAL || sev.sigev_notify == SIGEV_THREAD_ID {
+            if sev.sigev_signo <= 0 || sev.sigev_signo as usize >= NSIG {
+                return -EINVAL;
+            }
+            let signal = SignalFlags::from_signum(sev.sigev_signo as usize);
+            if sev.sigev_notify == SIGEV_THREAD_ID {
+                // 只能指定同一线程组中的线程
+                let tid = sev.sigev_notify_thread_id as usize;
+                match pid2task(tid) {
+                    Some(target) if target.tgid == task.tgid => (signal, Some(tid)),
+                    _ => return -EINVAL,
+                }
+            } else {
+                (signal, None)
+            }
+        } else {
+            return -EINVAL;
+        }
+    };
+    let mut inner = task.inner_exclusive_access();
+    let id = match inner.posix_timers.iter().position(|timer| timer.is_none()) {
+        Some(id) => id,
+        None if inner.posix_timers.len() < POSIX_TIMER_MAX => {
+            inner.posix_timers.push(None);
+            inner.posix_timers.len() - 1
+        }
+        None => return -EAGAIN,
+    };
+    inner.posix_timers[id] = Some(RealTimer::new(signal, target_tid));
+    drop(inner);
+    if let Err(errno) = copy_to_user(token, timer_id, &(id as i32)) {
+        task.inner_exclusive_access().posix_timers[id] = None;
+        return -errno;
+    }
+    0
+}
+
+/// ### 启动或停止 POSIX 定时器
+/// - 参数：
+///     - `timer_id`：`timer_create` 返回的定时器 ID
+///     - `flags`：带有 `TIMER_ABSTIME` 时 `it_value` 为绝对时刻
+///     - `new_value`：`it_value` 为 0 时停止定时器，`it_interval` 为 0 时只触发一次
+///     - `old_value`：非空时写入原来的值
+/// - 返回值：成功返回 0；定时器不存在或参数非法返回 -EINVAL
+/// - syscall ID：110
+pub fn sys_timer_settime(timer_id: usize, flags: usize, new_value: *const ITimerSpec, old_value: *mut ITimerSpec) -> isize {
+    let token = current_user_token();
+    let new_value = try_errno!(copy_from_user(token, new_value));
+    if !new_value.it_value.is_valid() || !new_value.it_interval.is_valid() {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let slot = RealTimerSlot::Posix(timer_id);
+    let timer = match inner.real_timer_mut(slot) {
+        Some(timer) => timer,
+        None => return -EINVAL,
+    };
+    let old = ITimerSpec {
+        it_interval: Timespec::from_us(timer.interval_us),
+        it_value: Timespec::from_us(timer.remaining_us()),
+    };
+    let value_us = new_value.it_value.to_us_ceil();
+    let expire_us = if value_us == 0 {
+        0
+    } else if flags & TIMER_ABSTIME != 0 {
+        value_us
+    } else {
+        get_time_us().saturating_add(value_us)
+    };
+    timer.set(expire_us, new_value.it_interval.to_us_ceil());
+    drop(inner);
+    arm_real_timer(&task, slot);
+    if !old_value.is_null() {
+        try_errno!(copy_to_user(token, old_value, &old));
+    }
+    0
+}
+
+/// ### 获取 POSIX 定时器的剩余时间与间隔
+/// - 返回值：成功返回 0；定时器不存在返回 -EINVAL
+/// - syscall ID：108
+pub fn sys_timer_gettime(timer_id: usize, curr_value: *mut ITimerSpec) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let curr = match inner.real_timer(RealTimerSlot::Posix(timer_id)) {
+        Some(timer) => ITimerSpec {
+            it_interval: Timespec::from_us(timer.interval_us),
+            it_value: Timespec::from_us(timer.remaining_us()),
+        },
+        None => return -EINVAL,
+    };
+    drop(inner);
+    try_errno!(copy_to_user(current_user_token(), curr_value, &curr));
+    0
+}
+
+/// ### 获取 POSIX 定时器上次到期时错过的次数
+/// - 返回值：定时器不存在返回 -EINVAL
+/// - syscall ID：109
+pub fn sys_timer_getoverrun(timer_id: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    match inner.real_timer(RealTimerSlot::Posix(timer_id)) {
+        Some(timer) => timer.overrun as isize,
+        None => -EINVAL,
+    }
+}
+
+/// ### 删除 POSIX 定时器
+/// - 返回值：成功返回 0；定时器不存在返回 -EINVAL
+/// - syscall ID：111
+pub fn sys_timer_delete(timer_id: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.posix_timers.get_mut(timer_id) {
+        Some(timer) if timer.is_some() => {
+            *timer = None;
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
 /// ### 获取CPU上电时间 秒+微秒
 /// syscall_id：169
 /// - 输入参数
@@ -60,67 +316,105 @@ pub fn sys_nanosleep(buf: *const u8) -> isize {
 /// - 返回值：正确执行返回 0，出现错误返回 -1。
 pub fn sys_gettimeofday(buf: *const u8) -> isize {
     let token = current_user_token();
-    let buffers = translated_byte_buffer(token, buf, core::mem::size_of::<TimeVal>());
-    let mut userbuf = UserBuffer::new(buffers);
-    userbuf.write(get_timeval().as_bytes());
+    try_errno!(copy_to_user(token, buf as *mut TimeVal, &get_timeval()));
     0
 }
 
+/// 微秒转换为时钟滴答数（`times` 使用的单位）
+fn us_to_clock_ticks(us: usize) -> isize {
+    (us / (USEC_PER_SEC / TICKS_PER_SEC)) as isize
+}
+
+/// ### 获取进程及其已回收子进程的 CPU 时间
+/// - 参数：
+///     - `buf`：用户空间 `struct tms` 的地址，为 0 时只返回当前时间
+/// - 返回值：CPU 上电以来的时钟滴答数
+/// - syscall ID：153
 pub fn sys_times(buf: *const u8) -> isize {
-    let sec = get_time_ms() as isize * 1000;
-    let token = current_user_token();
-    let buffers = translated_byte_buffer(token, buf, core::mem::size_of::<tms>());
-    let mut userbuf = UserBuffer::new(buffers);
-    userbuf.write(
-        tms {
-            tms_stime: sec,
-            tms_utime: sec,
-            tms_cstime: sec,
-            tms_cutime: sec,
-        }
-        .as_bytes(),
-    );
-    0
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.account_system_time();
+    drop(inner);
+    let usage = task.process_usage();
+    let inner = task.inner_exclusive_access();
+    let children_usage = inner.children_usage;
+    let token = inner.get_user_token();
+    drop(inner);
+
+    if buf as usize != 0 {
+        let mut userbuf = try_errno!(user_buffer(token, buf, core::mem::size_of::<tms>(), true));
+        userbuf.write(
+            tms {
+                tms_utime: us_to_clock_ticks(usage.utime_us),
+                tms_stime: us_to_clock_ticks(usage.stime_us),
+                tms_cutime: us_to_clock_ticks(children_usage.utime_us),
+                tms_cstime: us_to_clock_ticks(children_usage.stime_us),
+            }
+            .as_bytes(),
+        );
+    }
+    us_to_clock_ticks(get_time_us())
 }
 
 //  long clone(unsigned long flags, void *child_stack, int *ptid, int *ctid, unsigned long newtls);
 
-/// ### 当前进程 fork/clone 出来一个子进程。
+/// ### 当前进程 fork/clone 出来一个子进程或线程。
 /// - 参数：
-///     - `flags`:
-///     - `stack_ptr`
-///     - `ptid`
-///     - `ctid`
-///     - `newtls`
-/// - 返回值：对于子进程返回 0，对于当前进程则返回子进程的 PID 。
+///     - `flags`: 支持 `CLONE_VM`、`CLONE_FILES`、`CLONE_SIGHAND`、`CLONE_THREAD`，含义见 `TaskControlBlock::fork`；
+///         以及 `CLONE_SETTLS`、`CLONE_PARENT_SETTID`、`CLONE_CHILD_SETTID`、`CLONE_CHILD_CLEARTID`；
+///         当前任务不会等待 `CLONE_VFORK` 的子进程 exec 或退出，因此没有指定新栈时 `CLONE_VFORK` 退化为写时复制的 fork
+///     - `stack_ptr`：子任务的用户栈，为 0 时沿用当前任务的栈指针
+///     - `ptid`：`CLONE_PARENT_SETTID` 时在当前任务地址空间中写入子任务 TID 的位置
+///     - `ctid`：`CLONE_CHILD_SETTID` 时在子任务地址空间中写入其 TID 的位置，
+///         `CLONE_CHILD_CLEARTID` 时作为子任务的 `clear_child_tid`
+///     - `newtls`：`CLONE_SETTLS` 时子任务 `tp` 寄存器的值
+/// - 返回值：对于子任务返回 0，对于当前任务则返回子任务的 PID（线程则为 TID）。
+///     与 Linux 一致，`CLONE_THREAD` 必须同时带有 `CLONE_SIGHAND`，`CLONE_SIGHAND` 必须同时带有 `CLONE_VM`，否则返回 -EINVAL
 /// - syscall ID：220
-pub fn sys_fork(flags: usize, stack_ptr: usize, _ptid: usize, _ctid: usize, _newtls: usize) -> isize {
+pub fn sys_fork(flags: usize, stack_ptr: usize, ptid: usize, ctid: usize, newtls: usize) -> isize {
     // println!(
     //     "[DEBUG] enter sys_fork: flags:{}, stack_ptr:{}, ptid:{}, ctid:{}, newtls:{}",
-    //     flags, stack_ptr, _ptid, _ctid, _newtls
+    //     flags, stack_ptr, ptid, ctid, newtls
     // );
+    let flags = match CloneFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    if flags.contains(CloneFlags::CLONE_THREAD) && !flags.contains(CloneFlags::CLONE_SIGHAND) {
+        return -EINVAL;
+    }
+    if flags.contains(CloneFlags::CLONE_SIGHAND) && !flags.contains(CloneFlags::CLONE_VM) {
+        return -EINVAL;
+    }
+    // musl 的 vfork 不指定新栈，父子进程若共享地址空间就会同时使用同一个用户栈
+    let flags = if flags.contains(CloneFlags::CLONE_VFORK) && stack_ptr == 0 && !flags.contains(CloneFlags::CLONE_THREAD) {
+        flags - CloneFlags::CLONE_VM
+    } else {
+        flags
+    };
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork(false);
-
-    // let tid = new_task.getpid();
-    let flags = CloneFlags::from_bits(flags).unwrap();
-    _ = flags;
-    // if flags.contains(CloneFlags::CLONE_CHILD_SETTID) && ctid != 0{
-    //     new_task.inner_exclusive_access().address.set_child_tid = ctid;
-    //     *translated_refmut(new_task.inner_exclusive_access().get_user_token(), ctid as *mut i32) = tid  as i32;
-    // }
-    // if flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) && ctid != 0{
-    //     new_task.inner_exclusive_access().address.clear_child_tid = ctid;
-    // }
-    // if !flags.contains(CloneFlags::SIGCHLD) {
-    //     panic!("sys_fork: FLAG not supported!");
-    // }
+    let new_task = current_task.fork(flags);
+    let new_pid = new_task.pid.0;
+
+    // 与 Linux 一致，写入 TID 失败时静默忽略
+    if flags.contains(CloneFlags::CLONE_PARENT_SETTID) && ptid != 0 {
+        let _ = copy_to_user(current_user_token(), ptid as *mut u32, &(new_pid as u32));
+    }
+    if flags.contains(CloneFlags::CLONE_CHILD_SETTID) && ctid != 0 {
+        new_task.write_user_u32(ctid, new_pid as u32);
+    }
+    if flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+        new_task.inner_exclusive_access().clear_child_tid = ctid;
+    }
 
     if stack_ptr != 0 {
         let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
         trap_cx.set_sp(stack_ptr);
     }
-    let new_pid = new_task.pid.0;
+    if flags.contains(CloneFlags::CLONE_SETTLS) {
+        let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
+        trap_cx.x[4] = newtls;
+    }
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
     // we do not have to move to next instruction since we have done it before
@@ -141,21 +435,21 @@ pub fn sys_fork(flags: usize, stack_ptr: usize, _ptid: usize, _ctid: usize, _new
 ///     - `path` 给出了要加载的可执行文件的名字
 ///     - `args` 数组中的每个元素都是一个命令行参数字符串的起始地址，以地址为0表示参数尾
 ///     - 'envs' 环境变量，暂未处理，直接加地址0结束
-/// - 返回值：如果出错的话（如找不到名字相符的可执行文件）则返回 -1，否则返回参数个数 `argc`。
+/// - 返回值：如果出错的话（如找不到名字相符的可执行文件）则返回 -ENOENT，否则返回参数个数 `argc`。
 /// - syscall ID：221
 pub fn sys_exec(path: *const u8, mut args: *const usize, mut _envs: *const usize) -> isize {
     let token = current_user_token();
     // 读取到用户空间的应用程序名称（路径）
-    let path = translated_str(token, path);
+    let path = try_errno!(copy_str_from_user(token, path));
     let mut args_vec: Vec<String> = Vec::new();
     if args as usize != 0 {
         loop {
-            let arg_str_ptr = *translated_ref(token, args);
+            let arg_str_ptr = try_errno!(copy_from_user(token, args));
             if arg_str_ptr == 0 {
                 // 读到下一参数地址为0表示参数结束
                 break;
             } // 否则从用户空间取出参数，压入向量
-            args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+            args_vec.push(try_errno!(copy_str_from_user(token, arg_str_ptr as *const u8)));
             unsafe {
                 args = args.add(1);
             }
@@ -180,7 +474,11 @@ pub fn sys_exec(path: *const u8, mut args: *const usize, mut _envs: *const usize
         for i in &args_vec {
             new_args.push(i.clone());
         }
-        task.exec(open("/", "busybox", OpenFlags::O_RDONLY).unwrap(), new_args, envs_vec);
+        let busybox = match open("/", "busybox", OpenFlags::O_RDONLY) {
+            Some(inode) => inode,
+            None => return -ENOENT,
+        };
+        task.exec(busybox, new_args, envs_vec);
         // memory_usage();
         return 0 as isize;
     }
@@ -192,84 +490,218 @@ pub fn sys_exec(path: *const u8, mut args: *const usize, mut _envs: *const usize
         // memory_usage();
         0 as isize
     } else {
-        -1
+        -ENOENT
     }
 }
 
 /// ### 当前进程等待一个子进程变为僵尸进程，回收其全部资源并收集其返回值。
 /// - 参数：
-///     - pid 表示要等待的子进程的进程 ID，如果为 -1 的话表示等待任意一个子进程；
-///     - exit_code 表示保存子进程返回值的地址，如果这个地址为 0 的话表示不必保存。
+///     - `pid`：要等待的子进程
+///         - 大于 0 表示进程 ID 为 `pid` 的子进程
+///         - -1 表示任意一个子进程
+///         - 0 表示与当前进程同一进程组的子进程
+///         - 小于 -1 表示进程组号为 `-pid` 的子进程
+///     - `wstatus`：保存子进程状态字的地址，为 0 表示不必保存。
+///     - `options`：
+///         - `WNOHANG`：没有可报告的子进程时立即返回 0
+///         - `WUNTRACED`：同时报告停止的子进程
+///         - `WCONTINUED`：同时报告被 SIGCONT 恢复的子进程
+///     - `rusage`：保存子进程资源使用情况的地址，为 0 表示不必保存。
 /// - 返回值：
-///     - 如果要等待的子进程不存在则返回 -1；
-///     - 否则如果要等待的子进程均未结束则返回，则放权等待；
+///     - `options` 非法返回 -EINVAL；
+///     - 如果要等待的子进程不存在则返回 -ECHILD；
+///     - 否则如果要等待的子进程均未结束，指定了 `WNOHANG` 时返回 0，
+///       否则阻塞等待，等待中被信号打断时返回 -ERESTARTSYS（视 `SA_RESTART` 重新执行或返回 -EINTR）；
 ///     - 否则返回结束的子进程的进程 ID。
 /// - syscall ID：260
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    // println!("[KERNEL] pid {} waitpid {}",current_task().unwrap().pid.0, pid);
-    // crate::task::debug_show_ready_queue();
-    let task = current_task().unwrap();
-    // ---- access current TCB exclusively
-    let inner = task.inner_exclusive_access();
-
-    // 根据pid参数查找有没有符合要求的进程
-    if !inner.children.iter().any(|p| pid == -1 || pid as usize == p.getpid()) {
-        return -1;
-        // ---- release current PCB
+pub fn sys_wait4(pid: isize, wstatus: *mut i32, options: usize, rusage: *mut u8) -> isize {
+    if options & !(WNOHANG | WUNTRACED | WCONTINUED | __WNOTHREAD | __WALL | __WCLONE) != 0 {
+        return -EINVAL;
     }
-    drop(inner);
+    let task = current_task().unwrap();
+    let pgid = task.inner_exclusive_access().pgid;
+    // 子进程是否符合 pid 参数的要求，调用时持有子进程的锁
+    let selected = |child_pid: usize, child_pgid: usize| match pid {
+        -1 => true,
+        0 => child_pgid == pgid,
+        pid if pid < -1 => child_pgid == (-pid) as usize,
+        pid => child_pid == pid as usize,
+    };
     loop {
         let mut inner = task.inner_exclusive_access();
-        // 查找所有符合PID要求的处于僵尸状态的进程，如果有的话还需要同时找出它在当前进程控制块子进程向量中的下标
-        let pair = inner.children.iter().enumerate().find(|(_, p)| {
+        // 查找所有符合要求的处于僵尸状态的进程，如果有的话还需要同时找出它在当前进程控制块子进程向量中的下标
+        let mut found_child = false;
+        let mut zombie = None;
+        for (idx, child) in inner.children.iter().enumerate() {
             // ++++ temporarily access child PCB lock exclusively
-            p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+            let child_inner = child.inner_exclusive_access();
+            if selected(child.getpid(), child_inner.pgid) {
+                found_child = true;
+                // 主线程退出后仍有其他线程运行时不能回收
+                if child_inner.group_exited() {
+                    zombie = Some(idx);
+                    break;
+                }
+            }
             // ++++ release child PCB
-        });
-        if let Some((idx, _)) = pair {
+        }
+        if !found_child {
+            return -ECHILD;
+        }
+        let (child, status) = if let Some(idx) = zombie {
             // 将子进程从向量中移除并置于当前上下文中
             let child = inner.children.remove(idx);
             // 确认这是对于该子进程控制块的唯一一次强引用，即它不会出现在某个进程的子进程向量中，
             // 更不会出现在处理器监控器或者任务管理器中。当它所在的代码块结束，这次引用变量的生命周期结束，
             // 将导致该子进程进程控制块的引用计数变为 0 ，彻底回收掉它占用的所有资源，
             // 包括：内核栈和它的 PID 还有它的应用地址空间存放页表的那些物理页帧等等
-            // println!("[KERNEL] pid {} waitpid {}",current_task().unwrap().pid.0, pid);
             assert_eq!(Arc::strong_count(&child), 1);
-            // 收集的子进程信息返回
-            let found_pid = child.getpid();
             // ++++ temporarily access child TCB exclusively
-            let exit_code = child.inner_exclusive_access().exit_code;
+            let child_inner = child.inner_exclusive_access();
+            let status = child_inner.exit_code;
+            // 子进程（包括其全部线程）及其已回收子进程的资源使用计入当前进程的 children_usage
+            inner.children_usage.add(&child_inner.thread_group.lock().exited_usage);
+            inner.children_usage.add(&child_inner.children_usage);
+            drop(child_inner);
             // ++++ release child PCB
-            // 将子进程的退出码写入到当前进程的应用地址空间中
-            if exit_code_ptr as usize != 0 {
-                *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code << 8;
-            }
-            return found_pid as isize;
+            (child, status)
         } else {
-            // 如果找不到的话则放权等待
-            drop(inner); // 手动释放 TaskControlBlock 全局可变部分
-            suspend_current_and_run_next();
+            // 没有僵尸子进程时，按 options 报告停止（WUNTRACED）或继续运行（WCONTINUED）的子进程，每次状态变化只报告一次
+            let mut report = None;
+            for child in inner.children.iter() {
+                let mut child_inner = child.inner_exclusive_access();
+                if !selected(child.getpid(), child_inner.pgid) {
+                    continue;
+                }
+                if options & WUNTRACED != 0 {
+                    if let Some(signo) = child_inner.wait_stopped.take() {
+                        report = Some((child.clone(), ((signo as i32) << 8) | 0x7f));
+                        break;
+                    }
+                }
+                if options & WCONTINUED != 0 && child_inner.wait_continued {
+                    child_inner.wait_continued = false;
+                    report = Some((child.clone(), 0xffff));
+                    break;
+                }
+            }
+            match report {
+                Some(report) => report,
+                None => {
+                    drop(inner); // 手动释放 TaskControlBlock 全局可变部分
+                    if options & WNOHANG != 0 {
+                        return 0;
+                    }
+                    // 在等待队列上阻塞，直到有子进程退出、停止或继续运行
+                    if task.child_exit.wait() < 0 {
+                        return -ERESTARTSYS;
+                    }
+                    continue;
+                }
+            }
+        };
+        let token = inner.get_user_token();
+        drop(inner);
+        // 将子进程的状态字与资源使用情况写入到当前进程的应用地址空间中
+        if wstatus as usize != 0 {
+            try_errno!(copy_to_user(token, wstatus, &status));
+        }
+        if rusage as usize != 0 {
+            let child_inner = child.inner_exclusive_access();
+            let mut child_usage = child_inner.thread_group.lock().exited_usage;
+            child_usage.add(&child_inner.children_usage);
+            drop(child_inner);
+            let mut userbuf = try_errno!(user_buffer(token, rusage, core::mem::size_of::<RUsage>(), true));
+            userbuf.write(RUsage::from_usage(&child_usage).as_bytes());
         }
-        // ---- release current PCB lock automatically
+        return child.getpid() as isize;
     }
 }
 
-pub fn sys_kill(pid: usize, signal: u32) -> isize {
+/// ### 向进程或进程组发送信号
+/// - 参数：
+///     - `pid`：
+///         - 大于 0 表示进程 ID 为 `pid` 的进程
+///         - 0 表示当前进程所在进程组中的全部进程
+///         - -1 表示除 initproc 与当前进程外的全部进程
+///         - 小于 -1 表示进程组 `-pid` 中的全部进程
+///     - `signal`：信号编号，为 0 时只检查目标进程是否存在
+/// - 返回值：成功返回 0，信号编号非法返回 -EINVAL，没有目标进程返回 -ESRCH
+/// - syscall ID：129
+///
+/// initproc 不会收到发给进程组的信号
+pub fn sys_kill(pid: isize, signal: usize) -> isize {
     // println!("[KERNEL] enter sys_kill: pid:{} send to pid:{}, signal:0x{:x}",current_task().unwrap().pid.0, pid, signal);
-    if signal == 0 {
-        return 0;
+    if signal >= NSIG {
+        return -EINVAL;
     }
-    let signal = 1 << signal;
-    if let Some(task) = pid2task(pid) {
-        if let Some(flag) = SignalFlags::from_bits(signal) {
-            task.inner_exclusive_access().signals |= flag;
-            0
-        } else {
-            panic!("[DEBUG] sys_kill: unsupported signal");
+    let signal = if signal == 0 { SignalFlags::empty() } else { SignalFlags::from_signum(signal) };
+    let found = match pid {
+        pid if pid > 0 => match pid2task(pid as usize) {
+            Some(task) => {
+                if !signal.is_empty() {
+                    send_signal(&task, signal);
+                }
+                true
+            }
+            None => false,
+        },
+        0 => {
+            let pgid = current_task().unwrap().inner_exclusive_access().pgid;
+            signal_group(pgid, signal)
         }
-    } else {
-        1
+        -1 => {
+            let tgid = current_task().unwrap().tgid;
+            let mut targets = all_processes();
+            targets.retain(|target| target.tgid != tgid && !Arc::ptr_eq(target, &INITPROC));
+            if !signal.is_empty() {
+                for target in targets.iter() {
+                    send_signal(target, signal);
+                }
+            }
+            !targets.is_empty()
+        }
+        pid => signal_group((-pid) as usize, signal),
+    };
+    if !found {
+        return -ESRCH;
+    }
+    0
+}
+
+/// ### 向线程发送信号
+/// - 参数：
+///     - `tgid`：线程所在的线程组，为 `None` 时（`tkill`）不检查
+///     - `tid`：目标线程
+///     - `signal`：信号编号，为 0 时只检查目标线程是否存在
+/// - 返回值：成功返回 0，参数非法返回 -EINVAL，线程不存在或不属于 `tgid` 返回 -ESRCH
+fn do_tkill(tgid: Option<isize>, tid: isize, signal: usize) -> isize {
+    if signal >= NSIG || tid <= 0 || tgid.map_or(false, |tgid| tgid <= 0) {
+        return -EINVAL;
+    }
+    let task = match pid2task(tid as usize) {
+        Some(task) => task,
+        None => return -ESRCH,
+    };
+    if tgid.map_or(false, |tgid| task.tgid != tgid as usize) {
+        return -ESRCH;
     }
+    if signal != 0 {
+        send_signal_to_thread(&task, SignalFlags::from_signum(signal));
+    }
+    0
+}
+
+/// ### 向线程 `tid` 发送信号，见 `do_tkill`
+/// - syscall ID：130
+pub fn sys_tkill(tid: isize, signal: usize) -> isize {
+    do_tkill(None, tid, signal)
+}
+
+/// ### 向线程组 `tgid` 中的线程 `tid` 发送信号，见 `do_tkill`
+/// - syscall ID：131
+pub fn sys_tgkill(tgid: isize, tid: isize, signal: usize) -> isize {
+    do_tkill(Some(tgid), tid, signal)
 }
 
 /// ### 获取系统utsname参数
@@ -281,7 +713,7 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
 pub fn sys_uname(buf: *const u8) -> isize {
     let token = current_user_token();
     let uname = UTSNAME.lock();
-    let mut userbuf = UserBuffer::new(translated_byte_buffer(token, buf, core::mem::size_of::<Utsname>()));
+    let mut userbuf = try_errno!(user_buffer(token, buf, core::mem::size_of::<Utsname>(), true));
     userbuf.write(uname.as_bytes());
     0
 }
@@ -289,25 +721,111 @@ pub fn sys_uname(buf: *const u8) -> isize {
 // 在进程虚拟地址空间中分配创建一片虚拟内存地址映射
 pub fn sys_mmap(addr: usize, length: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> isize {
     if length == 0 {
-        panic!("mmap:len == 0");
+        return -EINVAL;
+    }
+    let prot = match MmapProts::from_bits(prot) {
+        Some(prot) => prot,
+        None => return -EINVAL,
+    };
+    let flags = match MmapFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    // 长度超出用户地址空间时无论如何都放不下，也避免向上取整到页时溢出
+    if length > USER_SPACE_END {
+        return -ENOMEM;
+    }
+    // 没有 MAP_FIXED 时 addr 只是提示，未对齐时向下取整到页
+    if flags.contains(MmapFlags::MAP_FIXED) {
+        if addr % PAGE_SIZE != 0 {
+            return -EINVAL;
+        }
+        match addr.checked_add(length) {
+            Some(end) if end <= USER_SPACE_END => {}
+            _ => return -EINVAL,
+        }
     }
-    let prot = MmapProts::from_bits(prot).expect("unsupported mmap prot");
-    let flags = MmapFlags::from_bits(flags).expect("unsupported mmap flags");
 
     // info!("[KERNEL syscall] enter mmap: addr:0x{:x}, length:0x{:x}, prot:{:?}, flags:{:?},fd:{}, offset:0x{:x}", addr, length, prot, flags, fd, offset);
     
     let task = current_task().unwrap();
     let result_addr = task.mmap(addr, length, prot, flags, fd, offset);
     // info!("[DEBUG] sys_mmap return: 0x{:x}", result_addr);
-    return result_addr as isize;
+    return result_addr;
+}
+
+/// ### 修改一段虚拟地址范围的访问权限
+/// - 参数
+///     - `addr`：起始地址，必须页对齐
+///     - `length`：长度，向上取整到页
+///     - `prot`：新的访问权限
+/// - 返回值：地址未对齐或 `prot` 不合法时返回 -EINVAL，范围超出用户地址空间或包含未映射的页面时返回 -ENOMEM
+pub fn sys_mprotect(addr: usize, length: usize, prot: usize) -> isize {
+    if addr % PAGE_SIZE != 0 {
+        return -EINVAL;
+    }
+    let prot = match MmapProts::from_bits(prot) {
+        Some(prot) if !prot.intersects(MmapProts::PROT_GROWSDOWN | MmapProts::PROT_GROWSUP) => prot,
+        _ => return -EINVAL,
+    };
+    if length == 0 {
+        return 0;
+    }
+    match addr.checked_add(length) {
+        Some(end) if end <= USER_SPACE_END => {}
+        _ => return -ENOMEM,
+    }
+    let task = current_task().unwrap();
+    task.mprotect(addr, length, prot)
 }
 
+/// ### 解除一段虚拟地址范围内的映射
+/// - 返回值：地址未对齐、长度为 0 或范围超出用户地址空间时返回 -EINVAL，范围内没有映射时也返回 0
 pub fn sys_munmap(addr: usize, length: usize) -> isize {
+    if addr % PAGE_SIZE != 0 || length == 0 {
+        return -EINVAL;
+    }
+    match addr.checked_add(length) {
+        Some(end) if end <= USER_SPACE_END => {}
+        _ => return -EINVAL,
+    }
     let task = current_task().unwrap();
     let ret = task.munmap(addr, length);
     ret
 }
 
+/// 异步写回，本内核中写回总是同步完成
+const MS_ASYNC: usize = 1;
+/// 使其他映射失效，共享文件映射的页面本就由所有映射者共享，无需处理
+const MS_INVALIDATE: usize = 2;
+/// 同步写回
+const MS_SYNC: usize = 4;
+
+/// ### 将一段虚拟地址范围内共享文件映射的修改写回文件
+/// - 参数
+///     - `addr`：起始地址，必须页对齐
+///     - `length`：长度，向上取整到页
+///     - `flags`：`MS_ASYNC`、`MS_SYNC` 与 `MS_INVALIDATE` 的组合
+/// - 返回值：地址未对齐、`flags` 不合法或同时指定 `MS_ASYNC` 与 `MS_SYNC` 时返回 -EINVAL，
+///   范围超出用户地址空间或包含未映射的页面时返回 -ENOMEM
+pub fn sys_msync(addr: usize, length: usize, flags: usize) -> isize {
+    if addr % PAGE_SIZE != 0 || flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0 {
+        return -EINVAL;
+    }
+    if flags & MS_ASYNC != 0 && flags & MS_SYNC != 0 {
+        return -EINVAL;
+    }
+    if length == 0 {
+        return 0;
+    }
+    match addr.checked_add(length) {
+        Some(end) if end <= USER_SPACE_END => {}
+        _ => return -ENOMEM,
+    }
+    let task = current_task().unwrap();
+    task.msync(addr, length)
+}
+
 pub fn sys_sbrk(grow_size: isize, _is_shrink: usize) -> isize {
     let current_va = current_task().unwrap().grow_proc(grow_size) as isize;
     current_va
@@ -321,7 +839,7 @@ pub fn sys_brk(brk_addr: usize) -> isize {
         addr_new = sys_sbrk(0, 0) as usize;
     } else {
         let former_addr = current_task().unwrap().grow_proc(0);
-        let grow_size: isize = (brk_addr - former_addr) as isize;
+        let grow_size: isize = brk_addr as isize - former_addr as isize;
         addr_new = current_task().unwrap().grow_proc(grow_size);
     }
     // info!("[DEBUG] sys_brk return: 0x{:x}",addr_new);
@@ -331,24 +849,21 @@ pub fn sys_brk(brk_addr: usize) -> isize {
 pub fn sys_prlimit64(_pid: usize, resource: usize, new_limit: *const u8, old_limit: *const u8) -> isize {
     let token = current_user_token();
     // println!("[DEBUG] enter sys_prlimit64: pid:{},resource:{},new_limit:{},old_limit:{}",pid,resource,new_limit as usize,old_limit as usize);
+    if resource >= RESOURCE_KIND_NUMBER {
+        return -EINVAL;
+    }
     if old_limit as usize != 0 {
-        let mut buf = UserBuffer::new(translated_byte_buffer(token, old_limit as *const u8, size_of::<RLimit>()));
         let task = current_task().unwrap();
-        let inner = task.inner_exclusive_access();
-        let old_resource = inner.resource[resource];
-        let old_limit = RLimit {
+        let old_resource = task.inner_exclusive_access().resource[resource];
+        let old_limit_value = RLimit {
             rlim_cur: old_resource.rlim_cur,
             rlim_max: old_resource.rlim_max,
         };
-        buf.write(old_limit.as_bytes());
+        try_errno!(copy_to_user(token, old_limit as *mut RLimit, &old_limit_value));
     }
 
     if new_limit as usize != 0 {
-        let buf = translated_byte_buffer(token, new_limit as *const u8, size_of::<RLimit>())
-            .pop()
-            .unwrap();
-        let addr = buf.as_ptr() as *const _ as usize;
-        let new_limit = unsafe { &*(addr as *const RLimit) };
+        let new_limit: RLimit = try_errno!(copy_from_user(token, new_limit as *const RLimit));
         let task = current_task().unwrap();
         let mut inner = task.inner_exclusive_access();
         let rlimit = RLimit {
@@ -360,26 +875,51 @@ pub fn sys_prlimit64(_pid: usize, resource: usize, new_limit: *const u8, old_lim
     0
 }
 
-pub fn sys_clock_gettime(_clk_id: usize, ts: *mut u64) -> isize {
+/// ### 获取时钟的当前值
+/// - 参数：
+///     - `clk_id`：`CLOCK_PROCESS_CPUTIME_ID`/`CLOCK_THREAD_CPUTIME_ID` 返回进程 / 线程消耗的 CPU 时间，
+///         其余时钟都返回 CPU 上电时间
+///     - `ts`：用户空间 `Timespec` 的地址
+/// - syscall ID：113
+pub fn sys_clock_gettime(clk_id: usize, ts: *mut u64) -> isize {
     if ts as usize == 0 {
         return 0;
     }
     let token = current_user_token();
-    let ticks = get_time();
-    let sec = (ticks / CLOCK_FREQ) as u64;
-    let nsec = ((ticks % CLOCK_FREQ) * (NSEC_PER_SEC / CLOCK_FREQ)) as u64;
-    *translated_refmut(token, ts) = sec;
-    *translated_refmut(token, unsafe { ts.add(1) }) = nsec;
+    let (sec, nsec) = match clk_id {
+        CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => {
+            let task = current_task().unwrap();
+            task.inner_exclusive_access().account_system_time();
+            let cpu_time_us = if clk_id == CLOCK_THREAD_CPUTIME_ID {
+                task.inner_exclusive_access().usage.cpu_time_us()
+            } else {
+                task.process_usage().cpu_time_us()
+            };
+            let time = Timespec::from_us(cpu_time_us);
+            (time.tv_sec, time.tv_nsec)
+        }
+        _ => {
+            let ticks = get_time();
+            ((ticks / CLOCK_FREQ) as u64, ((ticks % CLOCK_FREQ) * (NSEC_PER_SEC / CLOCK_FREQ)) as u64)
+        }
+    };
+    try_errno!(copy_to_user(token, ts as *mut [u64; 2], &[sec, nsec]));
     0
 }
 
-/// 获取当前正在运行程序的 PID
+/// 获取当前进程的 PID，即线程组号 tgid
 pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid.0 as isize
+    current_task().unwrap().tgid as isize
 }
 
+/// 获取父进程的 PID，没有父进程（initproc）时返回 0
 pub fn sys_getppid() -> isize {
-    current_task().unwrap().tgid as isize
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    match inner.parent.as_ref().and_then(|parent| parent.upgrade()) {
+        Some(parent) => parent.tgid as isize,
+        None => 0,
+    }
 }
 
 pub fn sys_geteuid() -> isize {
@@ -390,27 +930,117 @@ pub fn sys_getegid() -> isize {
     0
 }
 
+/// 获取当前线程的 TID
 pub fn sys_gettid() -> isize {
-    0
+    current_task().unwrap().pid.0 as isize
 }
 
 pub fn sys_getuid() -> isize {
     0
 }
 
-pub fn sys_getpgid() -> isize {
-    0
+/// `pid` 为 0 时表示当前任务，否则按 pid 查找任务
+fn pid_or_current(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    match pid {
+        0 => current_task(),
+        pid => pid2task(pid),
+    }
 }
 
-pub fn sys_setpgid() -> isize {
+/// ### 获取进程 `pid` 的进程组号
+/// - 参数：`pid` 为 0 表示当前进程
+/// - 返回值：成功返回进程组号，进程不存在返回 -ESRCH
+/// - syscall ID：155
+pub fn sys_getpgid(pid: usize) -> isize {
+    match pid_or_current(pid) {
+        Some(task) => task.inner_exclusive_access().pgid as isize,
+        None => -ESRCH,
+    }
+}
+
+/// ### 设置进程 `pid` 的进程组
+/// - 参数：
+///     - `pid`：当前进程或其子进程，为 0 表示当前进程
+///     - `pgid`：加入的进程组，为 0 表示以 `pid` 为进程组号新建进程组
+/// - 返回值：
+///     - 成功返回 0
+///     - `pgid` 为负返回 -EINVAL
+///     - `pid` 既不是当前进程也不是其子进程返回 -ESRCH
+///     - 目标进程是会话首进程、与当前进程不在同一会话，或要加入的进程组不在当前会话中返回 -EPERM
+/// - syscall ID：154
+pub fn sys_setpgid(pid: usize, pgid: isize) -> isize {
+    if pgid < 0 {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let sid = task.inner_exclusive_access().sid;
+    let target = match pid_or_current(pid) {
+        Some(target) => target,
+        None => return -ESRCH,
+    };
+    let target_inner = target.inner_exclusive_access();
+    let is_child = target_inner.parent.as_ref().and_then(|parent| parent.upgrade()).map_or(false, |parent| parent.tgid == task.tgid);
+    if target.tgid != task.tgid && !is_child {
+        return -ESRCH;
+    }
+    // 会话首进程不能改变进程组，子进程必须与当前进程在同一会话中
+    if target_inner.sid == target.tgid || target_inner.sid != sid {
+        return -EPERM;
+    }
+    drop(target_inner);
+    let pgid = if pgid == 0 { target.tgid } else { pgid as usize };
+    if pgid != target.tgid && !pgid2tasks(pgid).iter().any(|member| member.inner_exclusive_access().sid == sid) {
+        return -EPERM;
+    }
+    for thread in tgid2tasks(target.tgid) {
+        thread.inner_exclusive_access().pgid = pgid;
+    }
     0
 }
 
-pub fn sys_ppoll() -> isize {
-    1
+/// ### 获取进程 `pid` 的会话号
+/// - 参数：`pid` 为 0 表示当前进程
+/// - 返回值：成功返回会话号，进程不存在返回 -ESRCH
+/// - syscall ID：156
+pub fn sys_getsid(pid: usize) -> isize {
+    match pid_or_current(pid) {
+        Some(task) => task.inner_exclusive_access().sid as isize,
+        None => -ESRCH,
+    }
+}
+
+/// ### 新建会话
+/// 当前进程成为新会话与新进程组的首进程，新会话没有控制终端
+/// - 返回值：成功返回新的会话号；当前进程已是进程组首进程时返回 -EPERM
+/// - syscall ID：157
+pub fn sys_setsid() -> isize {
+    let tgid = current_task().unwrap().tgid;
+    if !pgid2tasks(tgid).is_empty() {
+        return -EPERM;
+    }
+    for thread in tgid2tasks(tgid) {
+        let mut inner = thread.inner_exclusive_access();
+        inner.pgid = tgid;
+        inner.sid = tgid;
+    }
+    tgid as isize
 }
 
-pub fn sys_sysinfo() -> isize {
+/// ### 获取系统整体统计信息
+/// - 参数：`info`：用户空间 `struct sysinfo` 的地址
+/// - 返回值：成功返回 0，地址非法返回 -EFAULT
+/// - syscall ID：179
+pub fn sys_sysinfo(info: *mut SysInfo) -> isize {
+    let (total_pages, free_pages) = frame_stat();
+    let sysinfo = SysInfo {
+        uptime: (get_time_ms() / MSEC_PER_SEC) as isize,
+        totalram: total_pages,
+        freeram: free_pages,
+        procs: all_processes().len() as u16,
+        mem_unit: PAGE_SIZE as u32,
+        ..Default::default()
+    };
+    try_errno!(copy_to_user(current_user_token(), info, &sysinfo));
     0
 }
 
@@ -424,19 +1054,39 @@ pub fn sys_madvise(_addr: *const u8, _length: usize, _advice: usize) -> isize {
 }
 
 const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+const RUSAGE_THREAD: isize = 1;
+
+/// ### 获取资源使用统计
+/// - 参数：
+///     - `who`：`RUSAGE_SELF` 为当前进程全部线程之和，`RUSAGE_CHILDREN` 为已回收的子进程之和，
+///         `RUSAGE_THREAD` 为当前线程
+///     - `usage`：用户空间 `struct rusage` 的地址
+/// - 返回值：成功返回 0，`who` 非法返回 -EINVAL
+/// - syscall ID：165
 pub fn sys_getrusage(who: isize, usage: *mut u8) -> isize {
-    if who != RUSAGE_SELF {
-        panic!("sys_getrusage: \"who\" not supported!");
-    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().account_system_time();
+    let task_usage = match who {
+        RUSAGE_SELF => task.process_usage(),
+        RUSAGE_CHILDREN => task.inner_exclusive_access().children_usage,
+        RUSAGE_THREAD => task.inner_exclusive_access().usage,
+        _ => return -EINVAL,
+    };
     let token = current_user_token();
-    let mut userbuf = UserBuffer::new(translated_byte_buffer(token, usage, core::mem::size_of::<RUsage>()));
-    let rusage = RUsage::new();
+    let mut userbuf = try_errno!(user_buffer(token, usage, core::mem::size_of::<RUsage>(), true));
+    let rusage = RUsage::from_usage(&task_usage);
     userbuf.write(rusage.as_bytes());
     0
 }
 
-pub fn sys_set_tid_address(tidptr: *mut usize) -> isize {
-    let token = current_user_token();
-    *translated_refmut(token, tidptr) = 0 as usize;
-    0
+/// ### 设置当前任务的 `clear_child_tid`
+/// - 参数：
+///     - `tidptr`：任务退出时内核向该地址写入 0 并对其执行 futex 唤醒，`pthread_join` 依赖于此
+/// - 返回值：当前任务的 TID
+/// - syscall ID：96
+pub fn sys_set_tid_address(tidptr: *mut u32) -> isize {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().clear_child_tid = tidptr as usize;
+    task.pid.0 as isize
 }
@@ -1,45 +1,295 @@
-use crate::mm::{translated_byte_buffer, UserBuffer};
-use crate::task::{current_task, current_user_token, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK};
+use super::errno::*;
+use crate::mm::{copy_from_user, copy_to_user};
+use crate::task::{
+    current_add_signal, current_task, current_trap_cx, current_user_token, dequeue_signal, futex_key, futex_requeue,
+    futex_wait, futex_wake, restore_signal_frame, wait_for_signal, SigAction, SigInfo, SignalFlags,
+    SignalStack, FUTEX_BITSET_MATCH_ANY, MINSIGSTKSZ, NSIG, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, SS_DISABLE, SS_ONSTACK,
+};
+use crate::timer::{get_time_ms, Timespec};
 
-pub fn sys_rt_sigprocmask(how: i32, set: *const usize, oldset: *const usize, _sigsetsize: usize) -> isize {
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+const FUTEX_REQUEUE: usize = 3;
+const FUTEX_CMP_REQUEUE: usize = 4;
+const FUTEX_WAIT_BITSET: usize = 9;
+const FUTEX_WAKE_BITSET: usize = 10;
+const FUTEX_PRIVATE_FLAG: usize = 128;
+const FUTEX_CLOCK_REALTIME: usize = 256;
+
+/// ### 查询或修改信号屏蔽字
+/// - 参数：
+///     - `how`：`SIG_BLOCK` 屏蔽 `set` 中的信号，`SIG_UNBLOCK` 解除屏蔽，`SIG_SETMASK` 将屏蔽字设为 `set`
+///     - `set`：为 0 时只查询
+///     - `oldset`：非 0 时写入原来的屏蔽字
+///     - `sigsetsize`：必须为 8，即 64 个信号
+/// - 返回值：成功返回 0，参数非法返回 -EINVAL，地址非法返回 -EFAULT；`SIGKILL` 与 `SIGSTOP` 不能被屏蔽
+/// - syscall ID：135
+pub fn sys_rt_sigprocmask(how: i32, set: *const u64, oldset: *mut u64, sigsetsize: usize) -> isize {
+    if sigsetsize != core::mem::size_of::<u64>() {
+        return -EINVAL;
+    }
     let token = current_user_token();
     let task = current_task().unwrap();
-    // println!("enter sys_rt_sigprocmask!");
-    // println!("how:{},set:{:?},oldset:{:?}",how,set,oldset);
-    if oldset as usize != 0 {
-        let inner = task.inner_exclusive_access();
-        let buf_vec = translated_byte_buffer(token, oldset as *const u8, 128);
-        let mut userbuf = UserBuffer::new(buf_vec);
-        userbuf.write(inner.sigset.bits.as_slice());
-    }
-    if set as usize != 0 {
-        let mut buf_vec = translated_byte_buffer(token, set as *const u8, 128);
-        let buf = buf_vec.pop().unwrap();
-        let mut inner = task.inner_exclusive_access();
-        for (i, v) in inner.sigset.bits.iter_mut().enumerate() {
-            match how {
-                SIG_BLOCK => *v = *v | buf[i],
-                SIG_UNBLOCK => *v = *v & buf[i],
-                SIG_SETMASK => *v = buf[i],
-                _ => panic!("sys_rt_sigprocmask: unsupported how"),
-            }
+    let old_mask = task.inner_exclusive_access().sigmask;
+    let new_mask = if set.is_null() {
+        old_mask
+    } else {
+        let set = SignalFlags::from_bits_truncate(try_errno!(copy_from_user(token, set)));
+        match how {
+            SIG_BLOCK => old_mask | set,
+            SIG_UNBLOCK => old_mask - set,
+            SIG_SETMASK => set,
+            _ => return -EINVAL,
         }
+    };
+    if !oldset.is_null() {
+        try_errno!(copy_to_user(token, oldset, &old_mask.bits()));
     }
+    task.inner_exclusive_access().sigmask = new_mask - SignalFlags::unblockable();
     0
 }
 
-pub fn sys_rt_sigreturn(_setptr: *mut usize) -> isize {
-    0
+/// ### 同步地等待 `set` 中的信号
+/// - 参数：
+///     - `set`：等待的信号，通常应先被屏蔽
+///     - `info`：非 0 时写入收到的信号的信息
+///     - `timeout`：相对超时时间，为 0 时一直等待
+///     - `sigsetsize`：必须为 8
+/// - 返回值：
+///     - 收到的信号的编号，该信号从待处理信号中移除
+///     - 超时返回 -EAGAIN，被 `set` 之外的信号打断返回 -EINTR，参数非法返回 -EINVAL
+/// - syscall ID：137
+pub fn sys_rt_sigtimedwait(set: *const u64, info: *mut SigInfo, timeout: *const Timespec, sigsetsize: usize) -> isize {
+    if sigsetsize != core::mem::size_of::<u64>() {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let set = SignalFlags::from_bits_truncate(try_errno!(copy_from_user(token, set))) - SignalFlags::unblockable();
+    let expire_ms = if timeout.is_null() {
+        None
+    } else {
+        let timeout = try_errno!(copy_from_user(token, timeout));
+        if !timeout.is_valid() {
+            return -EINVAL;
+        }
+        Some(get_time_ms().saturating_add(timeout.to_ms_ceil()))
+    };
+    let signo = match dequeue_signal(set) {
+        Some(signo) => signo,
+        None => {
+            // 等待期间解除对 set 中信号的屏蔽，使其他任务发来的这些信号能够唤醒当前任务
+            let task = current_task().unwrap();
+            let mut inner = task.inner_exclusive_access();
+            let old_mask = inner.sigmask;
+            inner.sigmask = old_mask - set;
+            drop(inner);
+            drop(task);
+            let interrupted = wait_for_signal(set, expire_ms);
+            current_task().unwrap().inner_exclusive_access().sigmask = old_mask;
+            match dequeue_signal(set) {
+                Some(signo) => signo,
+                None if interrupted => return -EINTR,
+                None => return -EAGAIN,
+            }
+        }
+    };
+    if !info.is_null() {
+        try_errno!(copy_to_user(token, info, &SigInfo::new(signo)));
+    }
+    signo as isize
 }
 
-pub fn sys_rt_sigaction() -> isize {
-    0
+/// ### 以 `mask` 为信号屏蔽字等待信号
+/// 屏蔽字的替换与开始等待之间不会丢失信号；信号处理函数返回后恢复原来的屏蔽字
+/// - 参数：
+///     - `mask`：等待期间的信号屏蔽字
+///     - `sigsetsize`：必须为 8
+/// - 返回值：总是返回 -EINTR，参数非法返回 -EINVAL
+/// - syscall ID：133
+pub fn sys_rt_sigsuspend(mask: *const u64, sigsetsize: usize) -> isize {
+    if sigsetsize != core::mem::size_of::<u64>() {
+        return -EINVAL;
+    }
+    let mask = SignalFlags::from_bits_truncate(try_errno!(copy_from_user(current_user_token(), mask)));
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.saved_sigmask = Some(inner.sigmask);
+    inner.sigmask = mask - SignalFlags::unblockable();
+    drop(inner);
+    drop(task);
+    wait_for_signal(SignalFlags::empty(), None);
+    -EINTR
 }
 
-pub fn sys_rt_sigtimedwait() -> isize {
+/// ### 设置或查询备用信号栈
+/// - 参数：
+///     - `ss`：新的备用信号栈，`ss_flags` 为 `SS_DISABLE` 时取消备用信号栈；为 0 时只查询
+///     - `old_ss`：非 0 时写入原来的备用信号栈，正在备用信号栈上执行时 `ss_flags` 为 `SS_ONSTACK`
+/// - 返回值：
+///     - 成功返回 0
+///     - 正在备用信号栈上执行时不能修改，返回 -EPERM
+///     - `ss_flags` 非法返回 -EINVAL，栈小于 `MINSIGSTKSZ` 返回 -ENOMEM，地址非法返回 -EFAULT
+/// - syscall ID：132
+pub fn sys_sigaltstack(ss: *const SignalStack, old_ss: *mut SignalStack) -> isize {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let altstack = task.inner_exclusive_access().sigaltstack;
+    let on_altstack = altstack.contains(current_trap_cx().x[2]);
+    if !old_ss.is_null() {
+        let mut old = altstack;
+        if on_altstack {
+            old.ss_flags = SS_ONSTACK;
+        }
+        try_errno!(copy_to_user(token, old_ss, &old));
+    }
+    if !ss.is_null() {
+        let new = try_errno!(copy_from_user(token, ss));
+        if on_altstack {
+            return -EPERM;
+        }
+        let new = match new.ss_flags {
+            SS_DISABLE => SignalStack::disabled(),
+            0 | SS_ONSTACK => {
+                if new.ss_size < MINSIGSTKSZ {
+                    return -ENOMEM;
+                }
+                SignalStack { ss_flags: 0, ..new }
+            }
+            _ => return -EINVAL,
+        };
+        task.inner_exclusive_access().sigaltstack = new;
+    }
     0
 }
 
-pub fn sys_futex() -> isize {
+/// ### 从信号处理函数返回
+/// 由信号返回跳板调用，从用户栈上的信号栈帧恢复信号到来前的寄存器与信号屏蔽字
+/// - 返回值：恢复后的 `a0`，使返回用户态后 `a0` 保持原值；栈帧不可读时以 SIGSEGV 终止进程
+/// - syscall ID：139
+pub fn sys_rt_sigreturn() -> isize {
+    match restore_signal_frame() {
+        Some(a0) => a0 as isize,
+        None => {
+            current_add_signal(SignalFlags::SIGSEGV);
+            -EFAULT
+        }
+    }
+}
+
+/// ### 设置或查询信号处理方式
+/// - 参数：
+///     - `signum`：信号编号，`SIGKILL` 与 `SIGSTOP` 的处理方式不能修改
+///     - `act`：新的处理方式，为 0 时只查询
+///     - `oldact`：非 0 时写入原来的处理方式
+/// - 返回值：成功返回 0，信号编号非法返回 -EINVAL，地址非法返回 -EFAULT
+/// - syscall ID：134
+pub fn sys_rt_sigaction(signum: usize, act: *const SigAction, oldact: *mut SigAction, _sigsetsize: usize) -> isize {
+    if signum == 0 || signum >= NSIG {
+        return -EINVAL;
+    }
+    if act as usize != 0 && SignalFlags::from_signum(signum).intersects(SignalFlags::unblockable()) {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let sigactions = task.inner_exclusive_access().sigactions.clone();
+    if oldact as usize != 0 {
+        let old = sigactions.lock()[signum];
+        try_errno!(copy_to_user(token, oldact, &old));
+    }
+    if act as usize != 0 {
+        let mut new: SigAction = try_errno!(copy_from_user(token, act));
+        // SIGKILL 与 SIGSTOP 不能被屏蔽
+        new.sa_mask &= !SignalFlags::unblockable().bits();
+        sigactions.lock()[signum] = new;
+    }
     0
 }
+
+/// ### 读取 futex 超时时间，换算为超时时刻（CPU 上电时间，毫秒）
+/// - `absolute`：`timeout` 是否为绝对时间（`FUTEX_WAIT_BITSET`），否则为相对时间（`FUTEX_WAIT`）
+///
+/// 内核的各个时钟都以上电时间计，因此绝对时间可以直接比较
+fn futex_expire_ms(timeout: *const Timespec, absolute: bool) -> Result<Option<usize>, Errno> {
+    if timeout.is_null() {
+        return Ok(None);
+    }
+    let timeout = copy_from_user(current_user_token(), timeout)?;
+    if !timeout.is_valid() {
+        return Err(EINVAL);
+    }
+    let ms = timeout.to_ms_ceil();
+    if absolute {
+        Ok(Some(ms))
+    } else {
+        Ok(Some(get_time_ms().saturating_add(ms)))
+    }
+}
+
+/// ### 快速用户空间互斥锁
+/// - 参数：
+///     - `uaddr`：futex 字的地址，需要 4 字节对齐
+///     - `futex_op`：操作，可以带有 `FUTEX_PRIVATE_FLAG`、`FUTEX_CLOCK_REALTIME`
+///     - `val`：`WAIT` 时为期望的 futex 字的值，`WAKE`/`REQUEUE` 时为最多唤醒的任务数
+///     - `timeout`：`WAIT` 时为超时时间（`FUTEX_WAIT` 为相对时间，`FUTEX_WAIT_BITSET` 为绝对时间），
+///         `REQUEUE` 时作为整数，表示最多转移的任务数
+///     - `uaddr2`：`REQUEUE` 的目标 futex
+///     - `val3`：`CMP_REQUEUE` 时为期望的 futex 字的值，`*_BITSET` 时为 bitset
+/// - 返回值：
+///     - `WAIT`：被唤醒返回 0；futex 字与 `val` 不等返回 -EAGAIN，超时返回 -ETIMEDOUT，被信号打断返回 -EINTR
+///     - `WAKE`：唤醒的任务数
+///     - `REQUEUE`：唤醒与转移的任务总数；`CMP_REQUEUE` 时 futex 字与 `val3` 不等返回 -EAGAIN
+///     - 不支持的操作返回 -ENOSYS
+/// - syscall ID：98
+pub fn sys_futex(uaddr: *const u32, futex_op: usize, val: u32, timeout: *const Timespec, uaddr2: *const u32, val3: u32) -> isize {
+    let op = futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+    if uaddr as usize % 4 != 0 {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let token = current_user_token();
+    let key = match futex_key(&task, uaddr as usize) {
+        Some(key) => key,
+        None => return -EFAULT,
+    };
+    match op {
+        FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+            let bitset = if op == FUTEX_WAIT { FUTEX_BITSET_MATCH_ANY } else { val3 };
+            if bitset == 0 {
+                return -EINVAL;
+            }
+            let expire_ms = try_errno!(futex_expire_ms(timeout, op == FUTEX_WAIT_BITSET));
+            if try_errno!(copy_from_user(token, uaddr)) != val {
+                return -EAGAIN;
+            }
+            if let Some(expire_ms) = expire_ms {
+                if expire_ms <= get_time_ms() {
+                    return -ETIMEDOUT;
+                }
+            }
+            drop(task);
+            futex_wait(key, bitset, expire_ms)
+        }
+        FUTEX_WAKE | FUTEX_WAKE_BITSET => {
+            let bitset = if op == FUTEX_WAKE { FUTEX_BITSET_MATCH_ANY } else { val3 };
+            if bitset == 0 {
+                return -EINVAL;
+            }
+            futex_wake(key, val as usize, bitset) as isize
+        }
+        FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+            if uaddr2 as usize % 4 != 0 {
+                return -EINVAL;
+            }
+            let new_key = match futex_key(&task, uaddr2 as usize) {
+                Some(key) => key,
+                None => return -EFAULT,
+            };
+            if op == FUTEX_CMP_REQUEUE && try_errno!(copy_from_user(token, uaddr)) != val3 {
+                return -EAGAIN;
+            }
+            futex_requeue(key, val as usize, new_key, timeout as usize) as isize
+        }
+        _ => -ENOSYS,
+    }
+}
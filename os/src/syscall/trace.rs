@@ -0,0 +1,170 @@
+/// # 系统调用跟踪
+/// `os/src/syscall/trace.rs`
+///
+/// 类似 strace，对开启了跟踪的进程（见 `/proc/<pid>/trace`）打印每次系统调用：
+/// ```text
+/// [strace] pid 3: openat(AT_FDCWD, "/lib/libc.so", O_RDONLY | O_CLOEXEC, 0) = -2 ENOENT
+/// ```
+/// - 路径参数解码为字符串，标志位参数解码为 `OpenFlags`/`MmapFlags`/`CloneFlags` 等名称
+/// - 其余参数以十六进制打印
+//
+use super::errno::*;
+use super::*;
+use crate::fs::OpenFlags;
+use crate::mm::{copy_str_from_user, MmapFlags, MmapProts};
+use crate::task::{current_task, current_user_token, CloneFlags};
+use alloc::{format, string::String, vec, vec::Vec};
+
+/// 路径字符串最多打印的字符数
+const PATH_PRINT_MAX: usize = 64;
+
+/// 按 `bitflags` 类型解码标志位，未定义的位以十六进制附在后面
+macro_rules! fmt_flags {
+    ($ty: ty, $bits: expr) => {{
+        let bits = $bits;
+        let extra = bits & !<$ty>::all().bits();
+        if extra == 0 {
+            format!("{:?}", <$ty>::from_bits_truncate(bits))
+        } else {
+            format!("{:?} | {:#x}", <$ty>::from_bits_truncate(bits), extra)
+        }
+    }};
+}
+
+fn fmt_hex(arg: usize) -> String {
+    format!("{:#x}", arg)
+}
+
+fn fmt_fd(arg: usize) -> String {
+    format!("{}", arg as isize)
+}
+
+fn fmt_dirfd(arg: usize) -> String {
+    if arg as isize == -100 {
+        String::from("AT_FDCWD")
+    } else {
+        fmt_fd(arg)
+    }
+}
+
+/// 从用户空间读出路径字符串，地址非法时打印原始地址
+fn fmt_path(token: usize, arg: usize) -> String {
+    if arg == 0 {
+        return String::from("NULL");
+    }
+    match copy_str_from_user(token, arg as *const u8) {
+        Ok(path) if path.len() > PATH_PRINT_MAX => {
+            let mut end = PATH_PRINT_MAX;
+            while !path.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{:?}...", &path[..end])
+        }
+        Ok(path) => format!("{:?}", path),
+        Err(_) => fmt_hex(arg),
+    }
+}
+
+/// ### 按系统调用解码参数
+/// 未特殊处理的系统调用打印全部 6 个参数
+fn fmt_args(syscall_id: usize, args: &[usize; 6]) -> Vec<String> {
+    let token = current_user_token();
+    match syscall_id {
+        SYSCALL_OPENAT => vec![
+            fmt_dirfd(args[0]),
+            fmt_path(token, args[1]),
+            fmt_flags!(OpenFlags, args[2] as u32),
+            format!("{:#o}", args[3]),
+        ],
+        SYSCALL_MKDIRAT => vec![fmt_dirfd(args[0]), fmt_path(token, args[1]), format!("{:#o}", args[2])],
+        SYSCALL_UNLINKAT | SYSCALL_FACCESSAT => vec![
+            fmt_dirfd(args[0]),
+            fmt_path(token, args[1]),
+            fmt_hex(args[2]),
+        ],
+        SYSCALL_NEWFSTATAT | SYSCALL_UTIMENSAT | SYSCALL_READLINKAT => vec![
+            fmt_dirfd(args[0]),
+            fmt_path(token, args[1]),
+            fmt_hex(args[2]),
+            fmt_hex(args[3]),
+        ],
+        SYSCALL_RENAMEAT2 => vec![
+            fmt_dirfd(args[0]),
+            fmt_path(token, args[1]),
+            fmt_dirfd(args[2]),
+            fmt_path(token, args[3]),
+            fmt_hex(args[4]),
+        ],
+        SYSCALL_CHDIR => vec![fmt_path(token, args[0])],
+        SYSCALL_STATFS => vec![fmt_path(token, args[0]), fmt_hex(args[1])],
+        SYSCALL_UMOUNT2 => vec![fmt_path(token, args[0]), fmt_hex(args[1])],
+        SYSCALL_MOUNT => vec![
+            fmt_path(token, args[0]),
+            fmt_path(token, args[1]),
+            fmt_path(token, args[2]),
+            fmt_hex(args[3]),
+            fmt_hex(args[4]),
+        ],
+        SYSCALL_EXEC => vec![fmt_path(token, args[0]), fmt_hex(args[1]), fmt_hex(args[2])],
+        SYSCALL_CLOSE | SYSCALL_DUP => vec![fmt_fd(args[0])],
+        SYSCALL_DUP3 => vec![fmt_fd(args[0]), fmt_fd(args[1]), fmt_flags!(OpenFlags, args[2] as u32)],
+        SYSCALL_READ | SYSCALL_WRITE | SYSCALL_READV | SYSCALL_WRITEV | SYSCALL_GETDENTS64 => {
+            vec![fmt_fd(args[0]), fmt_hex(args[1]), format!("{}", args[2])]
+        }
+        SYSCALL_PREAD64 => vec![fmt_fd(args[0]), fmt_hex(args[1]), format!("{}", args[2]), format!("{}", args[3])],
+        SYSCALL_LSEEK => vec![fmt_fd(args[0]), format!("{}", args[1] as isize), format!("{}", args[2])],
+        SYSCALL_FSTAT => vec![fmt_fd(args[0]), fmt_hex(args[1])],
+        SYSCALL_FCNTL | SYSCALL_IOCTL => vec![fmt_fd(args[0]), fmt_hex(args[1]), fmt_hex(args[2])],
+        SYSCALL_MMAP => vec![
+            fmt_hex(args[0]),
+            format!("{}", args[1]),
+            fmt_flags!(MmapProts, args[2]),
+            fmt_flags!(MmapFlags, args[3]),
+            fmt_fd(args[4]),
+            fmt_hex(args[5]),
+        ],
+        SYSCALL_MUNMAP => vec![fmt_hex(args[0]), format!("{}", args[1])],
+        SYSCALL_MPROTECT => vec![fmt_hex(args[0]), format!("{}", args[1]), fmt_flags!(MmapProts, args[2])],
+        SYSCALL_BRK => vec![fmt_hex(args[0])],
+        SYSCALL_FORK => vec![
+            fmt_flags!(CloneFlags, args[0]),
+            fmt_hex(args[1]),
+            fmt_hex(args[2]),
+            fmt_hex(args[3]),
+            fmt_hex(args[4]),
+        ],
+        SYSCALL_EXIT | SYSCALL_EXIT_GROUP => vec![format!("{}", args[0] as i32)],
+        SYSCALL_KILL => vec![format!("{}", args[0] as isize), format!("{}", args[1])],
+        SYSCALL_WAIT4 => vec![format!("{}", args[0] as isize), fmt_hex(args[1]), fmt_hex(args[2]), fmt_hex(args[3])],
+        SYSCALL_GETPID | SYSCALL_GETPPID | SYSCALL_GETTID | SYSCALL_GETUID | SYSCALL_GETEUID
+        | SYSCALL_GETEGID | SYSCALL_YIELD | SYSCALL_UMASK => Vec::new(),
+        _ => args.iter().map(|arg| fmt_hex(*arg)).collect(),
+    }
+}
+
+/// ### 系统调用进入时的跟踪
+/// - 返回值：格式化好的 `[strace] pid N: name(args)`，用于在返回时拼接结果
+///
+/// `exit`/`exit_group` 不会返回，因此直接打印并返回 `None`
+pub fn trace_enter(syscall_id: usize, args: &[usize; 6]) -> Option<String> {
+    let pid = current_task().unwrap().getpid();
+    let name = SYSCALL_NAME.get(&syscall_id).unwrap_or(&"unknown");
+    let call = format!("[strace] pid {}: {}({})", pid, name, fmt_args(syscall_id, args).join(", "));
+    if syscall_id == SYSCALL_EXIT || syscall_id == SYSCALL_EXIT_GROUP {
+        println!("{} = ?", call);
+        return None;
+    }
+    Some(call)
+}
+
+/// ### 系统调用返回时的跟踪
+/// 打印返回值，出错时附带错误码名称
+pub fn trace_exit(call: String, result: isize) {
+    if (-4095..0).contains(&result) {
+        println!("{} = {} {}", call, result, errno_name(-result));
+    } else if result > 0xffff {
+        println!("{} = {:#x}", call, result);
+    } else {
+        println!("{} = {}", call, result);
+    }
+}
@@ -0,0 +1,170 @@
+/// # 快速用户空间互斥锁 futex
+/// `os/src/task/futex.rs`
+/// ```
+/// pub fn futex_key(task: &Arc<TaskControlBlock>, uaddr: usize) -> Option<usize>
+/// pub fn futex_wait(key: usize, bitset: u32, expire_ms: Option<usize>) -> isize
+/// pub fn futex_wake(key: usize, max_count: usize, bitset: u32) -> usize
+/// pub fn futex_requeue(key: usize, max_wake: usize, new_key: usize, max_requeue: usize) -> usize
+/// ```
+///
+/// 等待队列以 futex 字所在的 **物理地址** 为键，因此通过共享映射在不同进程间使用同一个 futex 也能正确唤醒。
+/// 私有 futex（`FUTEX_PRIVATE_FLAG`）同样按物理地址处理
+//
+use super::{block_current_and_run_next, current_task, signal_pending, wakeup_task, TaskControlBlock};
+use crate::mm::{PhysAddr, VirtAddr};
+use crate::syscall::errno::{EINTR, ETIMEDOUT};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use lazy_static::*;
+use spin::Mutex;
+
+/// 匹配任意等待者的 bitset，`FUTEX_WAIT`/`FUTEX_WAKE` 即使用它
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// ### futex 等待者
+/// |成员变量|描述|
+/// |--|--|
+/// |`bitset`|等待时指定的 bitset，只有与唤醒时的 bitset 有交集才会被唤醒|
+/// |`task`|阻塞的任务|
+struct FutexWaiter {
+    bitset: u32,
+    task: Arc<TaskControlBlock>,
+}
+
+lazy_static! {
+    /// futex 物理地址 -> 等待队列
+    static ref FUTEX_QUEUES: Mutex<BTreeMap<usize, VecDeque<FutexWaiter>>> = Mutex::new(BTreeMap::new());
+}
+
+/// ### 求出 futex 字在等待队列中的键，即其物理地址
+/// - 参数
+///     - `task`：`uaddr` 所属地址空间的任务
+///     - `uaddr`：futex 字的用户虚拟地址
+/// - 返回值：地址非法时返回 `None`
+///
+/// 页面尚未分配时先完成缺页加载；写时复制的页面先完成复制，
+/// 避免 fork 出的进程在共享的只读页面上得到相同的键而互相唤醒
+pub fn futex_key(task: &Arc<TaskControlBlock>, uaddr: usize) -> Option<usize> {
+    let va = VirtAddr::from(uaddr);
+    task.check_lazy(va, true);
+    let pte = task.inner_exclusive_access().enquire_pte_via_vpn(va.floor())?;
+    if !pte.is_valid() {
+        return None;
+    }
+    Some(PhysAddr::from(pte.ppn()).0 + va.page_offset())
+}
+
+/// ### 在 futex 上阻塞当前任务
+/// - 参数
+///     - `key`：`futex_key` 求出的键
+///     - `bitset`：等待的 bitset
+///     - `expire_ms`：超时时刻（CPU 上电时间，毫秒），`None` 表示不超时
+/// - 返回值：被 `futex_wake`/`futex_requeue` 唤醒时返回 0，到达超时时刻返回 `-ETIMEDOUT`，
+///   有待处理的信号时返回 `-EINTR`
+///
+/// 调用者需要保证从检查 futex 字到调用本函数之间不会发生调度。
+/// 被唤醒后仍在等待队列中（可能已被 requeue 到其他键），且既没有超时也没有待处理的信号时继续等待
+pub fn futex_wait(key: usize, bitset: u32, expire_ms: Option<usize>) -> isize {
+    let task = current_task().unwrap();
+    FUTEX_QUEUES.lock().entry(key).or_insert_with(VecDeque::new).push_back(FutexWaiter {
+        bitset,
+        task: task.clone(),
+    });
+    loop {
+        // 阻塞前已有待处理的信号时，没有人会再唤醒当前任务
+        if signal_pending() {
+            remove_waiter(&task);
+            return -EINTR;
+        }
+        if let Some(expire_ms) = expire_ms {
+            add_timer(expire_ms, task.clone());
+        }
+        block_current_and_run_next();
+        remove_timer(&task);
+        if !is_waiting(&task) {
+            return 0;
+        }
+        if expire_ms.map_or(false, |expire_ms| get_time_ms() >= expire_ms) {
+            remove_waiter(&task);
+            return -ETIMEDOUT;
+        }
+    }
+}
+
+/// 任务是否仍在某个 futex 的等待队列中
+fn is_waiting(task: &Arc<TaskControlBlock>) -> bool {
+    FUTEX_QUEUES
+        .lock()
+        .values()
+        .any(|queue| queue.iter().any(|waiter| Arc::ptr_eq(&waiter.task, task)))
+}
+
+/// 将任务移出其所在的 futex 等待队列
+fn remove_waiter(task: &Arc<TaskControlBlock>) {
+    let mut queues = FUTEX_QUEUES.lock();
+    for queue in queues.values_mut() {
+        queue.retain(|waiter| !Arc::ptr_eq(&waiter.task, task));
+    }
+    queues.retain(|_, queue| !queue.is_empty());
+}
+
+/// ### 唤醒在 futex 上等待的任务
+/// - 参数
+///     - `key`：`futex_key` 求出的键
+///     - `max_count`：最多唤醒的任务数
+///     - `bitset`：只唤醒等待 bitset 与之有交集的任务
+/// - 返回值：实际唤醒的任务数
+pub fn futex_wake(key: usize, max_count: usize, bitset: u32) -> usize {
+    let mut queues = FUTEX_QUEUES.lock();
+    let queue = match queues.get_mut(&key) {
+        Some(queue) => queue,
+        None => return 0,
+    };
+    let mut woken = VecDeque::new();
+    let mut index = 0;
+    while index < queue.len() && woken.len() < max_count {
+        if queue[index].bitset & bitset != 0 {
+            woken.push_back(queue.remove(index).unwrap().task);
+        } else {
+            index += 1;
+        }
+    }
+    if queue.is_empty() {
+        queues.remove(&key);
+    }
+    drop(queues);
+    let count = woken.len();
+    for task in woken {
+        wakeup_task(task);
+    }
+    count
+}
+
+/// ### 唤醒在 futex 上等待的部分任务，并将剩余的部分任务转移到另一个 futex 上等待
+/// - 参数
+///     - `key`：原 futex 的键
+///     - `max_wake`：最多唤醒的任务数
+///     - `new_key`：目标 futex 的键
+///     - `max_requeue`：最多转移的任务数
+/// - 返回值：唤醒与转移的任务总数
+pub fn futex_requeue(key: usize, max_wake: usize, new_key: usize, max_requeue: usize) -> usize {
+    let woken = futex_wake(key, max_wake, FUTEX_BITSET_MATCH_ANY);
+    if key == new_key {
+        return woken;
+    }
+    let mut queues = FUTEX_QUEUES.lock();
+    let mut moved = VecDeque::new();
+    if let Some(queue) = queues.get_mut(&key) {
+        let count = max_requeue.min(queue.len());
+        moved.extend(queue.drain(..count));
+        if queue.is_empty() {
+            queues.remove(&key);
+        }
+    }
+    let requeued = moved.len();
+    if requeued > 0 {
+        queues.entry(new_key).or_insert_with(VecDeque::new).append(&mut moved);
+    }
+    woken + requeued
+}
@@ -3,6 +3,9 @@
 /// ```
 /// pub struct Utsname
 /// pub struct CloneFlags
+/// pub struct TaskUsage
+/// pub struct RUsage
+/// pub struct SysInfo
 /// ```
 //
 //use alloc::sync::Arc;
@@ -84,6 +87,41 @@ bitflags! {
     }
 }
 
+/// ### 任务的资源使用统计
+/// |成员变量|描述|
+/// |--|--|
+/// |`utime_us`|用户态 CPU 时间（微秒）|
+/// |`stime_us`|内核态 CPU 时间（微秒）|
+/// |`minflt`|无需读文件即可处理的缺页次数|
+/// |`majflt`|需要读文件的缺页次数|
+/// |`nvcsw`|主动让出处理器（阻塞等待）的次数|
+/// |`nivcsw`|被动让出处理器（时间片用完、`sched_yield`）的次数|
+#[derive(Copy, Clone, Default)]
+pub struct TaskUsage {
+    pub utime_us: usize,
+    pub stime_us: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+impl TaskUsage {
+    pub fn add(&mut self, other: &Self) {
+        self.utime_us += other.utime_us;
+        self.stime_us += other.stime_us;
+        self.minflt += other.minflt;
+        self.majflt += other.majflt;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+
+    /// 用户态与内核态 CPU 时间之和（微秒）
+    pub fn cpu_time_us(&self) -> usize {
+        self.utime_us + self.stime_us
+    }
+}
+
 #[allow(unused)]
 pub struct RUsage{
     ru_utime   :TimeVal,      /* user CPU time used */
@@ -92,16 +130,16 @@ pub struct RUsage{
     ru_ixrss   :isize  ,      // NOT IMPLEMENTED /* integral shared memory size */
     ru_idrss   :isize  ,      // NOT IMPLEMENTED /* integral unshared data size */
     ru_isrss   :isize  ,      // NOT IMPLEMENTED /* integral unshared stack size */
-    ru_minflt  :isize  ,      // NOT IMPLEMENTED /* page reclaims (soft page faults) */
-    ru_majflt  :isize  ,      // NOT IMPLEMENTED /* page faults (hard page faults) */
+    ru_minflt  :isize  ,      /* page reclaims (soft page faults) */
+    ru_majflt  :isize  ,      /* page faults (hard page faults) */
     ru_nswap   :isize  ,      // NOT IMPLEMENTED /* swaps */
     ru_inblock :isize  ,      // NOT IMPLEMENTED /* block input operations */
     ru_oublock :isize  ,      // NOT IMPLEMENTED /* block output operations */
     ru_msgsnd  :isize  ,      // NOT IMPLEMENTED /* IPC messages sent */
     ru_msgrcv  :isize  ,      // NOT IMPLEMENTED /* IPC messages received */
     ru_nsignals:isize  ,      // NOT IMPLEMENTED /* signals received */
-    ru_nvcsw   :isize  ,      // NOT IMPLEMENTED /* voluntary context switches */
-    ru_nivcsw  :isize  ,      // NOT IMPLEMENTED /* involuntary context switches */
+    ru_nvcsw   :isize  ,      /* voluntary context switches */
+    ru_nivcsw  :isize  ,      /* involuntary context switches */
 }
 
 impl RUsage{
@@ -126,6 +164,17 @@ impl RUsage{
         }
     }
 
+    pub fn from_usage(usage: &TaskUsage) -> Self {
+        let mut rusage = Self::new();
+        rusage.ru_utime = TimeVal::from_us(usage.utime_us);
+        rusage.ru_stime = TimeVal::from_us(usage.stime_us);
+        rusage.ru_minflt = usage.minflt as isize;
+        rusage.ru_majflt = usage.majflt as isize;
+        rusage.ru_nvcsw = usage.nvcsw as isize;
+        rusage.ru_nivcsw = usage.nivcsw as isize;
+        rusage
+    }
+
     // pub fn add_utime(&mut self, usec: usize){
     //     self.ru_utime.add_usec(usec);
     // }
@@ -144,4 +193,32 @@ impl RUsage{
         }
     }
 
-}
\ No newline at end of file
+}
+/// ### 系统整体统计信息（`struct sysinfo`）
+/// |成员变量|描述|
+/// |--|--|
+/// |`uptime`|启动以来经过的秒数|
+/// |`loads`|1、5、15 分钟平均负载，不统计，恒为 0|
+/// |`totalram`|物理内存总量，单位为 `mem_unit`|
+/// |`freeram`|空闲物理内存，单位为 `mem_unit`|
+/// |`procs`|当前的进程数|
+/// |`mem_unit`|内存大小的单位（字节）|
+///
+/// 其余成员（共享内存、缓冲区、交换区、高端内存）内核中没有，恒为 0
+#[repr(C)]
+#[derive(Default)]
+pub struct SysInfo {
+    pub uptime: isize,
+    pub loads: [usize; 3],
+    pub totalram: usize,
+    pub freeram: usize,
+    pub sharedram: usize,
+    pub bufferram: usize,
+    pub totalswap: usize,
+    pub freeswap: usize,
+    pub procs: u16,
+    pub pad: u16,
+    pub totalhigh: usize,
+    pub freehigh: usize,
+    pub mem_unit: u32,
+}
@@ -0,0 +1,209 @@
+/// # 间隔定时器与 POSIX 定时器
+/// `os/src/task/itimer.rs`
+/// ```
+/// pub struct ITimerVal
+/// pub struct ITimerSpec
+/// pub struct CpuTimer
+/// pub struct RealTimer
+/// pub fn arm_real_timer(task: &Arc<TaskControlBlock>, slot: RealTimerSlot)
+/// pub fn fire_real_timer(task: &Arc<TaskControlBlock>, slot: RealTimerSlot, generation: usize) -> Option<usize>
+/// ```
+///
+/// - `ITIMER_VIRTUAL`/`ITIMER_PROF` 按任务的用户态 / 全部 CPU 时间递减，在时间统计时检查（见 `TaskControlBlockInner::account_user_time`）
+/// - `ITIMER_REAL` 与 POSIX 定时器按墙上时间计时，挂在内核定时器列表上，到期时由 `check_timer` 调用 `fire_real_timer`
+///
+/// 所有时间在内核中都以微秒保存
+//
+use super::{pid2task, send_signal, send_signal_to_thread, SignalFlags, TaskControlBlock};
+use crate::timer::{add_real_timer, get_time_us, TimeVal, Timespec};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// POSIX 定时器数量上限
+pub const POSIX_TIMER_MAX: usize = 32;
+
+/// 全局递增的定时器代数，保证定时器 ID 被复用后旧的定时器列表表项也不会误触发
+static NEXT_GENERATION: AtomicUsize = AtomicUsize::new(1);
+
+/// ### `setitimer`/`getitimer` 使用的定时器值（Linux `struct itimerval`）
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ITimerVal {
+    pub it_interval: TimeVal,
+    pub it_value: TimeVal,
+}
+
+/// ### `timer_settime`/`timer_gettime` 使用的定时器值（Linux `struct itimerspec`）
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ITimerSpec {
+    pub it_interval: Timespec,
+    pub it_value: Timespec,
+}
+
+/// ### `timer_create` 使用的通知方式（Linux `struct sigevent` 的前几个成员）
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SigEvent {
+    pub sigev_value: usize,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    pub sigev_notify_thread_id: i32,
+}
+
+/// ### 按 CPU 时间计时的定时器（`ITIMER_VIRTUAL`/`ITIMER_PROF`）
+/// |成员变量|描述|
+/// |--|--|
+/// |`value_us`|距离到期还需消耗的 CPU 时间，0 表示未启用|
+/// |`interval_us`|到期后重新装载的值，0 表示只触发一次|
+#[derive(Copy, Clone, Default)]
+pub struct CpuTimer {
+    pub value_us: usize,
+    pub interval_us: usize,
+}
+
+impl CpuTimer {
+    /// ### 消耗 `delta_us` 的 CPU 时间
+    /// - 返回值：本次是否到期
+    pub fn tick(&mut self, delta_us: usize) -> bool {
+        if self.value_us == 0 {
+            return false;
+        }
+        if delta_us < self.value_us {
+            self.value_us -= delta_us;
+            return false;
+        }
+        self.value_us = self.interval_us;
+        true
+    }
+}
+
+/// ### 实时定时器在任务中的位置
+#[derive(Copy, Clone, PartialEq)]
+pub enum RealTimerSlot {
+    /// `ITIMER_REAL`
+    ITimerReal,
+    /// `timer_create` 创建的 POSIX 定时器，参数为定时器 ID
+    Posix(usize),
+}
+
+/// ### 按墙上时间计时的定时器（`ITIMER_REAL` 与 POSIX 定时器）
+/// |成员变量|描述|
+/// |--|--|
+/// |`signal`|到期时发送的信号，为空表示不发送（`SIGEV_NONE`）|
+/// |`target_tid`|`SIGEV_THREAD_ID` 指定接收信号的线程，`None` 表示定时器所属任务|
+/// |`expire_us`|到期时刻（CPU 上电时间），0 表示未启用|
+/// |`interval_us`|到期后重新装载的间隔，0 表示只触发一次|
+/// |`generation`|每次重设递增，定时器列表中代数不符的旧表项到期时被忽略|
+/// |`overrun`|上次到期时错过的触发次数|
+#[derive(Copy, Clone)]
+pub struct RealTimer {
+    pub signal: SignalFlags,
+    pub target_tid: Option<usize>,
+    pub expire_us: usize,
+    pub interval_us: usize,
+    pub generation: usize,
+    pub overrun: usize,
+}
+
+impl RealTimer {
+    pub fn new(signal: SignalFlags, target_tid: Option<usize>) -> Self {
+        Self {
+            signal,
+            target_tid,
+            expire_us: 0,
+            interval_us: 0,
+            generation: 0,
+            overrun: 0,
+        }
+    }
+
+    /// 距离到期的剩余时间，未启用时为 0
+    pub fn remaining_us(&self) -> usize {
+        if self.expire_us == 0 {
+            0
+        } else {
+            // 已到期但尚未处理时报告 1 微秒，与“未启用”区分
+            self.expire_us.saturating_sub(get_time_us()).max(1)
+        }
+    }
+
+    /// ### 重设定时器
+    /// - `expire_us`：到期时刻，0 表示停止定时器
+    /// - `interval_us`：重新装载的间隔
+    pub fn set(&mut self, expire_us: usize, interval_us: usize) {
+        self.expire_us = expire_us;
+        self.interval_us = interval_us;
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        self.overrun = 0;
+    }
+}
+
+/// 微秒转换为定时器列表使用的毫秒，向上取整
+fn us_to_ms_ceil(us: usize) -> usize {
+    (us + 999) / 1000
+}
+
+/// ### 将任务的实时定时器挂到内核定时器列表上
+/// 定时器未启用时什么也不做
+pub fn arm_real_timer(task: &Arc<TaskControlBlock>, slot: RealTimerSlot) {
+    let inner = task.inner_exclusive_access();
+    let timer = match inner.real_timer(slot) {
+        Some(timer) if timer.expire_us != 0 => *timer,
+        _ => return,
+    };
+    drop(inner);
+    add_real_timer(us_to_ms_ceil(timer.expire_us), task.clone(), slot, timer.generation);
+}
+
+/// ### 实时定时器到期
+/// - 参数
+///     - `task`：定时器所属任务
+///     - `slot`、`generation`：挂到定时器列表上时记录的定时器位置与代数
+/// - 返回值：周期定时器返回下一次到期的时刻（毫秒），由调用者重新挂到定时器列表上
+///
+/// 定时器已被重设、删除或任务已退出时忽略
+pub fn fire_real_timer(task: &Arc<TaskControlBlock>, slot: RealTimerSlot, generation: usize) -> Option<usize> {
+    let mut inner = task.inner_exclusive_access();
+    if inner.is_zombie() {
+        return None;
+    }
+    let timer = match inner.real_timer_mut(slot) {
+        Some(timer) if timer.generation == generation && timer.expire_us != 0 => timer,
+        _ => return None,
+    };
+    let current_us = get_time_us();
+    let next = if timer.interval_us == 0 {
+        timer.expire_us = 0;
+        None
+    } else {
+        // 错过的周期计入 overrun
+        timer.expire_us += timer.interval_us;
+        timer.overrun = 0;
+        if timer.expire_us <= current_us {
+            let missed = (current_us - timer.expire_us) / timer.interval_us + 1;
+            timer.overrun = missed;
+            timer.expire_us += missed * timer.interval_us;
+        }
+        Some(us_to_ms_ceil(timer.expire_us))
+    };
+    let signal = timer.signal;
+    let target_tid = timer.target_tid;
+    drop(inner);
+
+    if !signal.is_empty() {
+        match target_tid.and_then(pid2task) {
+            Some(target) => send_signal_to_thread(&target, signal),
+            None => send_signal(task, signal),
+        }
+    }
+    next
+}
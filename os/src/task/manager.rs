@@ -4,6 +4,7 @@ use super::TaskControlBlock;
 use spin::Mutex;
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
 /// ### FIFO 任务管理器
@@ -53,6 +54,32 @@ pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
     map.get(&pid).map(Arc::clone)
 }
 
+/// 获取线程组 `tgid` 中仍未被回收的全部线程
+pub fn tgid2tasks(tgid: usize) -> Vec<Arc<TaskControlBlock>> {
+    PID2TCB
+        .lock()
+        .values()
+        .filter(|task| task.tgid == tgid)
+        .map(Arc::clone)
+        .collect()
+}
+
+/// 获取全部进程（线程组的主线程）
+pub fn all_processes() -> Vec<Arc<TaskControlBlock>> {
+    PID2TCB.lock().values().filter(|task| !task.is_thread()).map(Arc::clone).collect()
+}
+
+/// ### 获取进程组 `pgid` 中的全部进程（线程组的主线程）
+/// 调用时不能持有任何任务的锁
+pub fn pgid2tasks(pgid: usize) -> Vec<Arc<TaskControlBlock>> {
+    PID2TCB
+        .lock()
+        .values()
+        .filter(|task| !task.is_thread() && task.inner_exclusive_access().pgid == pgid)
+        .map(Arc::clone)
+        .collect()
+}
+
 pub fn remove_from_pid2task(pid: usize) {
     let mut map = PID2TCB.lock();
     if map.remove(&pid).is_none() {
@@ -64,6 +91,6 @@ pub fn remove_from_pid2task(pid: usize) {
 pub fn debug_show_ready_queue() {
     for task in TASK_MANAGER.lock().ready_queue.iter() {
         let inner = task.inner_exclusive_access();
-        println!("pid = {}, signals: {:?}", task.pid.0, inner.signals);
+        println!("pid = {}, signals: {:?}", task.pid.0, inner.pending.signals);
     }
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
 mod aux;
 mod context; // 任务上下文模块
+mod futex; // futex 等待队列
 mod info; // 系统信息模块
+mod itimer; // 间隔定时器与 POSIX 定时器
 mod manager; // 进程管理器
 mod pid; // 进程标识符模块
 mod processor; // 处理器管理模块
@@ -9,25 +11,36 @@ mod signal; // 进程状态标志
 mod switch; // 任务上下文切换模块
 #[allow(clippy::module_inception)]
 mod task; // 进程控制块
+mod wait_queue; // 等待队列
 
 use alloc::{sync::Arc, vec::Vec};
 use lazy_static::*;
+use spin::Mutex;
 use manager::fetch_task;
 use manager::remove_from_pid2task;
+use processor::release_exited_thread;
 use switch::__switch;
-use task::{TaskControlBlock, TaskStatus};
+use task::TaskStatus;
 
 pub use aux::*;
 pub use context::TaskContext;
-pub use info::{CloneFlags, RUsage, Utsname, UTSNAME};
-pub use manager::{add_task, debug_show_ready_queue, pid2task};
-pub use pid::{pid_alloc, KernelStack, PidHandle};
-pub use processor::{current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task};
+pub use futex::{futex_key, futex_requeue, futex_wait, futex_wake, FUTEX_BITSET_MATCH_ANY};
+pub use info::{CloneFlags, RUsage, SysInfo, TaskUsage, Utsname, UTSNAME};
+pub use itimer::*;
+pub use manager::{add_task, all_processes, debug_show_ready_queue, pgid2tasks, pid2task, tgid2tasks};
+pub use pid::{pid_alloc, trap_cx_position, KernelStack, PidHandle};
+pub use processor::{
+    current_task, current_trap_cx, current_trap_cx_user_va, current_user_token, run_tasks, schedule, take_current_task,
+};
 pub use resource::*;
 pub use signal::*;
-pub use task::FD_LIMIT;
+pub use task::{FdTable, TaskControlBlock, FD_LIMIT};
+pub use wait_queue::WaitQueue;
 
+use crate::config::{TRAP_CONTEXT, USER_SPACE_END};
 use crate::fs::{open, OpenFlags};
+use crate::mm::{copy_to_user, release_shared_pages, MemorySet, MmapArea, VirtAddr};
+use crate::timer::remove_all_timers;
 
 /// 将当前任务置为就绪态，放回到进程管理器中的就绪队列中，重新选择一个进程运行
 pub fn suspend_current_and_run_next() -> isize {
@@ -35,17 +48,18 @@ pub fn suspend_current_and_run_next() -> isize {
     // 取出当前正在执行的任务
     let task_cp = current_task().unwrap();
     let mut task_inner = task_cp.inner_exclusive_access();
-    if task_inner.signals.contains(SignalFlags::SIGKILL) {
-        let exit_code = task_inner.exit_code;
+    if task_inner.pending_signals().contains(SignalFlags::SIGKILL) {
         drop(task_inner);
         drop(task_cp);
-        exit_current_and_run_next(exit_code);
+        kill_current_and_run_next(9);
         return 0;
     }
     let task = take_current_task().unwrap();
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     // 修改其进程控制块内的状态为就绪状态
     task_inner.task_status = TaskStatus::Ready;
+    task_inner.account_system_time();
+    task_inner.usage.nivcsw += 1;
     drop(task_inner);
     // 将进程加入进程管理器中的就绪队列
     add_task(task);
@@ -54,16 +68,135 @@ pub fn suspend_current_and_run_next() -> isize {
     0
 }
 
+/// ### 将当前任务置为阻塞态并重新选择一个进程运行
+/// 任务不会放回就绪队列，调用者需要事先把它登记到某个等待队列（如 futex、定时器）中，
+/// 之后由 `wakeup_task` 唤醒
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    task_inner.account_system_time();
+    task_inner.usage.nvcsw += 1;
+    drop(task_inner);
+    drop(task);
+    schedule(task_cx_ptr);
+}
+
+/// ### 停止当前任务并重新选择一个进程运行
+/// 任务不会放回就绪队列，直到收到 SIGCONT 或 SIGKILL 时由 `continue_task` 恢复
+pub fn stop_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Stopped;
+    task_inner.account_system_time();
+    task_inner.usage.nvcsw += 1;
+    drop(task_inner);
+    drop(task);
+    schedule(task_cx_ptr);
+}
+
+/// ### 恢复一个停止的任务，将其放回就绪队列
+/// - 返回值：任务原先是否处于停止状态
+pub fn continue_task(task: Arc<TaskControlBlock>) -> bool {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Stopped {
+        return false;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+    true
+}
+
+/// ### 唤醒一个阻塞态的任务，将其放回就绪队列
+/// 任务不处于阻塞态（已被其他途径唤醒或已退出）时什么也不做
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
+
+/// ### 处理当前任务的 `clear_child_tid`
+/// 若设置过（`CLONE_CHILD_CLEARTID` / `set_tid_address`），则向该地址写入 0 并唤醒在其上 futex 等待的一个任务，
+/// 写入失败时静默忽略
+fn clear_child_tid() {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let tidptr = inner.clear_child_tid;
+    inner.clear_child_tid = 0;
+    let token = inner.get_user_token();
+    drop(inner);
+    if tidptr != 0 && copy_to_user(token, tidptr as *mut u32, &0u32).is_ok() {
+        if let Some(key) = futex_key(&task, tidptr) {
+            futex_wake(key, 1, FUTEX_BITSET_MATCH_ANY);
+        }
+    }
+}
+
+/// ### 以退出码 `exit_code` 结束当前任务（`exit` 系统调用）
+/// 只保留退出码的低 8 位，线程组中的其他线程不受影响
 pub fn exit_current_and_run_next(exit_code: i32) {
+    do_exit((exit_code & 0xff) << 8);
+}
+
+/// ### 以退出码 `exit_code` 结束当前任务所在线程组的全部线程（`exit_group` 系统调用）
+pub fn exit_group_and_run_next(exit_code: i32) {
+    do_group_exit((exit_code & 0xff) << 8);
+}
+
+/// ### 以默认处理方式被信号 `signo` 终止当前任务所在线程组的全部线程
+/// 默认处理方式为 `CoreDump` 的信号在状态字中置上 core dump 标志，内核并不真正生成 core 文件
+pub fn kill_current_and_run_next(signo: usize) {
+    let core_dump = if default_action(signo) == SigDefault::CoreDump { 0x80 } else { 0 };
+    do_group_exit(signo as i32 | core_dump);
+}
+
+/// ### 记录线程组的退出状态，向线程组中的其他线程发送 SIGKILL，然后结束当前任务
+/// 其他线程返回用户态前处理 SIGKILL 时再次进入这里，此时线程组的退出状态已经设置，不再重复发送
+fn do_group_exit(wait_status: i32) {
+    let task = current_task().unwrap();
+    let thread_group = task.inner_exclusive_access().thread_group.clone();
+    let mut group = thread_group.lock();
+    let first = group.exit_code.is_none();
+    if first {
+        group.exit_code = Some(wait_status);
+    }
+    drop(group);
+    if first {
+        for thread in tgid2tasks(task.tgid) {
+            if !Arc::ptr_eq(&thread, &task) {
+                send_signal_to_thread(&thread, SignalFlags::SIGKILL);
+            }
+        }
+    }
+    drop(task);
+    do_exit(wait_status);
+}
+
+/// ### 结束当前任务，`wait_status` 为父进程 waitpid 得到的状态字
+/// 线程组中最后一个退出的线程负责向父进程报告线程组的退出状态，在此之前主线程即使已退出也不能被回收
+fn do_exit(wait_status: i32) {
     // println!("[KERNEL] pid:{} exited", current_task().unwrap().pid.0);
 
+    // 清零 clear_child_tid，使 pthread_join 等待的线程得以返回
+    // 需要在当前任务仍为 current 时进行，以便缺页 / 写时复制能正确处理
+    clear_child_tid();
+
     // 获取访问权限，修改进程状态
     let task = take_current_task().unwrap();
     remove_from_pid2task(task.getpid());
+    remove_all_timers(&task);
     let mut inner = task.inner_exclusive_access();
     inner.task_status = TaskStatus::Zombie; // 后续才能被父进程在 waitpid 系统调用的时候回收
-                                            // 记录退出码，后续父进程在 waitpid 的时候可以收集
-    inner.exit_code = exit_code;
+                                            // 记录退出状态，后续父进程在 waitpid 的时候可以收集
+    inner.exit_code = wait_status;
+    inner.account_system_time();
     // do not move to its parent but under initproc
 
     if task.getpid() == 0 {
@@ -79,11 +212,56 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         }
     }
 
+    // 转移过来的子进程中可能已有僵尸进程，唤醒 initproc 回收
+    if !inner.children.is_empty() {
+        INITPROC.child_exit.wake_all();
+    }
     inner.children.clear(); // 引用计数 +1
-                            // 对于当前进程占用的资源进行早期回收
-    inner.memory_set.recycle_data_pages();
+
+    // 解除本线程在共享地址空间中的 Trap 上下文映射
+    if inner.trap_cx_va != TRAP_CONTEXT {
+        let trap_cx_va = inner.trap_cx_va;
+        inner.memory_set.lock().remove_area_with_start_vpn(VirtAddr::from(trap_cx_va).into());
+    }
+    // 放弃对地址空间的引用，僵尸进程不再持有地址空间，最后一个使用它的任务退出时回收
+    // 共享文件映射的修改在回收前写回文件
+    let memory_set = core::mem::replace(&mut inner.memory_set, Arc::new(Mutex::new(MemorySet::new_bare())));
+    let mmap_area = core::mem::replace(&mut inner.mmap_area, Arc::new(Mutex::new(MmapArea::new())));
+    if Arc::strong_count(&memory_set) == 1 {
+        mmap_area.lock().sync_shared(0, USER_SPACE_END, &mut memory_set.lock());
+        drop(memory_set);
+        release_shared_pages();
+    }
+    // 放弃对文件描述符表的引用，最后一个使用它的任务退出时关闭其中的文件
+    let fd_table = core::mem::replace(&mut inner.fd_table, Arc::new(Mutex::new(FdTable::new(Vec::new()))));
+    let mut group = inner.thread_group.lock();
+    group.live -= 1;
+    // 线程退出后不再出现在 tgid2tasks 中，资源使用转入线程组，以便计入进程的统计
+    group.exited_usage.add(&inner.usage);
+    let report = if group.live == 0 {
+        let leader = if task.is_thread() { group.leader.upgrade() } else { Some(task.clone()) };
+        leader.map(|leader| (leader, group.exit_code))
+    } else {
+        None
+    };
+    drop(group);
     drop(inner);
-    drop(task);
+    // 关闭文件可能唤醒其他任务（如管道的另一端），不能持有锁
+    drop(fd_table);
+    if let Some((leader, group_exit_code)) = report {
+        // 线程组整体退出时，以线程组的退出状态作为主线程的退出状态
+        if let Some(exit_code) = group_exit_code {
+            leader.inner_exclusive_access().exit_code = exit_code;
+        }
+        // 向父进程发送 SIGCHLD，并唤醒在 waitpid 中等待的父进程
+        notify_parent(&leader, true);
+    }
+    if task.is_thread() {
+        // 线程没有父进程回收，切换走之后由 idle 控制流释放
+        release_exited_thread(task);
+    } else {
+        drop(task);
+    }
     // 使用全0的上下文填充换出上下文，开启新一轮进程调度
     let mut _unused = TaskContext::zero_init();
     schedule(&mut _unused as *mut _);
@@ -112,5 +290,6 @@ lazy_static! {
 
 /// 将初始进程 `initproc` 加入任务管理器
 pub fn add_initproc() {
+    INITPROC.inner_exclusive_access().thread_group.lock().leader = Arc::downgrade(&INITPROC);
     add_task(INITPROC.clone());
 }
@@ -1,6 +1,6 @@
 /// # 进程标识符和应用内核栈模块
 
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT};
 use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
 use spin::Mutex;
 use alloc::vec::Vec;
@@ -78,6 +78,12 @@ pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
     (bottom, top)
 }
 
+/// ### 线程的 Trap 上下文在应用地址空间中的位置
+/// 主线程使用 `TRAP_CONTEXT`，与之共享地址空间的其他任务按 tid 依次向下各占一页
+pub fn trap_cx_position(tid: usize) -> usize {
+    TRAP_CONTEXT - tid * PAGE_SIZE
+}
+
 /// ### 应用内核栈
 /// - 成员变量：pid
 /// ```
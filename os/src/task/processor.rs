@@ -9,6 +9,8 @@
 /// pub fn current_task() -> Option<Arc<TaskControlBlock>>
 /// pub fn current_user_token() -> usize
 /// pub fn current_trap_cx() -> &'static mut TrapContext
+/// pub fn current_trap_cx_user_va() -> usize
+/// pub fn release_exited_thread(task: Arc<TaskControlBlock>)
 /// pub fn schedule(switched_task_cx_ptr: *mut TaskContext)
 /// ```
 //
@@ -17,6 +19,8 @@ use super::__switch;
 use super::{fetch_task, TaskStatus};
 use super::{TaskContext, TaskControlBlock};
 use spin::Mutex;
+use crate::fs::poll_console;
+use crate::timer::check_timer;
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -26,6 +30,7 @@ use lazy_static::*;
 /// |--|--|
 /// |`current`|当前处理器上正在执行的任务|
 /// |`idle_task_cx`|当前处理器上的 idle 控制流的任务上下文|
+/// |`exited_thread`|刚刚退出、等待回到 idle 控制流后再释放的线程|
 /// ```
 /// Processor::new() -> Self
 /// Processor::take_current(&mut self) -> Option<Arc<TaskControlBlock>>
@@ -35,6 +40,8 @@ pub struct Processor {  /// 当前处理器上正在执行的任务
     current: Option<Arc<TaskControlBlock>>,
     /// 当前处理器上的 idle 控制流的任务上下文
     idle_task_cx: TaskContext,
+    /// 线程退出时仍运行在自己的内核栈上，不能立即释放，交给 idle 控制流释放
+    exited_thread: Option<Arc<TaskControlBlock>>,
 }
 
 impl Processor {
@@ -42,6 +49,7 @@ impl Processor {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            exited_thread: None,
         }
     }
     fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
@@ -68,6 +76,13 @@ lazy_static! {
 pub fn run_tasks() {
     loop {
         let mut processor = PROCESSOR.lock();
+        // 此时已经离开了退出线程的内核栈，可以释放它
+        let exited_thread = processor.exited_thread.take();
+        if exited_thread.is_some() {
+            drop(processor);
+            drop(exited_thread);
+            processor = PROCESSOR.lock();
+        }
         // TASK_MANAGER.exclusive_access().list_alltask();
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
@@ -75,6 +90,7 @@ pub fn run_tasks() {
             let mut task_inner = task.inner_exclusive_access();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
+            task_inner.reset_time_stamp();
             drop(task_inner);
             // release coming task TCB manually
             processor.current = Some(task);
@@ -83,6 +99,11 @@ pub fn run_tasks() {
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+        } else {
+            // 内核态不响应时钟中断，没有就绪任务时由 idle 控制流检查定时唤醒与控制台输入
+            drop(processor);
+            check_timer();
+            poll_console();
         }
     }
 }
@@ -111,6 +132,17 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
         .get_trap_cx()
 }
 
+/// 获取当前任务的 Trap 上下文在应用地址空间中的虚拟地址
+pub fn current_trap_cx_user_va() -> usize {
+    current_task().unwrap().inner_exclusive_access().trap_cx_va
+}
+
+/// ### 延迟释放已退出的线程
+/// 线程没有父进程负责回收，由 idle 控制流在切换走之后释放其内核栈等资源
+pub fn release_exited_thread(task: Arc<TaskControlBlock>) {
+    PROCESSOR.lock().exited_thread = Some(task);
+}
+
 /// 换到 idle 控制流并开启新一轮的任务调度
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
     let mut processor = PROCESSOR.lock();
@@ -1,87 +1,717 @@
+/// # 信号
+/// `os/src/task/signal.rs`
+/// ```
+/// pub struct SignalFlags
+/// pub struct SigPending
+/// pub struct SigAction
+/// pub fn default_action(signo: usize) -> SigDefault
+/// pub struct SignalFrame
+/// pub fn signal_pending() -> bool
+/// pub fn wait_for_signal(wanted: SignalFlags, expire_ms: Option<usize>) -> bool
+/// pub fn dequeue_signal(mask: SignalFlags) -> Option<usize>
+/// pub fn should_restart_syscall() -> bool
+/// pub fn handle_signals()
+/// pub fn restore_signal_frame() -> Option<usize>
+/// pub fn send_signal(task: &Arc<TaskControlBlock>, signal: SignalFlags)
+/// pub fn send_signal_to_thread(task: &Arc<TaskControlBlock>, signal: SignalFlags)
+/// pub fn signal_group(pgid: usize, signal: SignalFlags) -> bool
+/// pub fn notify_parent(task: &Arc<TaskControlBlock>, exited: bool)
+/// ```
+///
+/// 信号在从内核返回用户态前（`trap_return`）处理：
+/// - 处理方式为 `SIG_DFL` 的信号按 `default_action` 处理
+/// - 处理方式为 `SIG_IGN` 的信号直接丢弃
+/// - 被屏蔽的信号保持待处理，直到解除屏蔽
+/// - 设置了处理函数的信号在用户栈上构造 `SignalFrame`，返回用户态后进入处理函数，
+///   处理函数返回到 `SIGRETURN_TRAMPOLINE` 处的跳板，由其调用 `rt_sigreturn` 恢复信号到来前的上下文
+///
+/// 停止信号在处理时使任务进入 `Stopped` 状态；SIGCONT 在 **发送** 时即恢复停止的任务，处理时只调用处理函数
+//
 use bitflags::*;
-use super::current_task;
+use super::task::TaskControlBlockInner;
+use super::{
+    continue_task, current_task, current_trap_cx, kill_current_and_run_next, pgid2tasks, stop_current_and_run_next,
+    tgid2tasks, wakeup_task, TaskControlBlock, INITPROC,
+};
+use crate::config::SIGRETURN_TRAMPOLINE;
+use crate::fs::POLL_QUEUE;
+use crate::mm::{copy_from_user, copy_to_user};
+use crate::timer::get_time_ms;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 bitflags! {
-    /// 进程信号
-    pub struct SignalFlags: u32 {   /// - Killed
-        const SIGINT    = 1 << 2;   /// - Illegal Instruction
-        const SIGILL    = 1 << 4;   /// - Aborted
-        const SIGABRT   = 1 << 6;   /// - Erroneous Arithmetic Operation
-        const SIGFPE    = 1 << 8;   /// - Segmentation Fault
-        const SIGKILL   = 1 << 9;
-        const SIGUSR1   = 1 << 10;
-        const SIGSEGV   = 1 << 11;
+    /// ### 信号集合
+    /// 与 Linux `sigset_t` 一致，编号为 `n` 的信号对应第 `n - 1` 位，用于待处理信号与信号屏蔽字
+    pub struct SignalFlags: u64 {
+        const SIGHUP    = 1 << 0;
+        const SIGINT    = 1 << 1;
+        const SIGQUIT   = 1 << 2;
+        const SIGILL    = 1 << 3;
+        const SIGTRAP   = 1 << 4;
+        const SIGABRT   = 1 << 5;
+        const SIGBUS    = 1 << 6;
+        const SIGFPE    = 1 << 7;
+        const SIGKILL   = 1 << 8;
+        const SIGUSR1   = 1 << 9;
+        const SIGSEGV   = 1 << 10;
+        const SIGUSR2   = 1 << 11;
+        const SIGPIPE   = 1 << 12;
+        const SIGALRM   = 1 << 13;
+        const SIGTERM   = 1 << 14;
+        const SIGSTKFLT = 1 << 15;
+        const SIGCHLD   = 1 << 16;
+        const SIGCONT   = 1 << 17;
+        const SIGSTOP   = 1 << 18;
+        const SIGTSTP   = 1 << 19;
+        const SIGTTIN   = 1 << 20;
+        const SIGTTOU   = 1 << 21;
+        const SIGURG    = 1 << 22;
+        const SIGXCPU   = 1 << 23;
+        const SIGXFSZ   = 1 << 24;
+        const SIGVTALRM = 1 << 25;
+        const SIGPROF   = 1 << 26;
+        const SIGWINCH  = 1 << 27;
+        const SIGIO     = 1 << 28;
+        const SIGPWR    = 1 << 29;
+        const SIGSYS    = 1 << 30;
+        /// 实时信号 `SIGRTMIN..=SIGRTMAX`
+        const SIGRT     = 0xFFFF_FFFF_8000_0000;
     }
 }
 
-pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
+/// 第一个实时信号的编号
+pub const SIGRTMIN: usize = 32;
+/// 最后一个实时信号的编号
+pub const SIGRTMAX: usize = 64;
+
+impl SignalFlags {
+    /// 编号为 `signo`（`1..NSIG`）的信号
+    pub fn from_signum(signo: usize) -> Self {
+        Self::from_bits_truncate(1 << (signo - 1))
+    }
+
+    /// 集合中编号最小的信号，集合为空时返回 `None`
+    pub fn first(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits().trailing_zeros() as usize + 1)
+        }
+    }
+
+    /// 不能被屏蔽、忽略或捕获的信号
+    pub fn unblockable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+
+    /// 默认处理方式为停止进程的信号
+    pub fn stop_signals() -> Self {
+        Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU
+    }
+}
+
+/// ### 信号的默认处理方式
+#[derive(Copy, Clone, PartialEq)]
+pub enum SigDefault {
+    /// 终止进程
+    Terminate,
+    /// 终止进程并转储内存（内核不生成 core 文件，与 `Terminate` 相同）
+    CoreDump,
+    /// 忽略
+    Ignore,
+    /// 停止进程
+    Stop,
+    /// 继续运行已停止的进程
+    Continue,
+}
+
+/// ### 编号为 `signo` 的信号的默认处理方式
+pub fn default_action(signo: usize) -> SigDefault {
+    match SignalFlags::from_signum(signo) {
+        SignalFlags::SIGQUIT
+        | SignalFlags::SIGILL
+        | SignalFlags::SIGTRAP
+        | SignalFlags::SIGABRT
+        | SignalFlags::SIGBUS
+        | SignalFlags::SIGFPE
+        | SignalFlags::SIGSEGV
+        | SignalFlags::SIGXCPU
+        | SignalFlags::SIGXFSZ
+        | SignalFlags::SIGSYS => SigDefault::CoreDump,
+        SignalFlags::SIGCHLD | SignalFlags::SIGURG | SignalFlags::SIGWINCH => SigDefault::Ignore,
+        SignalFlags::SIGSTOP | SignalFlags::SIGTSTP | SignalFlags::SIGTTIN | SignalFlags::SIGTTOU => SigDefault::Stop,
+        SignalFlags::SIGCONT => SigDefault::Continue,
+        _ => SigDefault::Terminate,
+    }
+}
+
+/// 每个任务最多排队的实时信号实例数，超出后新的实例被丢弃
+const RT_QUEUE_MAX: usize = 1024;
+
+/// ### 待处理信号
+/// |成员变量|描述|
+/// |--|--|
+/// |`signals`|待处理的信号集合，同一标准信号多次到达只记录一次|
+/// |`rt_queue`|按到达顺序排队的实时信号编号，同一实时信号的每次到达都占一项|
+#[derive(Clone)]
+pub struct SigPending {
+    pub signals: SignalFlags,
+    rt_queue: VecDeque<usize>,
+}
+
+impl SigPending {
+    pub fn new() -> Self {
+        Self {
+            signals: SignalFlags::empty(),
+            rt_queue: VecDeque::new(),
+        }
+    }
+
+    /// 加入 `signal` 中的全部信号，实时信号各排队一个实例
+    pub fn add(&mut self, signal: SignalFlags) {
+        let mut rt = (signal & SignalFlags::SIGRT).bits();
+        while rt != 0 && self.rt_queue.len() < RT_QUEUE_MAX {
+            let signo = rt.trailing_zeros() as usize + 1;
+            self.rt_queue.push_back(signo);
+            rt &= rt - 1;
+        }
+        self.signals |= signal;
+    }
+
+    /// 取出编号为 `signo` 的信号的一个实例，实时信号还有其他实例排队时仍保持待处理
+    pub fn take(&mut self, signo: usize) {
+        if signo >= SIGRTMIN {
+            if let Some(index) = self.rt_queue.iter().position(|&queued| queued == signo) {
+                self.rt_queue.remove(index);
+            }
+            if self.rt_queue.contains(&signo) {
+                return;
+            }
+        }
+        self.signals.remove(SignalFlags::from_signum(signo));
+    }
+
+    /// 丢弃待处理的标准信号，实时信号不受影响
+    pub fn discard(&mut self, signal: SignalFlags) {
+        self.signals.remove(signal - SignalFlags::SIGRT);
+    }
+}
+
+/// 信号处理函数表的大小，信号编号为 `1..NSIG`
+pub const NSIG: usize = SIGRTMAX + 1;
+
+/// 默认处理方式
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// `sigaction` 的 `sa_flags`
+    pub struct SigActionFlags: usize {
+        const SA_NOCLDSTOP = 0x0000_0001;
+        const SA_NOCLDWAIT = 0x0000_0002;
+        /// 处理函数接收 `siginfo` 与 `ucontext` 两个额外参数
+        const SA_SIGINFO   = 0x0000_0004;
+        /// 跳板由用户提供，内核总是使用 `SIGRETURN_TRAMPOLINE`，忽略该标志
+        const SA_RESTORER  = 0x0400_0000;
+        /// 在备用信号栈上执行处理函数
+        const SA_ONSTACK   = 0x0800_0000;
+        /// 被信号打断的系统调用在处理函数返回后自动重新执行
+        const SA_RESTART   = 0x1000_0000;
+        /// 执行处理函数期间不屏蔽该信号本身
+        const SA_NODEFER   = 0x4000_0000;
+        /// 处理函数执行一次后恢复为 `SIG_DFL`
+        const SA_RESETHAND = 0x8000_0000;
+    }
+}
+
+/// ### 信号处理方式（Linux `struct sigaction`，RISC-V 上没有 `sa_restorer` 成员）
+/// |成员变量|描述|
+/// |--|--|
+/// |`sa_handler`|`SIG_DFL`、`SIG_IGN` 或处理函数的地址|
+/// |`sa_flags`|`SigActionFlags`|
+/// |`sa_mask`|执行处理函数期间额外屏蔽的信号|
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SigAction {
+    pub sa_handler: usize,
+    pub sa_flags: usize,
+    pub sa_mask: u64,
+}
+
+impl SigAction {
+    pub const fn new() -> Self {
+        Self {
+            sa_handler: SIG_DFL,
+            sa_flags: 0,
+            sa_mask: 0,
+        }
+    }
+
+    pub fn flags(&self) -> SigActionFlags {
+        SigActionFlags::from_bits_truncate(self.sa_flags)
+    }
+}
+
+/// 正在备用信号栈上执行
+pub const SS_ONSTACK: i32 = 1;
+/// 备用信号栈未启用
+pub const SS_DISABLE: i32 = 2;
+/// 备用信号栈的最小大小
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// ### 信号栈（Linux `stack_t`）
+/// 用于 `sigaltstack` 设置的备用信号栈，以及信号栈帧中的 `uc_stack`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalStack {
+    pub ss_sp: usize,
+    pub ss_flags: i32,
+    pub ss_size: usize,
+}
+
+impl SignalStack {
+    pub const fn disabled() -> Self {
+        Self {
+            ss_sp: 0,
+            ss_flags: SS_DISABLE,
+            ss_size: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ss_flags & SS_DISABLE == 0
+    }
+
+    /// 栈指针 `sp` 是否位于该信号栈上
+    pub fn contains(&self, sp: usize) -> bool {
+        self.is_enabled() && sp > self.ss_sp && sp <= self.ss_sp + self.ss_size
+    }
+}
+
+/// ### 信号信息（Linux `siginfo_t`，共 128 字节）
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: [i32; 29],
+}
+
+impl SigInfo {
+    pub const fn new(signo: usize) -> Self {
+        Self {
+            si_signo: signo as i32,
+            si_errno: 0,
+            si_code: 0,
+            _pad: [0; 29],
+        }
+    }
+}
+
+/// ### 机器上下文（Linux RISC-V `struct sigcontext`）
+/// `gregs[0]` 为 pc，`gregs[1..32]` 为 `x1..x31`；内核不保存浮点寄存器，`fpregs` 总为 0
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub struct MContext {
+    pub gregs: [usize; 32],
+    pub fpregs: [u64; 66],
+}
+
+/// ### 用户上下文（Linux RISC-V `struct ucontext`）
+/// `uc_sigmask` 之后预留了扩展空间，与用户态 `ucontext_t` 中 128 字节的 `sigset_t` 对齐
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct UContext {
+    pub uc_flags: usize,
+    pub uc_link: usize,
+    pub uc_stack: SignalStack,
+    pub uc_sigmask: u64,
+    _unused: [u8; 120],
+    pub uc_mcontext: MContext,
+}
+
+/// ### 构造在用户栈上的信号栈帧（Linux `struct rt_sigframe`）
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalFrame {
+    pub info: SigInfo,
+    pub ucontext: UContext,
+}
+
+/// ### 信号按当前的处理方式是否会被直接丢弃
+fn is_ignored(action: &SigAction, signo: usize) -> bool {
+    match action.sa_handler {
+        SIG_IGN => true,
+        // SIGCONT 在发送时已经恢复了任务
+        SIG_DFL => matches!(default_action(signo), SigDefault::Ignore | SigDefault::Continue),
+        _ => false,
+    }
+}
+
+/// ### 下一个应当处理的信号的编号（不从待处理信号中移除）
+/// 编号小的信号优先，被屏蔽的信号不处理
+fn next_signal(inner: &TaskControlBlockInner) -> Option<usize> {
+    (inner.pending_signals() - inner.sigmask).first()
+}
+
+/// ### 当前任务是否有未被屏蔽、且不会被忽略的待处理信号
+/// 阻塞在等待队列上的任务据此提前返回，以便回到用户态处理信号
+pub fn signal_pending() -> bool {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let mut deliverable = inner.pending_signals() - inner.sigmask;
+    let sigactions = inner.sigactions.lock();
+    while let Some(signo) = deliverable.first() {
+        if !is_ignored(&sigactions[signo], signo) {
+            return true;
+        }
+        deliverable.remove(SignalFlags::from_signum(signo));
+    }
+    false
+}
+
+/// ### 阻塞当前任务，直到有待处理的信号或到达 `expire_ms`（CPU 上电时间，毫秒）
+/// - 参数
+///     - `wanted`：除了 `signal_pending` 的信号外，这些信号到达时也返回，不论其是否被屏蔽或忽略
+///     - `expire_ms`：为 `None` 时不会超时
+/// - 返回值：因信号返回 `true`，超时返回 `false`
+pub fn wait_for_signal(wanted: SignalFlags, expire_ms: Option<usize>) -> bool {
+    // `wanted` 中的信号通常被屏蔽，发送时不会直接唤醒任务，因此在发送信号时总会唤醒的 POLL_QUEUE 上等待
+    loop {
+        let task = current_task().unwrap();
+        if task.inner_exclusive_access().pending_signals().intersects(wanted) {
+            return true;
+        }
+        drop(task);
+        let ret = match expire_ms {
+            Some(expire_ms) if get_time_ms() >= expire_ms => return false,
+            Some(expire_ms) => POLL_QUEUE.wait_timeout(expire_ms),
+            None => POLL_QUEUE.wait(),
+        };
+        if ret < 0 {
+            return true;
+        }
+    }
+}
+
+/// ### 从当前任务的待处理信号中取出 `mask` 中编号最小的一个
+/// 不论其是否被屏蔽，用于 `rt_sigtimedwait` 与 signalfd 同步地接收信号
+pub fn dequeue_signal(mask: SignalFlags) -> Option<usize> {
     let task = current_task().unwrap();
-    let task_inner = task.inner_exclusive_access();
-    match task_inner.signals{
-        SignalFlags::SIGINT => Some((-2, "Killed, SIGINT=2")),
-        SignalFlags::SIGILL => Some((-4, "Illegal Instruction, SIGILL=4")),
-        SignalFlags::SIGABRT=> Some((-6, "Aborted, SIGABRT=6")),
-        SignalFlags::SIGFPE => Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8")),
-        SignalFlags::SIGKILL=> Some((-9, "Kill, SIGKILL=9")),
-        SignalFlags::SIGSEGV=> Some((-11, "Segmentation Fault, SIGSEGV=11")),
-        _ => None
+    let mut inner = task.inner_exclusive_access();
+    let signo = (inner.pending_signals() & mask).first()?;
+    inner.take_signal(signo);
+    Some(signo)
+}
+
+/// ### 被信号打断（返回 `-ERESTARTSYS`）的系统调用是否应当重新执行
+/// 即将处理的信号设置了处理函数但没有 `SA_RESTART` 时返回 `false`，此时系统调用返回 `-EINTR`；
+/// 信号被忽略或将终止进程时总是重新执行
+pub fn should_restart_syscall() -> bool {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let signo = match next_signal(&inner) {
+        Some(signo) => signo,
+        None => return true,
+    };
+    let action = inner.sigactions.lock()[signo];
+    match action.sa_handler {
+        SIG_DFL | SIG_IGN => true,
+        _ => action.flags().contains(SigActionFlags::SA_RESTART),
+    }
+}
+
+/// ### 返回用户态前处理当前任务的待处理信号
+/// 默认处理方式为终止的信号终止当前进程，不会返回；
+/// 一次只为一个信号构造栈帧，其余信号在 `rt_sigreturn` 返回用户态时继续处理
+pub fn handle_signals() {
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        let signo = match next_signal(&inner) {
+            Some(signo) => signo,
+            None => {
+                // sigsuspend 等待到的信号没有构造栈帧，在此恢复原来的屏蔽字
+                if let Some(mask) = inner.saved_sigmask.take() {
+                    inner.sigmask = mask;
+                }
+                return;
+            }
+        };
+        inner.take_signal(signo);
+        let action = inner.sigactions.lock()[signo];
+        drop(inner);
+        if is_ignored(&action, signo) {
+            continue;
+        }
+        if action.sa_handler == SIG_DFL && default_action(signo) == SigDefault::Stop {
+            group_stop(&task, signo);
+            drop(task);
+            // 被 SIGCONT 或 SIGKILL 恢复后继续处理其余信号
+            stop_current_and_run_next();
+            continue;
+        }
+        if action.sa_handler == SIG_DFL {
+            drop(task);
+            kill_current_and_run_next(signo);
+            return;
+        }
+        if !setup_signal_frame(&task, signo, &action) {
+            // 无法在用户栈上构造栈帧，以 SIGSEGV 终止进程
+            drop(task);
+            kill_current_and_run_next(SignalFlags::SIGSEGV.first().unwrap());
+        }
+        return;
+    }
+}
+
+/// ### 当前线程因停止信号 `signo` 停止，第一个停止的线程使整个线程组停止
+/// 停止状态记录在主线程上，父进程的 `wait4(WUNTRACED)` 只检查主线程；
+/// 其余线程收到 SIGSTOP，返回用户态前随之停止
+fn group_stop(task: &Arc<TaskControlBlock>, signo: usize) {
+    let thread_group = task.inner_exclusive_access().thread_group.clone();
+    let mut group = thread_group.lock();
+    if group.stopped {
+        return;
+    }
+    group.stopped = true;
+    let leader = if task.is_thread() { group.leader.upgrade() } else { Some(task.clone()) };
+    drop(group);
+    for thread in tgid2tasks(task.tgid) {
+        if !Arc::ptr_eq(&thread, task) {
+            send_signal_to_thread(&thread, SignalFlags::SIGSTOP);
+        }
+    }
+    if let Some(leader) = leader {
+        let mut inner = leader.inner_exclusive_access();
+        inner.wait_stopped = Some(signo);
+        inner.wait_continued = false;
+        drop(inner);
+        notify_parent(&leader, false);
+    }
+}
+
+/// ### 在用户栈上构造信号栈帧，并修改 Trap 上下文使返回用户态时进入处理函数
+/// - 返回值：用户栈不可写时返回 `false`
+///
+/// 设置了 `SA_ONSTACK` 且启用了备用信号栈时，在备用信号栈上构造（已经在备用信号栈上时接着向下构造），否则在当前栈上构造。
+/// 栈帧中保存的屏蔽字在 sigsuspend 期间为调用 sigsuspend 前的屏蔽字
+fn setup_signal_frame(task: &Arc<TaskControlBlock>, signo: usize, action: &SigAction) -> bool {
+    let mut inner = task.inner_exclusive_access();
+    let token = inner.get_user_token();
+    let cur_mask = inner.sigmask;
+    let old_mask = inner.saved_sigmask.take().unwrap_or(cur_mask);
+    let altstack = inner.sigaltstack;
+    let flags = action.flags();
+    let mut new_mask = cur_mask | SignalFlags::from_bits_truncate(action.sa_mask);
+    if !flags.contains(SigActionFlags::SA_NODEFER) {
+        new_mask |= SignalFlags::from_signum(signo);
+    }
+    if flags.contains(SigActionFlags::SA_RESETHAND) {
+        inner.sigactions.lock()[signo] = SigAction::new();
+    }
+    drop(inner);
+
+    let trap_cx = current_trap_cx();
+    let sp = trap_cx.x[2];
+    let on_altstack = altstack.contains(sp);
+    let mut frame = SignalFrame {
+        info: SigInfo::new(signo),
+        ucontext: UContext {
+            uc_flags: 0,
+            uc_link: 0,
+            uc_stack: SignalStack {
+                ss_flags: if on_altstack { SS_ONSTACK } else { altstack.ss_flags },
+                ..altstack
+            },
+            uc_sigmask: old_mask.bits(),
+            _unused: [0; 120],
+            uc_mcontext: MContext {
+                gregs: trap_cx.x,
+                fpregs: [0; 66],
+            },
+        },
+    };
+    frame.ucontext.uc_mcontext.gregs[0] = trap_cx.sepc;
+    let stack_top = if flags.contains(SigActionFlags::SA_ONSTACK) && altstack.is_enabled() && !on_altstack {
+        altstack.ss_sp + altstack.ss_size
+    } else {
+        sp
+    };
+    // 栈帧按 16 字节对齐
+    let frame_va = match stack_top.checked_sub(core::mem::size_of::<SignalFrame>()) {
+        Some(va) => va & !0xf,
+        None => return false,
+    };
+    if copy_to_user(token, frame_va as *mut SignalFrame, &frame).is_err() {
+        return false;
+    }
+    task.inner_exclusive_access().sigmask = new_mask - SignalFlags::unblockable();
+
+    trap_cx.sepc = action.sa_handler;
+    trap_cx.x[1] = SIGRETURN_TRAMPOLINE; // ra
+    trap_cx.x[2] = frame_va; // sp
+    trap_cx.x[10] = signo; // a0
+    if flags.contains(SigActionFlags::SA_SIGINFO) {
+        trap_cx.x[11] = frame_va; // a1: &frame.info
+        trap_cx.x[12] = frame_va + core::mem::size_of::<SigInfo>(); // a2: &frame.ucontext
     }
+    true
 }
 
+/// ### 从用户栈上的信号栈帧恢复信号到来前的上下文，由 `rt_sigreturn` 调用
+/// - 返回值：恢复后的 `a0`，栈帧不可读时返回 `None`
+///
+/// 处理函数返回时栈指针已恢复到栈帧起始处
+pub fn restore_signal_frame() -> Option<usize> {
+    let task = current_task().unwrap();
+    let token = task.inner_exclusive_access().get_user_token();
+    let trap_cx = current_trap_cx();
+    let frame: SignalFrame = copy_from_user(token, trap_cx.x[2] as *const SignalFrame).ok()?;
+    let gregs = &frame.ucontext.uc_mcontext.gregs;
+    trap_cx.x[1..].copy_from_slice(&gregs[1..]);
+    trap_cx.sepc = gregs[0];
+    task.inner_exclusive_access().sigmask = SignalFlags::from_bits_truncate(frame.ucontext.uc_sigmask) - SignalFlags::unblockable();
+    Some(trap_cx.x[10])
+}
+
+/// 向当前线程发送信号，用于异常处理等内核产生的、只能由当前线程处理的信号
 pub fn current_add_signal(signal: SignalFlags) {
     let task = current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
-    task_inner.signals |= signal;
-}
-
-// pub const SIGHUP: u32 = 1;
-// pub const SIGINT: u32 = 2;
-// pub const SIGQUIT: u32 = 3;
-// pub const SIGILL: u32 = 4;
-// pub const SIGTRAP: u32 = 5;
-// pub const SIGABRT: u32 = 6;
-// pub const SIGIOT: u32 = SIGABRT;
-// pub const SIGBUS: u32 = 7;
-// pub const SIGFPE: u32 = 8;
-// pub const SIGKILL: u32 = 9;
-// pub const SIGUSR1: u32 = 10;
-// pub const SIGSEGV: u32 = 11;
-// pub const SIGUSR2: u32 = 12;
-// pub const SIGPIPE: u32 = 13;
-// pub const SIGALRM: u32 = 14;
-// pub const SIGTERM: u32 = 15;
-// pub const SIGSTKFLT: u32 = 16;
-// pub const SIGCHLD: u32 = 17;
-// pub const SIGCONT: u32 = 18;
-// pub const SIGSTOP: u32 = 19;
-// pub const SIGTSTP: u32 = 20;
-// pub const SIGTTIN: u32 = 21;
-// pub const SIGTTOU: u32 = 22;
-// pub const SIGURG: u32 = 23;
-// pub const SIGXCPU: u32 = 24;
-// pub const SIGXFSZ: u32 = 25;
-// pub const SIGVTALRM: u32 = 26;
-// pub const SIGPROF: u32 = 27;
-// pub const SIGWINCH: u32 = 28;
-// pub const SIGIO: u32 = 29;
-// pub const SIGPOLL: u32 = SIGIO;
-// pub const SIGPWR: u32 = 30;
-// pub const SIGSYS: u32 = 31;
-// pub const SIGUNUSED: u32 = SIGSYS;
+    task_inner.pending.add(signal);
+}
 
-pub const SIG_BLOCK: i32 = 0;
-pub const SIG_UNBLOCK: i32 = 1;
-pub const SIG_SETMASK: i32 = 2;
+/// SIGCONT 丢弃待处理的停止信号，停止信号丢弃待处理的 SIGCONT
+fn discard_conflicting(pending: &mut SigPending, signal: SignalFlags) {
+    if signal.contains(SignalFlags::SIGCONT) {
+        pending.discard(SignalFlags::stop_signals());
+    }
+    if signal.intersects(SignalFlags::stop_signals()) {
+        pending.discard(SignalFlags::SIGCONT);
+    }
+}
 
-// pub const SIGSET_LEN: usize = 1024 / (8 * (core::mem::size_of::<usize>()));
+/// ### 唤醒收到信号的线程
+/// 线程处于阻塞态、且没有屏蔽该信号、也不会忽略它时将其唤醒，使其能够处理信号；SIGCONT 与 SIGKILL 恢复停止的线程
+/// - 返回值：线程因 SIGCONT 从停止状态恢复时返回 `true`
+fn wake_for_signal(task: &Arc<TaskControlBlock>, signal: SignalFlags) -> bool {
+    if signal.intersects(SignalFlags::SIGCONT | SignalFlags::SIGKILL) && continue_task(task.clone()) {
+        return signal.contains(SignalFlags::SIGCONT);
+    }
+    let inner = task.inner_exclusive_access();
+    let deliverable = match (signal - inner.sigmask).first() {
+        Some(signo) => !is_ignored(&inner.sigactions.lock()[signo], signo),
+        None => false,
+    };
+    drop(inner);
+    if deliverable {
+        wakeup_task(task.clone());
+    }
+    false
+}
 
-pub struct SigSet {
-    // pub bits: [usize; SIGSET_LEN],
-    pub bits: [u8; 128],
+/// ### 停止的任务被 SIGCONT 恢复后，在主线程上记录状态变化并通知父进程
+fn report_continued(task: &Arc<TaskControlBlock>) {
+    let thread_group = task.inner_exclusive_access().thread_group.clone();
+    let mut group = thread_group.lock();
+    group.stopped = false;
+    let leader = if task.is_thread() { group.leader.upgrade() } else { Some(task.clone()) };
+    drop(group);
+    let leader = match leader {
+        Some(leader) => leader,
+        None => return,
+    };
+    let mut inner = leader.inner_exclusive_access();
+    inner.wait_stopped = None;
+    inner.wait_continued = true;
+    drop(inner);
+    notify_parent(&leader, false);
 }
 
-impl SigSet {
-    pub fn new() -> Self {
-        Self { bits: [0; 128] }
+/// ### 向进程发送信号
+/// 信号加入 `task` 所在线程组共享的待处理信号，由任意一个没有屏蔽它的线程处理，线程组中的全部线程都会被唤醒
+/// - SIGCONT 丢弃待处理的停止信号，并恢复停止的线程；停止信号丢弃待处理的 SIGCONT
+/// - SIGKILL 也会恢复停止的线程，使其能够退出
+pub fn send_signal(task: &Arc<TaskControlBlock>, signal: SignalFlags) {
+    let threads = tgid2tasks(task.tgid);
+    for thread in threads.iter() {
+        discard_conflicting(&mut thread.inner_exclusive_access().pending, signal);
+    }
+    let thread_group = task.inner_exclusive_access().thread_group.clone();
+    let mut group = thread_group.lock();
+    discard_conflicting(&mut group.pending, signal);
+    group.pending.add(signal);
+    // 尚未处理的停止信号已被丢弃，之后的停止信号重新开始一次线程组停止
+    if signal.contains(SignalFlags::SIGCONT) {
+        group.stopped = false;
     }
+    drop(group);
+    // signalfd 的读者与 pselect 在 POLL_QUEUE 上等待，它们等待的信号通常被屏蔽，不会被下面的唤醒打断
+    POLL_QUEUE.wake_all();
+    let mut continued = false;
+    for thread in threads.iter() {
+        continued |= wake_for_signal(thread, signal);
+    }
+    if continued {
+        report_continued(task);
+    }
+}
+
+/// ### 向线程发送信号
+/// 信号只能由该线程处理，用于 `tkill`/`tgkill` 与 `SIGEV_THREAD_ID` 定时器；其余同 `send_signal`
+pub fn send_signal_to_thread(task: &Arc<TaskControlBlock>, signal: SignalFlags) {
+    let mut inner = task.inner_exclusive_access();
+    discard_conflicting(&mut inner.pending, signal);
+    inner.pending.add(signal);
+    drop(inner);
+    POLL_QUEUE.wake_all();
+    if wake_for_signal(task, signal) {
+        report_continued(task);
+    }
+}
+
+/// ### 向进程组 `pgid` 中的全部进程发送信号
+/// - 返回值：进程组中没有进程时返回 `false`
+///
+/// `signal` 为空时只检查进程组是否存在；initproc 不会收到发给进程组的信号
+pub fn signal_group(pgid: usize, signal: SignalFlags) -> bool {
+    let members: Vec<_> = pgid2tasks(pgid).into_iter().filter(|member| !Arc::ptr_eq(member, &INITPROC)).collect();
+    if !signal.is_empty() {
+        for member in members.iter() {
+            send_signal(member, signal);
+        }
+    }
+    !members.is_empty()
 }
+
+/// ### 子进程退出、停止或继续运行时通知父进程
+/// - 参数
+///     - `task`：状态发生变化的任务，调用时不能持有其锁
+///     - `exited`：是否为退出，停止 / 继续运行时父进程对 SIGCHLD 设置了 `SA_NOCLDSTOP` 则不发送信号
+///
+/// 总是唤醒在 waitpid 中等待的父进程；线程没有父进程回收，不发送 SIGCHLD
+pub fn notify_parent(task: &Arc<TaskControlBlock>, exited: bool) {
+    let parent = task.inner_exclusive_access().parent.as_ref().and_then(|parent| parent.upgrade());
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return,
+    };
+    if !task.is_thread() {
+        let signo = SignalFlags::SIGCHLD.first().unwrap();
+        let action = parent.inner_exclusive_access().sigactions.lock()[signo];
+        if exited || !action.flags().contains(SigActionFlags::SA_NOCLDSTOP) {
+            send_signal(&parent, SignalFlags::SIGCHLD);
+        }
+    }
+    parent.child_exit.wake_all();
+}
+
+/// `rt_sigprocmask` 的 `how` 参数
+pub const SIG_BLOCK: i32 = 0;
+pub const SIG_UNBLOCK: i32 = 1;
+pub const SIG_SETMASK: i32 = 2;
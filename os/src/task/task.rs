@@ -1,25 +1,102 @@
-use super::signal::SigSet;
-use super::{aux, RLimit, TaskContext, AT_RANDOM, RESOURCE_KIND_NUMBER};
-use super::{pid_alloc, KernelStack, PidHandle, SignalFlags};
+use super::{aux, CloneFlags, RLimit, TaskContext, AT_RANDOM, RESOURCE_KIND_NUMBER};
+use super::{pid_alloc, trap_cx_position, CpuTimer, KernelStack, PidHandle, RealTimer, RealTimerSlot, SignalFlags, WaitQueue};
+use super::{SigAction, SigPending, SignalStack, NSIG, SIG_IGN};
+use super::{tgid2tasks, ITimerVal, TaskUsage, ITIMER_REAL, ITIMER_VIRTUAL};
 use crate::config::*;
 use crate::fs::{File, Stdin, Stdout, OSInode};
 use crate::mm::{translated_refmut, MapPermission, MemorySet, MmapArea, PhysPageNum, VirtAddr, KERNEL_SPACE, VirtPageNum, PageTableEntry, MmapFlags, MmapProts};
+use crate::mm::{copy_to_user, release_shared_pages, shared_page};
 use spin::{Mutex, MutexGuard};
+use crate::syscall::errno::{EACCES, EBADF, EINVAL, ENODEV, ENOMEM};
+use crate::timer::{get_time_us, TimeVal};
 use crate::trap::{trap_handler, TrapContext};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
 
 pub const FD_LIMIT: usize = 128;
 
+/// ### 文件描述符表
+/// - 通过 `Deref` 当作 `Vec<Option<Arc<dyn File + Send + Sync>>>` 使用
+/// - 以 `CLONE_FILES` 创建的任务共享同一张表
+#[derive(Clone)]
+pub struct FdTable(Vec<Option<Arc<dyn File + Send + Sync>>>);
+
+impl FdTable {
+    pub fn new(fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>) -> Self {
+        Self(fd_table)
+    }
+
+    /// ### 查找空闲文件描述符下标
+    /// 从文件描述符表中 **由低到高** 查找空位，返回向量下标，没有空位则在最后插入一个空位
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.len()).find(|fd| self[*fd].is_none()) {
+            fd
+        } else {
+            if self.len() == FD_LIMIT {
+                return FD_LIMIT;
+            }
+            self.push(None);
+            self.len() - 1
+        }
+    }
+}
+
+impl Deref for FdTable {
+    type Target = Vec<Option<Arc<dyn File + Send + Sync>>>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for FdTable {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// ### 线程组共享的状态
+/// |成员变量|描述|
+/// |--|--|
+/// |`live`|尚未退出的线程数，为 0 时主线程才能被父进程回收|
+/// |`exit_code`|`exit_group` 或致命信号设置的线程组退出状态，`None` 表示线程组没有整体退出|
+/// |`leader`|主线程，最后一个退出的线程借此向父进程报告|
+/// |`pending`|发给整个进程的待处理信号，由任意一个没有屏蔽它的线程处理|
+/// |`exited_usage`|已经退出的线程（包括主线程）的资源使用之和|
+/// |`stopped`|线程组是否因停止信号停止，停止后收到 SIGCONT 之前其余线程停止时不再通知父进程|
+pub struct ThreadGroup {
+    pub live: usize,
+    pub exit_code: Option<i32>,
+    pub leader: Weak<TaskControlBlock>,
+    pub pending: SigPending,
+    pub exited_usage: TaskUsage,
+    pub stopped: bool,
+}
+
+impl ThreadGroup {
+    pub fn new() -> Self {
+        Self {
+            live: 1,
+            exit_code: None,
+            leader: Weak::new(),
+            pending: SigPending::new(),
+            exited_usage: TaskUsage::default(),
+            stopped: false,
+        }
+    }
+}
+
 pub struct TaskControlBlock {
-    /// 进程标识符
+    /// 进程标识符，对线程而言即线程号 tid
     pub pid: PidHandle,
-    /// thread group id
+    /// 线程组号，即线程组中主线程的 pid，`getpid` 返回的就是它
     pub tgid: usize,
     /// 应用内核栈
     pub kernel_stack: KernelStack,
+    /// 子进程退出时唤醒，`waitpid` 在其上等待
+    pub child_exit: WaitQueue,
     inner: Mutex<TaskControlBlockInner>,
 }
 
@@ -27,6 +104,8 @@ pub struct TaskControlBlockInner {
     // 进程
     /// 应用地址空间中的 Trap 上下文所在的物理页帧的物理页号
     pub trap_cx_ppn: PhysPageNum,
+    /// Trap 上下文在应用地址空间中的虚拟地址，主线程为 `TRAP_CONTEXT`，其余线程各占一页
+    pub trap_cx_va: usize,
     /// 任务上下文
     pub task_cx: TaskContext,
     /// 维护当前进程的执行状态
@@ -35,29 +114,65 @@ pub struct TaskControlBlockInner {
     pub parent: Option<Weak<TaskControlBlock>>,
     /// 当前进程的所有子进程的任务控制块向量
     pub children: Vec<Arc<TaskControlBlock>>,
-    /// 退出码
+    /// 退出状态，按 wait 状态字编码：正常退出为 `(退出码 & 0xff) << 8`，被信号终止为信号编号（产生 core dump 时再或上 0x80）
     pub exit_code: i32,
+    /// 进程组号，fork 时继承
+    pub pgid: usize,
+    /// 会话号，fork 时继承
+    pub sid: usize,
+    /// 线程组共享的状态，以 `CLONE_THREAD` 创建的任务共享
+    pub thread_group: Arc<Mutex<ThreadGroup>>,
+    /// 使进程停止的信号，尚未被父进程通过 waitpid（`WUNTRACED`）获取时为 `Some`
+    pub wait_stopped: Option<usize>,
+    /// 进程被 SIGCONT 恢复运行，尚未被父进程通过 waitpid（`WCONTINUED`）获取
+    pub wait_continued: bool,
+    /// 退出时需要清零并唤醒 futex 的用户地址（`CLONE_CHILD_CLEARTID` / `set_tid_address`），0 表示未设置
+    pub clear_child_tid: usize,
 
     // 内存
     /// 应用数据仅有可能出现在应用地址空间低于 base_size 字节的区域中。
     /// 借助它我们可以清楚的知道应用有多少数据驻留在内存中
     pub base_size: usize,
-    /// 应用地址空间
-    pub memory_set: MemorySet,
-    // 虚拟内存地址映射空间
-    pub mmap_area: MmapArea,
+    /// 应用地址空间，以 `CLONE_VM` 创建的任务共享
+    pub memory_set: Arc<Mutex<MemorySet>>,
+    // 虚拟内存地址映射空间，与 memory_set 一同共享
+    pub mmap_area: Arc<Mutex<MmapArea>>,
 
     // 文件
-    /// 文件描述符表
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// 文件描述符表，以 `CLONE_FILES` 创建的任务共享
+    pub fd_table: Arc<Mutex<FdTable>>,
 
     // 状态信息
-    pub signals: SignalFlags,
+    /// 发给该线程的待处理信号，发给整个进程的在 `thread_group` 中
+    pub pending: SigPending,
     pub current_path: String,
 
-    // 决赛添加：信号集
-    pub sigset: SigSet,
+    /// 信号屏蔽字，fork 时继承
+    pub sigmask: SignalFlags,
+    /// `sigsuspend` 临时替换屏蔽字前的屏蔽字，处理完等待到的信号后恢复
+    pub saved_sigmask: Option<SignalFlags>,
+    /// 备用信号栈，fork 时继承，exec 后取消
+    pub sigaltstack: SignalStack,
+    /// 信号处理函数表，以 `CLONE_SIGHAND` 创建的任务共享
+    pub sigactions: Arc<Mutex<[SigAction; NSIG]>>,
     pub resource: [RLimit; RESOURCE_KIND_NUMBER],
+
+    // 时间
+    /// 资源使用统计
+    pub usage: TaskUsage,
+    /// 已回收子进程的资源使用统计之和
+    pub children_usage: TaskUsage,
+    /// 上次统计 CPU 时间的时刻（微秒）
+    pub last_time_us: usize,
+    pub itimer_virtual: CpuTimer,
+    pub itimer_prof: CpuTimer,
+    pub itimer_real: RealTimer,
+    /// `timer_create` 创建的 POSIX 定时器，下标即定时器 ID
+    pub posix_timers: Vec<Option<RealTimer>>,
+
+    // 调试
+    /// 是否打印该进程的系统调用（通过 `/proc/<pid>/trace` 开关），fork 时继承
+    pub trace: bool,
 }
 
 impl TaskControlBlockInner {
@@ -66,7 +181,7 @@ impl TaskControlBlockInner {
     }
     /// 获取用户地址空间的 token (符合 satp CSR 格式要求的多级页表的根节点所在的物理页号)
     pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+        self.memory_set.lock().token()
     }
     fn get_status(&self) -> TaskStatus {
         self.task_status
@@ -74,34 +189,98 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
-    /// ### 查找空闲文件描述符下标
-    /// 从文件描述符表中 **由低到高** 查找空位，返回向量下标，没有空位则在最后插入一个空位
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
+    /// 线程组中的全部线程都已退出，主线程可以被父进程回收
+    pub fn group_exited(&self) -> bool {
+        self.thread_group.lock().live == 0
+    }
+    /// 该线程可以处理的待处理信号，包括发给该线程的与发给整个进程的
+    pub fn pending_signals(&self) -> SignalFlags {
+        self.pending.signals | self.thread_group.lock().pending.signals
+    }
+    /// 取出编号为 `signo` 的信号的一个实例，优先取发给该线程的
+    pub fn take_signal(&mut self, signo: usize) {
+        if self.pending.signals.contains(SignalFlags::from_signum(signo)) {
+            self.pending.take(signo);
         } else {
-            if self.fd_table.len() == FD_LIMIT {
-                return FD_LIMIT;
-            }
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+            self.thread_group.lock().pending.take(signo);
         }
     }
-
     pub fn get_work_path(&self) -> &str {
         self.current_path.as_str()
     }
     
     pub fn enquire_pte_via_vpn(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.memory_set.translate(vpn)
+        self.memory_set.lock().translate(vpn)
     }
 
     pub fn cow_alloc(&mut self, vpn: VirtPageNum, former_ppn: PhysPageNum) -> isize {
-        self.memory_set.cow_alloc(vpn, former_ppn)
+        self.memory_set.lock().cow_alloc(vpn, former_ppn)
     }
 
     pub fn lazy_alloc_heap(&mut self, vpn: VirtPageNum) -> isize {
-        self.memory_set.lazy_alloc_heap(vpn)
+        self.memory_set.lock().lazy_alloc_heap(vpn)
+    }
+
+    /// 更新统计时刻，返回距上次统计经过的时间（微秒）
+    fn update_time_stamp(&mut self) -> usize {
+        let current_us = get_time_us();
+        let delta = current_us.saturating_sub(self.last_time_us);
+        self.last_time_us = current_us;
+        delta
+    }
+
+    /// ### 统计用户态 CPU 时间，从用户态进入内核态时调用
+    /// 同时推进 `ITIMER_VIRTUAL`/`ITIMER_PROF`，到期时产生对应信号
+    pub fn account_user_time(&mut self) {
+        let delta = self.update_time_stamp();
+        self.usage.utime_us += delta;
+        if self.itimer_virtual.tick(delta) {
+            self.pending.add(SignalFlags::SIGVTALRM);
+        }
+        if self.itimer_prof.tick(delta) {
+            self.pending.add(SignalFlags::SIGPROF);
+        }
+    }
+
+    /// ### 统计内核态 CPU 时间，返回用户态或被换出处理器时调用
+    pub fn account_system_time(&mut self) {
+        let delta = self.update_time_stamp();
+        self.usage.stime_us += delta;
+        if self.itimer_prof.tick(delta) {
+            self.pending.add(SignalFlags::SIGPROF);
+        }
+    }
+
+    /// 被调度到处理器上时调用，此前的时间不计入该任务
+    pub fn reset_time_stamp(&mut self) {
+        self.last_time_us = get_time_us();
+    }
+
+    /// 获取 `which` 对应的间隔定时器的当前值
+    pub fn get_itimer(&self, which: usize) -> ITimerVal {
+        let (value_us, interval_us) = match which {
+            ITIMER_REAL => (self.itimer_real.remaining_us(), self.itimer_real.interval_us),
+            ITIMER_VIRTUAL => (self.itimer_virtual.value_us, self.itimer_virtual.interval_us),
+            _ => (self.itimer_prof.value_us, self.itimer_prof.interval_us),
+        };
+        ITimerVal {
+            it_interval: TimeVal::from_us(interval_us),
+            it_value: TimeVal::from_us(value_us),
+        }
+    }
+
+    pub fn real_timer(&self, slot: RealTimerSlot) -> Option<&RealTimer> {
+        match slot {
+            RealTimerSlot::ITimerReal => Some(&self.itimer_real),
+            RealTimerSlot::Posix(id) => self.posix_timers.get(id)?.as_ref(),
+        }
+    }
+
+    pub fn real_timer_mut(&mut self, slot: RealTimerSlot) -> Option<&mut RealTimer> {
+        match slot {
+            RealTimerSlot::ITimerReal => Some(&mut self.itimer_real),
+            RealTimerSlot::Posix(id) => self.posix_timers.get_mut(id)?.as_mut(),
+        }
     }
     // pub fn lazy_alloc_stack(&mut self, vpn: VirtPageNum) -> isize {
     //     self.memory_set.lazy_alloc_stack(vpn)
@@ -131,28 +310,47 @@ impl TaskControlBlock {
             pid: pid_handle,
             tgid,
             kernel_stack,
+            child_exit: WaitQueue::new(),
             inner:Mutex::new(TaskControlBlockInner {
                     trap_cx_ppn,
+                    trap_cx_va: TRAP_CONTEXT,
                     base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    memory_set: Arc::new(Mutex::new(memory_set)),
                     parent: None,
                     children: Vec::new(),
                     exit_code: 0,
-                    fd_table: vec![
+                    pgid: tgid,
+                    sid: tgid,
+                    thread_group: Arc::new(Mutex::new(ThreadGroup::new())),
+                    wait_stopped: None,
+                    wait_continued: false,
+                    clear_child_tid: 0,
+                    fd_table: Arc::new(Mutex::new(FdTable::new(vec![
                         // 0 -> stdin
                         Some(Arc::new(Stdin)),
                         // 1 -> stdout
                         Some(Arc::new(Stdout)),
                         // 2 -> stderr
                         Some(Arc::new(Stdout)),
-                    ],
-                    signals: SignalFlags::empty(),
+                    ]))),
+                    pending: SigPending::new(),
                     current_path: String::from("/"),
-                    mmap_area: MmapArea::new(VirtAddr::from(MMAP_BASE), VirtAddr::from(MMAP_BASE)),
-                    sigset: SigSet::new(),
+                    mmap_area: Arc::new(Mutex::new(MmapArea::new())),
+                    sigmask: SignalFlags::empty(),
+                    saved_sigmask: None,
+                    sigaltstack: SignalStack::disabled(),
+                    sigactions: Arc::new(Mutex::new([SigAction::new(); NSIG])),
                     resource: [RLimit { rlim_cur: 0, rlim_max: 1 }; RESOURCE_KIND_NUMBER],
+                    usage: TaskUsage::default(),
+                    children_usage: TaskUsage::default(),
+                    last_time_us: 0,
+                    itimer_virtual: CpuTimer::default(),
+                    itimer_prof: CpuTimer::default(),
+                    itimer_real: RealTimer::new(SignalFlags::SIGALRM, None),
+                    posix_timers: Vec::new(),
+                    trace: false,
                 })
             ,
         };
@@ -253,12 +451,40 @@ impl TaskControlBlock {
         *translated_refmut(memory_set.token(), user_sp as *mut usize) = args.len();
         let mut inner = self.inner_exclusive_access();
 
-        inner.memory_set = memory_set; // 这将导致原有的地址空间生命周期结束，里面包含的全部物理页帧都会被回收
+        // 与其他线程共享的地址空间中还映射着本线程的 Trap 上下文页面，需要先解除
+        if inner.trap_cx_va != TRAP_CONTEXT {
+            let trap_cx_va = inner.trap_cx_va;
+            inner.memory_set.lock().remove_area_with_start_vpn(VirtAddr::from(trap_cx_va).into());
+        }
+        // 这将导致原有的地址空间生命周期结束（没有其他线程共享时），里面包含的全部物理页帧都会被回收
+        // 地址空间、mmap 区域和文件描述符表都不再与其他线程共享，原有的映射全部解除
+        if Arc::strong_count(&inner.memory_set) == 1 {
+            inner.mmap_area.lock().sync_shared(0, USER_SPACE_END, &mut inner.memory_set.lock());
+        }
+        let fd_table = inner.fd_table.lock().clone();
+        inner.memory_set = Arc::new(Mutex::new(memory_set));
+        inner.mmap_area = Arc::new(Mutex::new(MmapArea::new()));
+        release_shared_pages();
+        inner.fd_table = Arc::new(Mutex::new(fd_table));
         inner.trap_cx_ppn = trap_cx_ppn;
+        inner.trap_cx_va = TRAP_CONTEXT;
+        inner.clear_child_tid = 0;
+        // 设置了处理函数的信号恢复默认处理方式，被忽略的信号保持忽略，信号处理函数表不再与其他任务共享
+        let mut sigactions = *inner.sigactions.lock();
+        for action in sigactions.iter_mut() {
+            if action.sa_handler != SIG_IGN {
+                *action = SigAction::new();
+            }
+        }
+        inner.sigactions = Arc::new(Mutex::new(sigactions));
+        inner.sigaltstack = SignalStack::disabled();
+        // POSIX 定时器在 exec 后删除，间隔定时器保留
+        inner.posix_timers.clear();
         let trap_cx = inner.get_trap_cx();
 
         inner
             .fd_table
+            .lock()
             .iter_mut()
             .find(|fd| {
                 // if fd.is_some(){
@@ -280,60 +506,116 @@ impl TaskControlBlock {
         // trap_cx.x[11] = argv_base; // a1 则表示 参数字符串首地址数组 的起始地址
     }
 
-    /// 用来实现 fork 系统调用，即当前进程 fork 出来一个与之几乎相同的子进程
-    pub fn fork(self: &Arc<TaskControlBlock>, is_create_thread: bool) -> Arc<TaskControlBlock> {
+    /// ### 用来实现 fork/clone 系统调用，即当前任务创建出一个子进程或线程
+    /// |标志|效果|
+    /// |--|--|
+    /// |`CLONE_VM`|共享地址空间与 mmap 区域，否则以 COW 的方式复制|
+    /// |`CLONE_FILES`|共享文件描述符表，否则复制一份|
+    /// |`CLONE_SIGHAND`|共享信号处理函数表，否则复制一份|
+    /// |`CLONE_THREAD`|加入当前线程组，与当前任务拥有相同的 tgid 和父进程，退出后自动回收|
+    ///
+    /// 每个任务都有自己的内核栈和 Trap 上下文页面，子任务的 Trap 上下文从当前任务复制而来
+    pub fn fork(self: &Arc<TaskControlBlock>, flags: CloneFlags) -> Arc<TaskControlBlock> {
         let mut parent_inner = self.inner_exclusive_access();
-        // copy mmap_area
-        let mmap_area = parent_inner.mmap_area.clone();
-        // mmap_area.debug_show();
-        // 拷贝用户地址空间
-        let memory_set = MemorySet::from_copy_on_write(&mut parent_inner.memory_set);  // use 4 pages
-        let trap_cx_ppn = memory_set.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap().ppn();
         // 分配一个 PID
         let pid_handle = pid_alloc();
-        let mut tgid = 0;
-        _ = tgid;
-        if is_create_thread {
-            tgid = self.pid.0;
+        let is_thread = flags.contains(CloneFlags::CLONE_THREAD);
+        let tgid = if is_thread { self.tgid } else { pid_handle.0 };
+        // 地址空间
+        let (memory_set, mmap_area, trap_cx_va) = if flags.contains(CloneFlags::CLONE_VM) {
+            // 在共享的地址空间中为子任务单独映射一页 Trap 上下文
+            let trap_cx_va = trap_cx_position(pid_handle.0);
+            parent_inner.memory_set.lock().insert_framed_area(
+                trap_cx_va.into(),
+                (trap_cx_va + PAGE_SIZE).into(),
+                MapPermission::R | MapPermission::W,
+            );
+            (parent_inner.memory_set.clone(), parent_inner.mmap_area.clone(), trap_cx_va)
         } else {
-            tgid = pid_handle.0;
-        }
+            // 拷贝用户地址空间
+            let memory_set = MemorySet::from_copy_on_write(&mut parent_inner.memory_set.lock()); // use 4 pages
+            let mmap_area = parent_inner.mmap_area.lock().clone();
+            (Arc::new(Mutex::new(memory_set)), Arc::new(Mutex::new(mmap_area)), TRAP_CONTEXT)
+        };
+        let trap_cx_ppn = memory_set.lock().translate(VirtAddr::from(trap_cx_va).into()).unwrap().ppn();
+        // 子任务从当前任务（不一定是主线程）的 Trap 上下文返回用户态
+        trap_cx_ppn.get_bytes_array().copy_from_slice(parent_inner.trap_cx_ppn.get_bytes_array());
         // 根据 PID 创建一个应用内核栈
-        let kernel_stack = KernelStack::new(&pid_handle);  // use 2 pages
+        let kernel_stack = KernelStack::new(&pid_handle); // use 2 pages
         let kernel_stack_top = kernel_stack.get_top();
-        // copy fd table
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
+        // 文件描述符表
+        let fd_table = if flags.contains(CloneFlags::CLONE_FILES) {
+            parent_inner.fd_table.clone()
+        } else {
+            Arc::new(Mutex::new(parent_inner.fd_table.lock().clone()))
+        };
+        // 信号处理函数表
+        let sigactions = if flags.contains(CloneFlags::CLONE_SIGHAND) {
+            parent_inner.sigactions.clone()
+        } else {
+            Arc::new(Mutex::new(*parent_inner.sigactions.lock()))
+        };
+        // 线程组
+        let thread_group = if is_thread {
+            parent_inner.thread_group.lock().live += 1;
+            parent_inner.thread_group.clone()
+        } else {
+            Arc::new(Mutex::new(ThreadGroup::new()))
+        };
+        // 线程与创建它的任务是兄弟关系
+        let parent = if is_thread { parent_inner.parent.clone() } else { Some(Arc::downgrade(self)) };
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             tgid,
             kernel_stack,
+            child_exit: WaitQueue::new(),
             inner: Mutex::new(TaskControlBlockInner {
                     trap_cx_ppn,
+                    trap_cx_va,
                     base_size: parent_inner.base_size,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
                     memory_set,
-                    parent: Some(Arc::downgrade(self)),
+                    parent,
                     children: Vec::new(),
                     exit_code: 0,
-                    fd_table: new_fd_table,
-                    signals: SignalFlags::empty(),
+                    pgid: parent_inner.pgid,
+                    sid: parent_inner.sid,
+                    thread_group,
+                    wait_stopped: None,
+                    wait_continued: false,
+                    clear_child_tid: 0,
+                    fd_table,
+                    pending: SigPending::new(),
                     current_path: parent_inner.current_path.clone(),
                     mmap_area,
-                    sigset: SigSet::new(),
+                    sigmask: parent_inner.sigmask,
+                    saved_sigmask: None,
+                    // 共享地址空间的子任务不能与当前任务使用同一个备用信号栈
+                    sigaltstack: if flags.contains(CloneFlags::CLONE_VM) {
+                        SignalStack::disabled()
+                    } else {
+                        parent_inner.sigaltstack
+                    },
+                    sigactions,
                     resource: [RLimit { rlim_cur: 0, rlim_max: 1 }; RESOURCE_KIND_NUMBER],
+                    // 子进程不继承父进程的定时器
+                    usage: TaskUsage::default(),
+                    children_usage: TaskUsage::default(),
+                    last_time_us: 0,
+                    itimer_virtual: CpuTimer::default(),
+                    itimer_prof: CpuTimer::default(),
+                    itimer_real: RealTimer::new(SignalFlags::SIGALRM, None),
+                    posix_timers: Vec::new(),
+                    trace: parent_inner.trace,
                 })
             ,
         });
-        // 把新生成的进程加入到子进程向量中
-        parent_inner.children.push(task_control_block.clone());
+        // 把新生成的进程加入到子进程向量中，线程不需要父进程回收
+        if !is_thread {
+            parent_inner.children.push(task_control_block.clone());
+            task_control_block.inner_exclusive_access().thread_group.lock().leader = Arc::downgrade(&task_control_block);
+        }
         // 更新子进程 trap 上下文中的栈顶指针
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
@@ -341,6 +623,38 @@ impl TaskControlBlock {
         task_control_block
     }
 
+    /// 是否为线程组中的非主线程
+    pub fn is_thread(&self) -> bool {
+        self.pid.0 != self.tgid
+    }
+
+    /// ### 整个进程的资源使用，包括已经退出的线程
+    /// 调用者不能持有线程组中任何任务的锁
+    pub fn process_usage(&self) -> TaskUsage {
+        let thread_group = self.inner_exclusive_access().thread_group.clone();
+        let mut usage = thread_group.lock().exited_usage;
+        for thread in tgid2tasks(self.tgid) {
+            usage.add(&thread.inner_exclusive_access().usage);
+        }
+        usage
+    }
+
+    /// ### 向该任务（不一定是当前任务）的地址空间写入一个 `u32`
+    /// 用于 `CLONE_CHILD_SETTID`：刚 fork 出的子进程与父进程共享写时复制页面，
+    /// 需要先在子进程中完成写时复制或缺页加载，才能写入而不影响父进程
+    /// - 返回值：地址不可写（包括用户态不可访问的页面）时返回 `false`
+    ///
+    /// `copy_to_user` 只会为当前任务补齐缺页，因此页面无法在该任务中补齐时直接失败
+    pub fn write_user_u32(&self, va: usize, value: u32) -> bool {
+        match self.check_lazy(VirtAddr::from(va), true) {
+            // 缺页已补齐，或页面本就有效
+            0 | -4 => {}
+            _ => return false,
+        }
+        let token = self.inner_exclusive_access().get_user_token();
+        copy_to_user(token, va as *mut u32, &value).is_ok()
+    }
+
     /// ### 尝试用时加载缺页，目前只支持mmap缺页
     /// - 参数：
     ///     - `va`：缺页中的虚拟地址
@@ -350,17 +664,17 @@ impl TaskControlBlock {
     ///     - `-1`：加载缺页失败
     pub fn check_lazy(&self, va: VirtAddr, is_load: bool) -> isize {
         let inner = self.inner_exclusive_access();
-        let mmap_start = inner.mmap_area.mmap_start;
-        let mmap_end = inner.mmap_area.mmap_top;
-        let heap_start = VirtAddr::from(inner.memory_set.heap_start);
-        let heap_end = VirtAddr::from(inner.memory_set.heap_start + USER_HEAP_SIZE);
+        let heap_start = VirtAddr::from(inner.memory_set.lock().heap_start);
+        let heap_end = VirtAddr::from(inner.memory_set.lock().heap_start + USER_HEAP_SIZE);
         drop(inner);
 
         let vpn: VirtPageNum = va.floor();
         let pte = self.inner_exclusive_access().enquire_pte_via_vpn(vpn);
         if pte.is_some() && pte.unwrap().is_cow() {
             let former_ppn = pte.unwrap().ppn();
-            return self.inner_exclusive_access().cow_alloc(vpn, former_ppn);
+            let mut inner = self.inner_exclusive_access();
+            inner.usage.minflt += 1;
+            return inner.cow_alloc(vpn, former_ppn);
         } else {
             if let Some(pte1) = pte {
                 if pte1.is_valid() {
@@ -368,18 +682,17 @@ impl TaskControlBlock {
                 }
             }
         }
+        // 逻辑段的权限不允许该访问（包括对 PROT_NONE 页面的任何访问）时不分配页面，由调用者发送 SIGSEGV
+        if !self.inner_exclusive_access().memory_set.lock().fault_allowed(vpn, is_load) {
+            return -1;
+        }
         if va >= heap_start && va <= heap_end {
-            self.inner_exclusive_access().lazy_alloc_heap(va.floor())
-        } else if va >= mmap_start && va < mmap_end {
-            self.lazy_mmap(va, is_load)
+            let mut inner = self.inner_exclusive_access();
+            inner.usage.minflt += 1;
+            inner.lazy_alloc_heap(va.floor())
+        } else if self.lazy_mmap(va, is_load) == 0 {
+            0
         } else {
-            println!("[check_lazy] {:?}", va);
-            println!("[check_lazy] mmap_start: 0x{:x}", mmap_start.0);
-            println!("[check_lazy] mmap_end: 0x{:x}", mmap_end.0);
-            println!("[check_lazy] heap_start: 0x{:x}", heap_start.0);
-            println!("[check_lazy] heap_end: 0x{:x}", heap_end.0);
-            println!("[check_lazy] current vma layout:");
-            self.inner_exclusive_access().memory_set.debug_show_layout();
             -2
         }
     }
@@ -392,70 +705,138 @@ impl TaskControlBlock {
     ///     - `0`
     ///     - `-1`
     pub fn lazy_mmap(&self, va: VirtAddr, is_load: bool) -> isize {
-        let mut inner = self.inner_exclusive_access();
-        let fd_table = inner.fd_table.clone();
-        let token = inner.get_user_token();
-        let lazy_result = inner.memory_set.lazy_mmap(va.into());
-
-        if lazy_result == 0 && is_load {
-            inner.mmap_area.lazy_map_page(va, fd_table, token);
+        let inner = self.inner_exclusive_access();
+        // 共享文件映射使用所有映射者共享的页面，不论读写都映射文件内容
+        let shared = inner.mmap_area.lock().shared_file_page(va);
+        let (lazy_result, from_file) = if let Some((file, offset)) = shared {
+            let frame = shared_page(file.as_osinode().unwrap(), offset / PAGE_SIZE);
+            (inner.memory_set.lock().map_shared_page(va.floor(), frame), true)
+        } else {
+            let fd_table = inner.fd_table.lock().to_vec();
+            let token = inner.get_user_token();
+            let lazy_result = inner.memory_set.lock().lazy_mmap(va.into(), is_load);
+            let mut from_file = false;
+            if lazy_result == 0 && is_load {
+                from_file = inner.mmap_area.lock().lazy_map_page(va, fd_table, token);
+            }
+            (lazy_result, from_file)
+        };
+        drop(inner);
+        if lazy_result == 0 {
+            let mut inner = self.inner_exclusive_access();
+            if from_file {
+                inner.usage.majflt += 1;
+            } else {
+                inner.usage.minflt += 1;
+            }
         }
         return lazy_result;
     }
 
-    // 在进程虚拟地址空间中分配创建一片虚拟内存地址映射
-    pub fn mmap(&self, addr: usize, length: usize, prot: MmapProts, flags: MmapFlags, fd: isize, offset: usize) -> usize {
-
-        if addr % PAGE_SIZE != 0 {
-            panic!("mmap: addr not aligned");
-        }
-
-        let mut inner = self.inner_exclusive_access();
-        let fd_table = inner.fd_table.clone();
-        let token = inner.get_user_token();
-
-        let mut start_va = VirtAddr::from(0);
-        let mut end_va = VirtAddr::from(0);
-        // "prot<<1" 右移一位以符合 MapPermission 的权限定义
-        // "1<<4" 增加 MapPermission::U 权限
-        let map_flags = (((prot.bits() & 0b111) << 1) + (1 << 4)) as u8;
+    /// ### 在进程虚拟地址空间中分配创建一片虚拟内存地址映射
+    /// - 带 `MAP_FIXED` 时映射到 `addr`，先解除与之重叠的已有映射
+    /// - 否则 `addr` 仅作为提示，提示的区间被占用时在地址空间中寻找空闲区间
+    /// - `MAP_SHARED` 的文件映射与映射同一文件的其他地址空间共享页面，修改在 msync、munmap 与进程退出时写回文件
+    /// - 返回值：映射的起始地址，没有足够的空闲区间时返回 `-ENOMEM`；
+    ///   共享文件映射的文件描述符不合法时返回 `-EBADF`，文件不支持映射时返回 `-ENODEV`，
+    ///   可写映射的文件不可写时返回 `-EACCES`，偏移未页对齐时返回 `-EINVAL`
+    pub fn mmap(&self, addr: usize, length: usize, prot: MmapProts, flags: MmapFlags, fd: isize, offset: usize) -> isize {
+        let inner = self.inner_exclusive_access();
+        let shared = flags.contains(MmapFlags::MAP_SHARED);
+        let file = if shared && !flags.contains(MmapFlags::MAP_ANONYMOUS) {
+            let file = match inner.fd_table.lock().get(fd as usize) {
+                Some(Some(file)) if fd >= 0 => file.clone(),
+                _ => return -EBADF,
+            };
+            if file.as_osinode().is_none() {
+                return -ENODEV;
+            }
+            if prot.contains(MmapProts::PROT_WRITE) && !file.writable() {
+                return -EACCES;
+            }
+            if offset % PAGE_SIZE != 0 {
+                return -EINVAL;
+            }
+            Some(file)
+        } else {
+            None
+        };
+        let mut memory_set = inner.memory_set.lock();
+        let mut mmap_area = inner.mmap_area.lock();
 
         // inner.memory_set.debug_show_layout();
 
-        if addr != 0{
-            if flags.contains(MmapFlags::MAP_FIXED) { // 处理 MAP_FIXED
-                inner.memory_set.reduce_chunk_range(addr, length);
-                inner.mmap_area.reduce_mmap_range(addr, length);
-                start_va = VirtAddr::from(addr);
-                end_va = VirtAddr::from(addr+length);
+        let pages = (length + PAGE_SIZE - 1) / PAGE_SIZE;
+        let start_va = if addr != 0 && flags.contains(MmapFlags::MAP_FIXED) {
+            // 处理 MAP_FIXED，先解除与新映射重叠的部分
+            mmap_area.sync_shared(addr, addr + pages * PAGE_SIZE, &mut memory_set);
+            memory_set.unmap_range(VirtAddr::from(addr).floor(), VirtAddr::from(addr + length).ceil());
+            mmap_area.remove(addr, pages * PAGE_SIZE);
+            release_shared_pages();
+            VirtAddr::from(addr)
+        } else {
+            match memory_set.find_free_range(VirtAddr::from(addr).floor(), pages) {
+                Some(start_vpn) => VirtAddr::from(start_vpn),
+                None => return -ENOMEM,
             }
-        } else{
-            start_va = inner.mmap_area.get_mmap_top();
-            end_va = VirtAddr::from(start_va.0 + length);
-        }
+        };
+        let end_va = VirtAddr::from(start_va.0 + length);
 
-        inner
-            .memory_set
-            .insert_mmap_area(start_va, end_va, MapPermission::from_bits(map_flags).unwrap());
-        
-        inner.mmap_area.push(start_va.0, length, prot.bits(), flags.bits(), fd, offset, fd_table, token);
+        memory_set.insert_mmap_area(start_va, end_va, MapPermission::from_mmap_prot(prot), shared);
+
+        mmap_area.push(start_va.0, length, prot.bits(), flags.bits(), fd, offset, file);
+        drop(mmap_area);
+        drop(memory_set);
         drop(inner);
 
-        start_va.0
+        start_va.0 as isize
     }
 
+    /// ### 解除一段页对齐的虚拟地址范围内的映射
+    /// 范围可以只覆盖某个映射的一部分，也可以跨越多个映射
     pub fn munmap(&self, addr: usize, length: usize) -> isize {
-        let mut inner = self.inner_exclusive_access();
+        let inner = self.inner_exclusive_access();
 
         // println!("[Kernel munmap] start munmap start: 0x{:x} len: 0x{:x};", start, len);
         // inner.memory_set.debug_show_layout();
-        
-        inner.memory_set.remove_area_with_start_vpn(VirtAddr::from(addr).into());
+
+        let length = (length + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+        let mut memory_set = inner.memory_set.lock();
+        let mut mmap_area = inner.mmap_area.lock();
+        mmap_area.sync_shared(addr, addr + length, &mut memory_set);
+        memory_set.unmap_range(VirtAddr::from(addr).floor(), VirtAddr::from(addr + length).floor());
 
         // println!("[Kernel munmap] after munmap;");
         // inner.memory_set.debug_show_layout();
 
-        inner.mmap_area.remove(addr, length)
+        mmap_area.remove(addr, length);
+        release_shared_pages();
+        0
+    }
+
+    /// ### 将一段页对齐的虚拟地址范围内共享文件映射的修改写回文件
+    /// 写回总是同步完成，`MS_ASYNC` 与 `MS_SYNC` 的效果相同
+    /// - 返回值：范围内存在未映射的页面时返回 `-ENOMEM`
+    pub fn msync(&self, addr: usize, length: usize) -> isize {
+        let inner = self.inner_exclusive_access();
+        let mut memory_set = inner.memory_set.lock();
+        let start_vpn = VirtAddr::from(addr).floor();
+        let end_vpn = VirtAddr::from(addr + length).ceil();
+        if (start_vpn.0..end_vpn.0).any(|vpn| memory_set.permission(VirtPageNum(vpn)).is_none()) {
+            return -ENOMEM;
+        }
+        inner.mmap_area.lock().sync_shared(addr, addr + length, &mut memory_set);
+        0
+    }
+
+    /// ### 修改一段页对齐的虚拟地址范围的访问权限
+    /// - 返回值：成功时返回 0，失败时返回 `MemorySet::mprotect` 给出的错误码
+    pub fn mprotect(&self, addr: usize, length: usize, prot: MmapProts) -> isize {
+        let inner = self.inner_exclusive_access();
+        let mut memory_set = inner.memory_set.lock();
+        let start_vpn = VirtAddr::from(addr).floor();
+        let end_vpn = VirtAddr::from(addr + length).ceil();
+        memory_set.mprotect(start_vpn, end_vpn, MapPermission::from_mmap_prot(prot))
     }
 
     pub fn getpid(&self) -> usize {
@@ -467,28 +848,19 @@ impl TaskControlBlock {
     //     inner.parent.as_ref().unwrap().upgrade()
     // }
 
+    /// ### 调整进程堆顶
+    /// 越过堆区上下界时保持堆顶不变，与 Linux `brk` 的语义一致，由调用者根据返回值判断是否成功
+    /// - 返回值：调整后的堆顶
     pub fn grow_proc(&self, grow_size: isize) -> usize {
-        if grow_size > 0 {
-            let growed_addr: usize = self.inner.lock().memory_set.heap_pt + grow_size as usize;
-            let limit = self.inner.lock().memory_set.heap_start + USER_HEAP_SIZE;
-            if growed_addr > limit {
-                panic!(
-                    "process doesn't have enough memsize to grow! limit:0x{:x}, heap_pt:0x{:x}, growed_addr:0x{:x}, pid:{}",
-                    limit,
-                    self.inner.lock().memory_set.heap_pt,
-                    growed_addr,
-                    self.pid.0
-                );
-            }
-            self.inner.lock().memory_set.heap_pt = growed_addr;
-        } else {
-            let shrinked_addr: usize = self.inner.lock().memory_set.heap_pt + grow_size as usize;
-            if shrinked_addr < self.inner.lock().memory_set.heap_start {
-                panic!("Memory shrinked to the lowest boundary!")
-            }
-            self.inner.lock().memory_set.heap_pt = shrinked_addr;
+        let inner = self.inner.lock();
+        let mut memory_set = inner.memory_set.lock();
+        let heap_pt = memory_set.heap_pt as isize + grow_size;
+        let heap_start = memory_set.heap_start as isize;
+        let limit = heap_start + USER_HEAP_SIZE as isize;
+        if heap_pt >= heap_start && heap_pt <= limit {
+            memory_set.heap_pt = heap_pt as usize;
         }
-        return self.inner.lock().memory_set.heap_pt;
+        memory_set.heap_pt
     }
 }
 
@@ -497,10 +869,14 @@ impl TaskControlBlock {
 /// |--|--|
 /// |`Ready`|准备运行|
 /// |`Running`|正在运行|
+/// |`Blocked`|阻塞，不在就绪队列中，等待被唤醒|
+/// |`Stopped`|被 SIGSTOP 等信号停止，不在就绪队列中，等待 SIGCONT|
 /// |`Zombie`|僵尸态|
 #[derive(Copy, Clone, PartialEq)] // 由编译器实现一些特性
 pub enum TaskStatus {
     Ready,   // 准备运行
     Running, // 正在运行
+    Blocked, // 阻塞
+    Stopped, // 停止
     Zombie,  // 僵尸态
 }
@@ -0,0 +1,90 @@
+/// # 等待队列
+/// `os/src/task/wait_queue.rs`
+/// ```
+/// WaitQueue::new() -> Self
+/// WaitQueue::wait(&self) -> isize
+/// WaitQueue::wait_timeout(&self, expire_ms: usize) -> isize
+/// WaitQueue::wake_one(&self) -> bool
+/// WaitQueue::wake_all(&self) -> usize
+/// ```
+///
+/// 条件不满足时，任务在等待队列上阻塞，离开就绪队列，直到条件可能发生变化时被唤醒，
+/// 取代 `suspend_current_and_run_next` 的忙等轮询。
+/// 单核且内核态不可抢占，因此检查条件后直接调用 `wait` 不会丢失唤醒，但调用时不能持有任何锁
+//
+use super::{block_current_and_run_next, current_task, signal_pending, wakeup_task, TaskControlBlock};
+use crate::timer::{add_timer, remove_timer};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+pub struct WaitQueue {
+    queue: Mutex<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// ### 阻塞当前任务直到被唤醒
+    /// - 返回值：被唤醒返回 0；有待处理的信号时返回 -1，调用者应尽快返回用户态以便处理信号
+    ///
+    /// 被唤醒不代表条件已经满足，调用者需要重新检查
+    pub fn wait(&self) -> isize {
+        self.wait_until(None)
+    }
+
+    /// ### 阻塞当前任务直到被唤醒或到达 `expire_ms`（CPU 上电时间，毫秒）
+    /// 返回值同 `wait`，超时也返回 0
+    pub fn wait_timeout(&self, expire_ms: usize) -> isize {
+        self.wait_until(Some(expire_ms))
+    }
+
+    fn wait_until(&self, expire_ms: Option<usize>) -> isize {
+        if signal_pending() {
+            return -1;
+        }
+        let task = current_task().unwrap();
+        self.queue.lock().push_back(task.clone());
+        if let Some(expire_ms) = expire_ms {
+            add_timer(expire_ms, task.clone());
+        }
+        block_current_and_run_next();
+        // 超时或被信号唤醒时仍在队列中，将自己移出
+        remove_timer(&task);
+        self.queue.lock().retain(|waiter| !Arc::ptr_eq(waiter, &task));
+        if signal_pending() {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// ### 唤醒队头的一个任务
+    /// - 返回值：队列为空时返回 `false`
+    #[allow(unused)]
+    pub fn wake_one(&self) -> bool {
+        let task = self.queue.lock().pop_front();
+        match task {
+            Some(task) => {
+                wakeup_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// ### 唤醒队列中的全部任务
+    /// - 返回值：唤醒的任务数
+    pub fn wake_all(&self) -> usize {
+        let tasks: VecDeque<_> = self.queue.lock().drain(..).collect();
+        let count = tasks.len();
+        for task in tasks {
+            wakeup_task(task);
+        }
+        count
+    }
+}
@@ -1,7 +1,14 @@
+use core::cmp::Ordering;
 use core::ops::{Add, Sub};
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::task::{fire_real_timer, wakeup_task, RealTimerSlot, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+use spin::Mutex;
 
 pub const TICKS_PER_SEC: usize = 100;
 pub const MSEC_PER_SEC: usize = 1000;
@@ -62,13 +69,20 @@ impl TimeVal {
         Self { sec: 0, usec: 0 }
     }
 
-    pub fn is_zero(&self) -> bool {
-        self.sec == 0 && self.usec == 0
+    pub fn from_us(us: usize) -> Self {
+        Self {
+            sec: us / USEC_PER_SEC,
+            usec: us % USEC_PER_SEC,
+        }
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
-        let size = core::mem::size_of::<Self>();
-        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, size) }
+    pub fn to_us(&self) -> usize {
+        self.sec * USEC_PER_SEC + self.usec
+    }
+
+    /// 微秒数小于 1 秒
+    pub fn is_valid(&self) -> bool {
+        self.usec < USEC_PER_SEC
     }
 }
 
@@ -146,7 +160,151 @@ pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
 
+/// ### 定时器到期时的动作
+pub enum TimerEvent {
+    /// 唤醒阻塞的任务
+    Wakeup,
+    /// 触发任务的实时定时器（`ITIMER_REAL` 或 POSIX 定时器），见 `fire_real_timer`
+    RealTimer { slot: RealTimerSlot, generation: usize },
+}
+
+/// ### 定时唤醒条件
+/// |成员变量|描述|
+/// |--|--|
+/// |`expire_ms`|到期时刻（CPU 上电时间，毫秒）|
+/// |`task`|到期时处理的任务|
+/// |`event`|到期时的动作|
+pub struct TimerCondVar {
+    pub expire_ms: usize,
+    pub task: Arc<TaskControlBlock>,
+    pub event: TimerEvent,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+
+impl Eq for TimerCondVar {}
+
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerCondVar {
+    /// 反转比较结果，使 `BinaryHeap` 成为以最早到期者为堆顶的小根堆
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_ms.cmp(&self.expire_ms)
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: Mutex<BinaryHeap<TimerCondVar>> = Mutex::new(BinaryHeap::new());
+}
+
+/// ### 登记一个定时唤醒，到期时对 `task` 调用 `wakeup_task`
+pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    TIMERS.lock().push(TimerCondVar {
+        expire_ms,
+        task,
+        event: TimerEvent::Wakeup,
+    });
+}
+
+/// ### 登记任务的实时定时器，到期时调用 `fire_real_timer`
+pub fn add_real_timer(expire_ms: usize, task: Arc<TaskControlBlock>, slot: RealTimerSlot, generation: usize) {
+    TIMERS.lock().push(TimerCondVar {
+        expire_ms,
+        task,
+        event: TimerEvent::RealTimer { slot, generation },
+    });
+}
+
+/// ### 撤销 `task` 登记的全部定时唤醒
+/// 实时定时器不受影响
+pub fn remove_timer(task: &Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.lock();
+    let remain: Vec<TimerCondVar> = timers
+        .drain()
+        .filter(|timer| !(Arc::ptr_eq(&timer.task, task) && matches!(timer.event, TimerEvent::Wakeup)))
+        .collect();
+    *timers = BinaryHeap::from(remain);
+}
+
+/// ### 撤销 `task` 登记的全部定时器，任务退出时调用
+pub fn remove_all_timers(task: &Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.lock();
+    let remain: Vec<TimerCondVar> = timers.drain().filter(|timer| !Arc::ptr_eq(&timer.task, task)).collect();
+    *timers = BinaryHeap::from(remain);
+}
+
+/// ### 唤醒所有已到期的定时任务
+/// 在时钟中断以及 idle 控制流中调用
+pub fn check_timer() {
+    let current_ms = get_time_ms();
+    let mut expired = Vec::new();
+    let mut timers = TIMERS.lock();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_ms > current_ms {
+            break;
+        }
+        expired.push(timers.pop().unwrap());
+    }
+    drop(timers);
+    for timer in expired {
+        match timer.event {
+            TimerEvent::Wakeup => wakeup_task(timer.task),
+            TimerEvent::RealTimer { slot, generation } => {
+                if let Some(next_ms) = fire_real_timer(&timer.task, slot, generation) {
+                    add_real_timer(next_ms, timer.task, slot, generation);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
 pub struct Timespec {
     pub tv_sec: u64,  // 秒
     pub tv_nsec: u64, // 纳秒
 }
+
+impl Timespec {
+    pub fn from_ms(ms: usize) -> Self {
+        Self {
+            tv_sec: (ms / MSEC_PER_SEC) as u64,
+            tv_nsec: (ms % MSEC_PER_SEC * (NSEC_PER_SEC / MSEC_PER_SEC)) as u64,
+        }
+    }
+
+    /// 转换为毫秒，不足 1 毫秒的部分向上取整，保证按它睡眠不会提前醒来；结果溢出时取 `usize::MAX`
+    pub fn to_ms_ceil(&self) -> usize {
+        let nsec_per_msec = NSEC_PER_SEC / MSEC_PER_SEC;
+        (self.tv_sec as usize)
+            .saturating_mul(MSEC_PER_SEC)
+            .saturating_add((self.tv_nsec as usize + nsec_per_msec - 1) / nsec_per_msec)
+    }
+
+    pub fn from_us(us: usize) -> Self {
+        Self {
+            tv_sec: (us / USEC_PER_SEC) as u64,
+            tv_nsec: (us % USEC_PER_SEC * (NSEC_PER_SEC / USEC_PER_SEC)) as u64,
+        }
+    }
+
+    /// 转换为微秒，不足 1 微秒的部分向上取整
+    pub fn to_us_ceil(&self) -> usize {
+        let nsec_per_usec = NSEC_PER_SEC / USEC_PER_SEC;
+        (self.tv_sec as usize)
+            .saturating_mul(USEC_PER_SEC)
+            .saturating_add((self.tv_nsec as usize + nsec_per_usec - 1) / nsec_per_usec)
+    }
+
+    /// 秒数非负且纳秒数小于 1 秒
+    pub fn is_valid(&self) -> bool {
+        (self.tv_sec as i64) >= 0 && self.tv_nsec < NSEC_PER_SEC as u64
+    }
+}
@@ -11,16 +11,18 @@
 //
 mod context;
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::config::TRAMPOLINE;
+use crate::fs::poll_console;
 use crate::mm::VirtAddr;
 #[allow(unused)]
 use crate::mm::{frame_usage, heap_usage};
-use crate::syscall::{syscall, SYSCALL_NAME};
+use crate::syscall::{syscall, trace_enter, trace_exit, SYSCALL_NAME, SYSCALL_RT_SIGRETURN};
+use crate::syscall::errno::{EINTR, ERESTARTSYS};
 use crate::task::{
-    check_signals_of_current, current_add_signal, current_task, current_trap_cx, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next, SignalFlags,
+    current_add_signal, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token, handle_signals,
+    should_restart_syscall, suspend_current_and_run_next, SignalFlags,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, set_next_trigger};
 use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
@@ -72,6 +74,7 @@ pub fn enable_timer_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    current_task().unwrap().inner_exclusive_access().account_user_time();
     let scause = scause::read(); // 用于描述 Trap 的原因
     let stval = stval::read(); // 给出 Trap 附加信息
     match scause.cause() {
@@ -83,15 +86,33 @@ pub fn trap_handler() -> ! {
                 debug!(
                     "[DEBUG] pid:{}, syscall_name: {}",
                     current_task().unwrap().getpid(),
-                    SYSCALL_NAME.get(&cx.x[17]).expect("syscall id convert to name error")
+                    SYSCALL_NAME.get(&cx.x[17]).unwrap_or(&"unknown")
                 );
             }
             // println!("fd_table:{:?}",current_task().unwrap().inner_exclusive_access().fd_table);
             cx.sepc += 4;
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]]);
+            let syscall_id = cx.x[17];
+            let args = [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]];
+            // 进程开启了系统调用跟踪（/proc/<pid>/trace）
+            let trace = current_task().unwrap().inner_exclusive_access().trace;
+            let traced_call = if trace { trace_enter(syscall_id, &args) } else { None };
+            let result = syscall(syscall_id, args);
+            if let Some(call) = traced_call {
+                trace_exit(call, result);
+            }
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
-            cx.x[10] = result as usize;
+            // rt_sigreturn 返回的是被信号打断的上下文中的 a0，即使恰好等于 -ERESTARTSYS 也不能当作重新执行的请求
+            if result == -ERESTARTSYS && syscall_id != SYSCALL_RT_SIGRETURN {
+                if should_restart_syscall() {
+                    // 回到 ecall 重新执行，a0 仍是原来的第一个参数
+                    cx.sepc -= 4;
+                } else {
+                    cx.x[10] = -EINTR as usize;
+                }
+            } else {
+                cx.x[10] = result as usize;
+            }
         }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
@@ -153,6 +174,8 @@ pub fn trap_handler() -> ! {
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
+            check_timer();
+            poll_console();
             suspend_current_and_run_next();
         }
         _ => {
@@ -165,15 +188,13 @@ pub fn trap_handler() -> ! {
 /// 通过在Rust语言中加入宏命令调用 `__restore` 汇编函数
 #[no_mangle]
 pub fn trap_return() -> ! {
-    
-    // check signals
-    if let Some((errno, _msg)) = check_signals_of_current() {
-        // println!("[kernel] {}", _msg);
-        exit_current_and_run_next(errno);
-    }
+    current_task().unwrap().inner_exclusive_access().account_system_time();
+
+    // 处理信号，可能终止当前进程或修改 Trap 上下文进入信号处理函数
+    handle_signals();
 
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
+    let trap_cx_ptr = current_trap_cx_user_va();
     let user_satp = current_user_token();
     extern "C" {
         fn __alltraps();
@@ -132,6 +132,13 @@ impl BlockCacheManager {
     pub fn drop_all(&mut self) {
         self.queue.clear();
     }
+
+    /// 将全部被修改过的块缓存写回磁盘，缓存仍保留在队列中
+    pub fn sync_all(&self) {
+        for (_, cache) in self.queue.iter() {
+            cache.write().sync();
+        }
+    }
 }
 
 // 64个缓存块，即 32KB
@@ -160,6 +167,11 @@ pub fn set_start_sec(start_sec: usize) {
     BLOCK_CACHE_MANAGER.write().set_start_sec(start_sec);
 }
 
+// 写回磁盘，不清空缓存
+pub fn sync_all() {
+    BLOCK_CACHE_MANAGER.read().sync_all();
+}
+
 // 写回磁盘，会调用Drop
 pub fn write_to_dev() {
     BLOCK_CACHE_MANAGER.write().drop_all();
@@ -16,6 +16,7 @@ pub use block_dev::BlockDevice;
 pub use layout::ShortDirEntry;
 pub use vfs::{VFile,create_root_vfile};
 use block_cache::{get_block_cache, set_start_sec, write_to_dev};
+pub use block_cache::sync_all;
 pub use fat32_manager::FAT32Manager;
 pub use layout::*;
 